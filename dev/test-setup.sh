@@ -29,6 +29,16 @@ setup_dbs() {
     mkdir -p "$PROJECT_ROOT"/test_node_{1,2,3}_db
 }
 
+# Function to derive the validator key named in dev/test_genesis.json. It is a test
+# fixture derived from a public seed, so it is generated here rather than checked in.
+create_keys() {
+    if [ ! -f "$SCRIPT_DIR/node1-validator.key" ]; then
+        echo -e "${GREEN}Deriving the node 1 validator key...${NC}"
+        cd "$PROJECT_ROOT"
+        cargo run --quiet --bin rustchain -- keytool derive --seed rustchain_test_validator_seed_123 --out dev/node1-validator.key
+    fi
+}
+
 # Function to create node configs
 create_configs() {
     echo -e "${GREEN}Creating node configurations...${NC}"
@@ -167,11 +177,13 @@ case "${1:-start}" in
     "setup")
         cleanup
         setup_dbs
+        create_keys
         create_configs
         echo -e "${GREEN}✅ Test environment set up. Run '$0 start' to start nodes.${NC}"
         ;;
     "start")
         setup_dbs
+        create_keys
         create_configs
         start_nodes
         ;;
@@ -189,6 +201,7 @@ case "${1:-start}" in
         rm -rf "$PROJECT_ROOT"/test_node_*_db
         rm -f "$SCRIPT_DIR"/node*-config.toml
         rm -f "$PROJECT_ROOT"/dev/node*.log
+        rm -f "$SCRIPT_DIR"/*.key
         echo -e "${GREEN}✅ Cleaned up all test files.${NC}"
         ;;
     *)
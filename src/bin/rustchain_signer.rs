@@ -0,0 +1,83 @@
+//! Holds a validator key away from the node, and signs the node's block headers over the
+//! remote signer protocol. It never signs two different headers at the same height, nor a
+//! height below the last one it signed.
+
+use clap::Parser;
+use rustchain::block_signer::{self, SignerAddress, DEFAULT_SIGNER_ADDR};
+use rustchain::keystore::KeyFile;
+use rustchain::signer_server::{SignerServer, WatermarkFile};
+use rustchain::types::address_from_public_key;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "Remote block signer for RustChain validators", long_about = None)]
+struct Args {
+    /// Validator key file: a raw key, or a keystore whose passphrase is prompted for
+    #[clap(long, value_parser)]
+    key: PathBuf,
+    /// File holding the secret shared with the node, at least 16 bytes
+    #[clap(long, value_parser)]
+    secret: PathBuf,
+    /// Address to listen on: host:port, or unix:/path/to/socket
+    #[clap(long, default_value = DEFAULT_SIGNER_ADDR)]
+    listen: SignerAddress,
+    /// Where the height of the last signed header is kept [default: <KEY>.watermark.json]
+    #[clap(long, value_parser)]
+    watermark_file: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env().add_directive("info".parse()?))
+        .init();
+    let args = Args::parse();
+
+    let signing_key = match KeyFile::read(&args.key)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", args.key.display(), e))?
+    {
+        KeyFile::Plain(signing_key) => signing_key,
+        KeyFile::Encrypted(keystore) => {
+            let passphrase = rpassword::prompt_password(format!("Passphrase for {}: ", args.key.display()))?;
+            keystore.decrypt(&passphrase)?
+        }
+    };
+    let secret = block_signer::read_secret(&args.secret)
+        .map_err(|e| anyhow::anyhow!("Failed to read the shared secret from {}: {}", args.secret.display(), e))?;
+    let watermark_path = args.watermark_file.unwrap_or_else(|| {
+        let mut name = args.key.clone().into_os_string();
+        name.push(".watermark.json");
+        PathBuf::from(name)
+    });
+    let watermark = WatermarkFile::open(&watermark_path)
+        .map_err(|e| anyhow::anyhow!("Failed to open watermark {}: {}", watermark_path.display(), e))?;
+
+    match watermark.current() {
+        Some(current) => tracing::info!("Last signed height {}, recorded in {}", current.height, watermark_path.display()),
+        None => tracing::info!("Nothing signed yet, recording the watermark in {}", watermark_path.display()),
+    }
+
+    let server = Arc::new(SignerServer::new(signing_key, secret, watermark));
+    tracing::info!("Signing for validator {} (account {})", server.public_key(), address_from_public_key(server.public_key()));
+
+    match &args.listen {
+        SignerAddress::Tcp(addr) => {
+            let listener = std::net::TcpListener::bind(addr)?;
+            tracing::info!("Remote signer listening on {}", args.listen);
+            server.serve_tcp(listener)?;
+        }
+        #[cfg(unix)]
+        SignerAddress::Unix(path) => {
+            // A socket left behind by a previous run would make binding fail
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            let listener = std::os::unix::net::UnixListener::bind(path)?;
+            tracing::info!("Remote signer listening on {}", args.listen);
+            server.serve_unix(listener)?;
+        }
+        #[cfg(not(unix))]
+        SignerAddress::Unix(_) => return Err(block_signer::SignerError::UnixUnsupported.into()),
+    }
+    Ok(())
+}
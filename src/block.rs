@@ -194,24 +194,12 @@ mod tests {
         let sender_address = Address(addr_bytes);
         let recipient_address = Address([1u8; 32]);
 
-        let tx_for_hash_calc = Transaction {
-            sender: sender_pk,
-            recipient: recipient_address,
-            amount,
-            nonce: Nonce(nonce_val),
-            signature: TypesSignature(signing_key.sign(&[salt]).to_bytes().to_vec()),
-        };
+        let tx_for_hash_calc = Transaction::new(sender_pk, recipient_address, amount, Nonce(nonce_val), TypesSignature(signing_key.sign(&[salt]).to_bytes().to_vec()));
         let data_hash = tx_for_hash_calc.data_to_sign_hash().expect("Data hash failed in dummy tx for block test");
         let final_signature = TypesSignature(signing_key.sign(data_hash.as_ref()).to_bytes().to_vec());
 
         (
-            Transaction {
-                sender: sender_pk,
-                recipient: recipient_address,
-                amount,
-                nonce: Nonce(nonce_val),
-                signature: final_signature,
-            },
+            Transaction::new(sender_pk, recipient_address, amount, Nonce(nonce_val), final_signature),
             sender_pk,
         )
     }
@@ -0,0 +1,351 @@
+use crate::block::BlockHeader;
+use crate::types::{PublicKey, Signature};
+use crate::wallet::Wallet;
+use ed25519_dalek::{Signer, Verifier};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Default address the standalone signer listens on.
+pub const DEFAULT_SIGNER_ADDR: &str = "127.0.0.1:9940";
+/// Shared secrets shorter than this are refused as guessable.
+pub const MIN_SECRET_LEN: usize = 16;
+pub const CHALLENGE_LEN: usize = 32;
+/// Prefixed to every MACed message, so request MACs cannot be mistaken for any other use of
+/// the secret.
+const MAC_DOMAIN: &[u8] = b"rustchain-remote-signer-v1";
+/// How long `RemoteSigner` waits for the signer to respond.
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum SignerError {
+    #[error("Signer connection failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid signer message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid signer address `{0}`: use host:port or unix:/path/to/socket")]
+    InvalidAddress(String),
+    #[error("Unix sockets are not supported on this platform")]
+    UnixUnsupported,
+    #[error("The shared secret must be at least {} bytes", MIN_SECRET_LEN)]
+    SecretTooShort,
+    #[error("Failed to encode block header: {0}")]
+    Encode(String),
+    #[error("Invalid signer message: {0}")]
+    InvalidMessage(String),
+    #[error("Signer connection closed without a response")]
+    Closed,
+    #[error("Signer refused: {0}")]
+    Refused(String),
+    #[error("Signer returned a signature that does not verify against its public key")]
+    InvalidSignature,
+}
+
+/// Signs the headers of blocks this node produces.
+///
+/// Signing may block on I/O, so async callers should run it on a blocking thread.
+pub trait BlockSigner: Send + Sync {
+    fn public_key(&self) -> &PublicKey;
+
+    /// Signs the hash of `header`, ignoring its current signature.
+    fn sign_header(&self, header: &BlockHeader) -> Result<Signature, SignerError>;
+}
+
+/// Signs with a key held in the node's own memory, e.g. loaded from `private_key_path`.
+pub struct LocalSigner {
+    wallet: Wallet,
+}
+
+impl LocalSigner {
+    pub fn new(wallet: Wallet) -> Self {
+        LocalSigner { wallet }
+    }
+}
+
+impl BlockSigner for LocalSigner {
+    fn public_key(&self) -> &PublicKey {
+        self.wallet.public_key()
+    }
+
+    fn sign_header(&self, header: &BlockHeader) -> Result<Signature, SignerError> {
+        let hash = header.calculate_hash().map_err(|e| SignerError::Encode(e.to_string()))?;
+        Ok(Signature(self.wallet.get_signing_key().sign(&hash.0).to_bytes().to_vec()))
+    }
+}
+
+/// Where a signer listens: `host:port` for TCP, or `unix:<path>` for a Unix socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for SignerAddress {
+    type Err = SignerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(SignerAddress::Unix(PathBuf::from(path))),
+            Some(_) => Err(SignerError::InvalidAddress(s.to_string())),
+            None if s.contains(':') => Ok(SignerAddress::Tcp(s.to_string())),
+            None => Err(SignerError::InvalidAddress(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for SignerAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerAddress::Tcp(addr) => write!(f, "{}", addr),
+            SignerAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+type Streams = (Box<dyn Read + Send>, Box<dyn Write + Send>);
+
+impl SignerAddress {
+    fn connect(&self) -> Result<Streams, SignerError> {
+        match self {
+            SignerAddress::Tcp(addr) => {
+                let stream = std::net::TcpStream::connect(addr)?;
+                stream.set_read_timeout(Some(SIGNER_TIMEOUT))?;
+                stream.set_write_timeout(Some(SIGNER_TIMEOUT))?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            #[cfg(unix)]
+            SignerAddress::Unix(path) => {
+                let stream = std::os::unix::net::UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(SIGNER_TIMEOUT))?;
+                stream.set_write_timeout(Some(SIGNER_TIMEOUT))?;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream)))
+            }
+            #[cfg(not(unix))]
+            SignerAddress::Unix(_) => Err(SignerError::UnixUnsupported),
+        }
+    }
+}
+
+/// Reads the secret shared by a node and its signer. Any file of at least `MIN_SECRET_LEN`
+/// bytes works, e.g. one written by `head -c 32 /dev/urandom`.
+pub fn read_secret(path: &Path) -> Result<Vec<u8>, SignerError> {
+    let secret = std::fs::read(path)?;
+    if secret.len() < MIN_SECRET_LEN {
+        return Err(SignerError::SecretTooShort);
+    }
+    Ok(secret)
+}
+
+// The protocol is line-delimited JSON. On every connection the signer first sends a
+// `SignerHello` with a fresh random challenge. Each request is then wrapped in an
+// `AuthenticatedRequest` whose MAC, keyed with the shared secret, covers the challenge, a
+// sequence number that must increase, and the request, so requests can be neither forged
+// nor replayed on another connection. Responses are not MACed: the node verifies every
+// signature it receives against the signer's public key.
+
+/// The first line the signer sends on a connection.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerHello {
+    /// `CHALLENGE_LEN` random bytes, hex-encoded
+    pub challenge: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum SignerRequest {
+    /// The public key of the validator key the signer holds.
+    PublicKey,
+    /// A signature over a block header, given as the hex of its bincode encoding.
+    SignHeader { header: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthenticatedRequest {
+    pub seq: u64,
+    /// The JSON of a `SignerRequest`, kept as the exact string the MAC covers.
+    pub request: String,
+    /// Hex HMAC-SHA256 of the request, see `request_mac`.
+    pub mac: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerResponse {
+    PublicKey(String),
+    Signature(String),
+    Error(String),
+}
+
+fn request_hmac(secret: &[u8], challenge: &[u8], seq: u64, request: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(MAC_DOMAIN);
+    mac.update(challenge);
+    mac.update(&seq.to_be_bytes());
+    mac.update(request.as_bytes());
+    mac
+}
+
+pub fn request_mac(secret: &[u8], challenge: &[u8], seq: u64, request: &str) -> [u8; 32] {
+    request_hmac(secret, challenge, seq, request).finalize().into_bytes().into()
+}
+
+/// Checks `mac` in constant time.
+pub fn verify_request_mac(secret: &[u8], challenge: &[u8], seq: u64, request: &str, mac: &[u8]) -> bool {
+    request_hmac(secret, challenge, seq, request).verify_slice(mac).is_ok()
+}
+
+struct Connection {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+    challenge: Vec<u8>,
+    seq: u64,
+}
+
+impl Connection {
+    fn open(address: &SignerAddress) -> Result<Self, SignerError> {
+        let (reader, writer) = address.connect()?;
+        let mut reader = BufReader::new(reader);
+        let hello: SignerHello = serde_json::from_str(&read_line(&mut reader)?)?;
+        let challenge = hex::decode(&hello.challenge)
+            .ok()
+            .filter(|challenge| challenge.len() == CHALLENGE_LEN)
+            .ok_or_else(|| SignerError::InvalidMessage(format!("invalid challenge `{}`", hello.challenge)))?;
+        Ok(Connection { reader, writer, challenge, seq: 0 })
+    }
+
+    fn call(&mut self, secret: &[u8], request: &SignerRequest) -> Result<SignerResponse, SignerError> {
+        self.seq += 1;
+        let request = serde_json::to_string(request)?;
+        let mac = hex::encode(request_mac(secret, &self.challenge, self.seq, &request));
+        let mut line = serde_json::to_string(&AuthenticatedRequest { seq: self.seq, request, mac })?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        Ok(serde_json::from_str(&read_line(&mut self.reader)?)?)
+    }
+}
+
+fn read_line(reader: &mut impl BufRead) -> Result<String, SignerError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(SignerError::Closed);
+    }
+    Ok(line)
+}
+
+/// Signs through a signer process holding the validator key, so the key never has to be
+/// on the node's disk. The connection is reopened when it breaks, e.g. after the signer
+/// restarts.
+pub struct RemoteSigner {
+    address: SignerAddress,
+    secret: Vec<u8>,
+    public_key: PublicKey,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl RemoteSigner {
+    /// Connects to the signer at `address` and asks for its public key.
+    pub fn connect(address: SignerAddress, secret: Vec<u8>) -> Result<Self, SignerError> {
+        let mut connection = Connection::open(&address)?;
+        let public_key = match connection.call(&secret, &SignerRequest::PublicKey)? {
+            SignerResponse::PublicKey(public_key) => parse_public_key(&public_key)?,
+            other => return Err(unexpected(other)),
+        };
+        Ok(RemoteSigner { address, secret, public_key, connection: Mutex::new(Some(connection)) })
+    }
+
+    pub fn address(&self) -> &SignerAddress {
+        &self.address
+    }
+
+    fn call(&self, request: &SignerRequest) -> Result<SignerResponse, SignerError> {
+        let mut connection = self.connection.lock().unwrap();
+        if let Some(open) = connection.as_mut() {
+            match open.call(&self.secret, request) {
+                Err(SignerError::Io(_) | SignerError::Closed) => {}
+                result => return result,
+            }
+        }
+        // Never connected, or the connection broke: a retried header is signed again only
+        // if it is the exact header the signer already signed at that height
+        *connection = None;
+        let mut reopened = Connection::open(&self.address)?;
+        let response = reopened.call(&self.secret, request);
+        *connection = Some(reopened);
+        response
+    }
+}
+
+impl BlockSigner for RemoteSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign_header(&self, header: &BlockHeader) -> Result<Signature, SignerError> {
+        let encoded = bincode::encode_to_vec(header, bincode::config::standard())
+            .map_err(|e| SignerError::Encode(e.to_string()))?;
+        let signature = match self.call(&SignerRequest::SignHeader { header: hex::encode(encoded) })? {
+            SignerResponse::Signature(signature) => signature,
+            other => return Err(unexpected(other)),
+        };
+        let signature: [u8; 64] = hex::decode(&signature)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| SignerError::InvalidMessage(format!("invalid signature `{}`", signature)))?;
+        let hash = header.calculate_hash().map_err(|e| SignerError::Encode(e.to_string()))?;
+        self.public_key
+            .0
+            .verify(&hash.0, &ed25519_dalek::Signature::from_bytes(&signature))
+            .map_err(|_| SignerError::InvalidSignature)?;
+        Ok(Signature(signature.to_vec()))
+    }
+}
+
+fn parse_public_key(public_key: &str) -> Result<PublicKey, SignerError> {
+    hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .and_then(|bytes: [u8; 32]| ed25519_dalek::VerifyingKey::from_bytes(&bytes).ok())
+        .map(PublicKey)
+        .ok_or_else(|| SignerError::InvalidMessage(format!("invalid public key `{}`", public_key)))
+}
+
+fn unexpected(response: SignerResponse) -> SignerError {
+    match response {
+        SignerResponse::Error(message) => SignerError::Refused(message),
+        other => SignerError::InvalidMessage(format!("unexpected response {:?}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signer_address_parsing() {
+        assert_eq!("127.0.0.1:9940".parse::<SignerAddress>().unwrap(), SignerAddress::Tcp("127.0.0.1:9940".to_string()));
+        let unix: SignerAddress = "unix:/run/rustchain/signer.sock".parse().unwrap();
+        assert_eq!(unix, SignerAddress::Unix(PathBuf::from("/run/rustchain/signer.sock")));
+        assert_eq!(unix.to_string(), "unix:/run/rustchain/signer.sock");
+        assert!(matches!("signer".parse::<SignerAddress>(), Err(SignerError::InvalidAddress(_))));
+        assert!(matches!("unix:".parse::<SignerAddress>(), Err(SignerError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn test_request_mac_binds_challenge_and_sequence() {
+        let secret = b"0123456789abcdef";
+        let challenge = [1u8; CHALLENGE_LEN];
+        let request = r#"{"method":"public_key"}"#;
+        let mac = request_mac(secret, &challenge, 1, request);
+        assert!(verify_request_mac(secret, &challenge, 1, request, &mac));
+        assert!(!verify_request_mac(secret, &challenge, 2, request, &mac));
+        assert!(!verify_request_mac(secret, &[2u8; CHALLENGE_LEN], 1, request, &mac));
+        assert!(!verify_request_mac(b"fedcba9876543210", &challenge, 1, request, &mac));
+    }
+}
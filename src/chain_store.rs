@@ -0,0 +1,430 @@
+use crate::block::{Block, BlockHeader};
+use crate::snapshot::StateSnapshot;
+use crate::state_machine::{Account, WorldState};
+use crate::storage::{address_entries, memo_entries, memo_hash, IntegrityRepair, PruningMode, StorageError, TransactionLocation};
+use crate::types::{Address, Hash};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Persistence used by the node: blocks, the header index, accounts with their history,
+/// the memo and address indexes and the chain tip. `Storage` implements it on RocksDB and `MemoryStore` in memory.
+///
+/// Every method that writes more than one record must apply all of them or none.
+pub trait ChainStore: Send + 'static {
+    fn get_block(&self, hash: &Hash) -> Result<Option<Block>, StorageError>;
+
+    fn put_block(&self, block: &Block) -> Result<(), StorageError>;
+
+    fn get_header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StorageError>;
+
+    fn put_header_by_height(&self, height: u64, header: &BlockHeader) -> Result<(), StorageError>;
+
+    /// Latest value of an account.
+    fn get_account(&self, address: &Address) -> Result<Option<Account>, StorageError>;
+
+    fn put_account(&self, address: &Address, account: &Account) -> Result<(), StorageError>;
+
+    /// Value of an account after the block at `height` was applied.
+    fn get_account_at(&self, address: &Address, height: u64) -> Result<Option<Account>, StorageError>;
+
+    fn put_account_at(&self, address: &Address, height: u64, account: &Account) -> Result<(), StorageError>;
+
+    /// Locations of every committed transaction whose memo is exactly `memo`, ordered by height.
+    fn get_transactions_by_memo(&self, memo: &[u8]) -> Result<Vec<TransactionLocation>, StorageError>;
+
+    /// Locations of every committed transaction that touches `address`, ordered by height.
+    fn get_transactions_by_address(&self, address: &Address) -> Result<Vec<TransactionLocation>, StorageError>;
+
+    /// Every current account, ordered by address.
+    fn load_accounts(&self) -> Result<Vec<(Address, Account)>, StorageError>;
+
+    fn get_chain_tip(&self) -> Result<Option<(Hash, u64)>, StorageError>;
+
+    fn set_chain_tip(&self, hash: &Hash, height: u64) -> Result<(), StorageError>;
+
+    /// Lowest height whose block body is retained.
+    fn get_pruned_height(&self) -> Result<u64, StorageError>;
+
+    /// Stores a block, its height index, memo and address index entries and the accounts the
+    /// block changed, as returned by `StateMachine::apply_block`, and moves the tip to it. Each
+    /// changed account gets a history record at the block's height; other accounts are not read.
+    fn commit_block(&self, block: &Block, changed_accounts: &WorldState) -> Result<(), StorageError>;
+
+    /// Verifies a snapshot and replaces the local state, history and tip with it.
+    fn import_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), StorageError>;
+
+    /// Removes data below the horizon of `mode`. Returns the number of block bodies removed.
+    fn prune(&self, mode: PruningMode) -> Result<u64, StorageError>;
+
+    /// Detects and repairs a tip that does not match the committed state or the height
+    /// index. Stores whose writes can never be torn need no repair.
+    fn check_integrity(&self) -> Result<Vec<IntegrityRepair>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    /// Returns the block at `height`, or `StorageError::Pruned` if its body has been pruned.
+    fn get_block_by_height(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        let horizon = self.get_pruned_height()?;
+        if height < horizon {
+            return Err(StorageError::Pruned { height, horizon });
+        }
+        match self.get_header_by_height(height)? {
+            Some(header) => {
+                let hash = header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+                self.get_block(&hash)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Loads the persisted world state into memory.
+    fn load_world_state(&self) -> Result<WorldState, StorageError> {
+        Ok(self.load_accounts()?.into_iter().collect())
+    }
+
+    /// Exports the current accounts as a snapshot tied to the block at `height`, in chunks
+    /// of at most `max_chunk_bytes`. Only the current tip can be exported, since older state
+    /// is not retained.
+    fn export_snapshot(&self, height: u64, max_chunk_bytes: usize) -> Result<StateSnapshot, StorageError> {
+        let (tip_hash, tip_height) = self.get_chain_tip()?
+            .ok_or_else(|| StorageError::NotFound("Chain tip".to_string()))?;
+        if height != tip_height {
+            return Err(StorageError::SnapshotUnavailable { requested: height, tip: tip_height });
+        }
+        let header = match self.get_block(&tip_hash)? {
+            Some(block) => block.header,
+            None => self.get_header_by_height(tip_height)?
+                .ok_or_else(|| StorageError::NotFound(format!("Header at height {}", tip_height)))?,
+        };
+        let accounts = self.load_accounts()?;
+        Ok(StateSnapshot::build(header, accounts, max_chunk_bytes)?)
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryTables {
+    blocks: BTreeMap<Hash, Block>,
+    headers: BTreeMap<u64, BlockHeader>,
+    state: BTreeMap<Address, Account>,
+    history: BTreeMap<(Address, u64), Account>,
+    history_index: BTreeSet<(u64, Address)>,
+    memos: BTreeSet<(Hash, TransactionLocation)>,
+    addresses: BTreeSet<(Address, TransactionLocation)>,
+    tip: Option<(Hash, u64)>,
+    pruned_height: u64,
+}
+
+/// A `ChainStore` backed by in-memory maps, for tests and for embedding the node without
+/// RocksDB. Nothing survives the process.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: RwLock<MemoryTables>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, MemoryTables> {
+        self.tables.read().expect("memory store lock poisoned")
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, MemoryTables> {
+        self.tables.write().expect("memory store lock poisoned")
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn get_block(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
+        Ok(self.read().blocks.get(hash).cloned())
+    }
+
+    fn put_block(&self, block: &Block) -> Result<(), StorageError> {
+        let hash = block.header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        self.write().blocks.insert(hash, block.clone());
+        Ok(())
+    }
+
+    fn get_header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StorageError> {
+        Ok(self.read().headers.get(&height).cloned())
+    }
+
+    fn put_header_by_height(&self, height: u64, header: &BlockHeader) -> Result<(), StorageError> {
+        self.write().headers.insert(height, header.clone());
+        Ok(())
+    }
+
+    fn get_account(&self, address: &Address) -> Result<Option<Account>, StorageError> {
+        Ok(self.read().state.get(address).cloned())
+    }
+
+    fn put_account(&self, address: &Address, account: &Account) -> Result<(), StorageError> {
+        self.write().state.insert(*address, account.clone());
+        Ok(())
+    }
+
+    fn get_account_at(&self, address: &Address, height: u64) -> Result<Option<Account>, StorageError> {
+        let tables = self.read();
+        if height < tables.pruned_height {
+            return Err(StorageError::Pruned { height, horizon: tables.pruned_height });
+        }
+        Ok(tables
+            .history
+            .range((*address, 0)..=(*address, height))
+            .next_back()
+            .map(|(_, account)| account.clone()))
+    }
+
+    fn put_account_at(&self, address: &Address, height: u64, account: &Account) -> Result<(), StorageError> {
+        let mut tables = self.write();
+        tables.history.insert((*address, height), account.clone());
+        tables.history_index.insert((height, *address));
+        Ok(())
+    }
+
+    fn get_transactions_by_memo(&self, memo: &[u8]) -> Result<Vec<TransactionLocation>, StorageError> {
+        let hash = memo_hash(memo);
+        let start = TransactionLocation { height: 0, tx_id: Hash([0; 32]) };
+        let end = TransactionLocation { height: u64::MAX, tx_id: Hash([u8::MAX; 32]) };
+        Ok(self.read().memos.range((hash, start)..=(hash, end)).map(|(_, location)| *location).collect())
+    }
+
+    fn get_transactions_by_address(&self, address: &Address) -> Result<Vec<TransactionLocation>, StorageError> {
+        let start = TransactionLocation { height: 0, tx_id: Hash([0; 32]) };
+        let end = TransactionLocation { height: u64::MAX, tx_id: Hash([u8::MAX; 32]) };
+        Ok(self.read().addresses.range((*address, start)..=(*address, end)).map(|(_, location)| *location).collect())
+    }
+
+    fn load_accounts(&self) -> Result<Vec<(Address, Account)>, StorageError> {
+        Ok(self.read().state.iter().map(|(address, account)| (*address, account.clone())).collect())
+    }
+
+    fn get_chain_tip(&self) -> Result<Option<(Hash, u64)>, StorageError> {
+        Ok(self.read().tip)
+    }
+
+    fn set_chain_tip(&self, hash: &Hash, height: u64) -> Result<(), StorageError> {
+        self.write().tip = Some((*hash, height));
+        Ok(())
+    }
+
+    fn get_pruned_height(&self) -> Result<u64, StorageError> {
+        Ok(self.read().pruned_height)
+    }
+
+    fn commit_block(&self, block: &Block, changed_accounts: &WorldState) -> Result<(), StorageError> {
+        let hash = block.header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let height = block.header.block_number.0;
+        let memos = memo_entries(block)?;
+        let addresses = address_entries(block, |address| Ok(self.get_account(address)?.and_then(|account| account.hash_lock)))?;
+        let mut tables = self.write();
+        tables.blocks.insert(hash, block.clone());
+        tables.memos.extend(memos);
+        tables.addresses.extend(addresses);
+        tables.headers.insert(height, block.header.clone());
+        for (address, account) in changed_accounts {
+            tables.history.insert((*address, height), account.clone());
+            tables.history_index.insert((height, *address));
+            tables.state.insert(*address, account.clone());
+        }
+        tables.tip = Some((hash, height));
+        Ok(())
+    }
+
+    fn import_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), StorageError> {
+        snapshot.verify()?;
+        let manifest = &snapshot.manifest;
+        let height = manifest.height.0;
+        let mut tables = self.write();
+        tables.state.clear();
+        tables.history.clear();
+        tables.history_index.clear();
+        for (address, account) in snapshot.chunks.iter().flat_map(|chunk| chunk.accounts.iter()) {
+            tables.state.insert(*address, account.clone());
+            tables.history.insert((*address, height), account.clone());
+            tables.history_index.insert((height, *address));
+        }
+        tables.headers.insert(height, manifest.header.clone());
+        tables.tip = Some((manifest.block_hash, height));
+        tables.pruned_height = height;
+        Ok(())
+    }
+
+    fn prune(&self, mode: PruningMode) -> Result<u64, StorageError> {
+        let mut tables = self.write();
+        let Some((_, tip_height)) = tables.tip else {
+            return Ok(0);
+        };
+        let Some(horizon) = mode.horizon(tip_height) else {
+            return Ok(0);
+        };
+        if horizon <= tables.pruned_height {
+            return Ok(0);
+        }
+
+        let mut removed = 0;
+        for height in tables.pruned_height..horizon {
+            let Some(header) = tables.headers.get(&height) else {
+                continue;
+            };
+            let hash = header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            tables.blocks.remove(&hash);
+            removed += 1;
+        }
+
+        // Keep only the newest record at or below the horizon for each address written since the last prune
+        let mut newest = BTreeMap::new();
+        while let Some(&(height, address)) = tables.history_index.first() {
+            if height > horizon {
+                break;
+            }
+            newest.insert(address, height);
+            tables.history_index.pop_first();
+        }
+        for (address, height) in newest {
+            let superseded: Vec<_> = tables.history.range((address, 0)..(address, height)).map(|(key, _)| *key).collect();
+            for key in superseded {
+                tables.history.remove(&key);
+            }
+        }
+
+        tables.pruned_height = horizon;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::htlc::{hashlock_of, HashTimeLock};
+    use crate::snapshot::DEFAULT_SNAPSHOT_CHUNK_BYTES;
+    use crate::storage::Storage;
+    use crate::transaction::{Transaction, TransactionKind};
+    use crate::types::{BlockHeight, Nonce, Signature, Timestamp};
+    use crate::wallet::Wallet;
+
+    fn block_at(height: u64, parent_hash: Hash) -> Block {
+        Block {
+            header: BlockHeader {
+                parent_hash,
+                block_number: BlockHeight(height),
+                timestamp: Timestamp(height),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![],
+        }
+    }
+
+    /// Runs the same sequence of operations against any backend, so both implementations
+    /// are held to identical behaviour.
+    fn exercise_store<S: ChainStore>(store: &S) {
+        let alice = Address([1; 32]);
+        assert_eq!(store.get_chain_tip().unwrap(), None);
+
+        let sender = *Wallet::new().public_key();
+        let memo_tx = |nonce| {
+            Transaction::new(sender, alice, 1, Nonce(nonce), Signature(vec![0; 64])).with_memo(b"order-7".to_vec())
+        };
+        let mut world_state = WorldState::new();
+        let mut parent_hash = Hash([0; 32]);
+        let mut blocks = Vec::new();
+        for height in 0..6u64 {
+            world_state.insert(alice, Account { balance: 100 - height * 10, nonce: Nonce(height), authorized_key: None, vesting: None, hash_lock: None });
+            let mut block = block_at(height, parent_hash);
+            if height == 1 || height == 4 {
+                block.transactions.push(memo_tx(height));
+            }
+            store.commit_block(&block, &world_state).unwrap();
+            parent_hash = block.header.calculate_hash().unwrap();
+            blocks.push(block);
+        }
+
+        assert_eq!(store.get_chain_tip().unwrap(), Some((parent_hash, 5)));
+        assert_eq!(store.get_block(&parent_hash).unwrap(), Some(blocks[5].clone()));
+        assert_eq!(store.get_block_by_height(2).unwrap(), Some(blocks[2].clone()));
+        assert_eq!(store.get_account(&alice).unwrap().unwrap().balance, 50);
+        assert_eq!(store.get_account_at(&alice, 2).unwrap().unwrap().balance, 80);
+        assert_eq!(store.load_world_state().unwrap(), world_state);
+        let memo_heights: Vec<u64> = store.get_transactions_by_memo(b"order-7").unwrap().iter().map(|l| l.height).collect();
+        assert_eq!(memo_heights, vec![1, 4]);
+        assert_eq!(store.get_transactions_by_memo(b"order-7").unwrap()[1].tx_id, memo_tx(4).id().unwrap());
+        assert!(store.get_transactions_by_memo(b"order-8").unwrap().is_empty());
+
+        assert_eq!(store.prune(PruningMode::KeepLast(2)).unwrap(), 4);
+        assert_eq!(store.get_pruned_height().unwrap(), 4);
+        assert!(matches!(store.get_block_by_height(3), Err(StorageError::Pruned { height: 3, horizon: 4 })));
+        assert!(matches!(store.get_account_at(&alice, 3), Err(StorageError::Pruned { .. })));
+        assert_eq!(store.get_account_at(&alice, 4).unwrap().unwrap().balance, 60);
+
+        let snapshot = store.export_snapshot(5, DEFAULT_SNAPSHOT_CHUNK_BYTES).unwrap();
+        assert_eq!(snapshot.manifest.block_hash, parent_hash);
+        assert!(store.export_snapshot(4, DEFAULT_SNAPSHOT_CHUNK_BYTES).is_err());
+    }
+
+    #[test]
+    fn test_memory_store_behaviour() {
+        exercise_store(&MemoryStore::new());
+    }
+
+    #[test]
+    fn test_rocksdb_store_behaviour() {
+        let dir = tempfile::tempdir().unwrap();
+        exercise_store(&Storage::new(dir.path()).unwrap());
+    }
+
+    /// A claim names only the escrow account, but it must show up in the history of the
+    /// lock's recipient and refund address even when someone else submits it.
+    fn exercise_third_party_claim<S: ChainStore>(store: &S) {
+        let alice = Wallet::new();
+        let alice_address = crate::types::address_from_public_key(alice.public_key());
+        let (bob, carol, refund) = (Address([7; 32]), *Wallet::new().public_key(), Address([8; 32]));
+        let lock = HashTimeLock { hashlock: hashlock_of(&[3; 32]), timelock: BlockHeight(10), recipient: bob, refund_address: refund };
+        let lock_id = HashTimeLock::lock_address(&alice_address, Nonce(0));
+        let open = Transaction::with_kind(*alice.public_key(), Nonce(0), TransactionKind::LockFunds { amount: 40, lock }, Signature(vec![]));
+        let claim = Transaction::with_kind(carol, Nonce(0), TransactionKind::ClaimLock { lock_id, preimage: [3; 32] }, Signature(vec![]));
+
+        let mut world_state = WorldState::new();
+        world_state.insert(lock_id, Account { balance: 40, hash_lock: Some(lock), ..Default::default() });
+        let mut first = block_at(1, Hash([0; 32]));
+        first.transactions.push(open.clone());
+        store.commit_block(&first, &world_state).unwrap();
+
+        // Claiming closes the escrow, so the block's resulting state no longer holds the terms
+        world_state.insert(lock_id, Account::default());
+        world_state.insert(bob, Account { balance: 40, ..Default::default() });
+        let mut second = block_at(2, first.header.calculate_hash().unwrap());
+        second.transactions.push(claim.clone());
+        store.commit_block(&second, &world_state).unwrap();
+
+        let ids = |address| store.get_transactions_by_address(&address).unwrap().into_iter().map(|l| l.tx_id).collect::<Vec<_>>();
+        let (open_id, claim_id) = (open.id().unwrap(), claim.id().unwrap());
+        assert_eq!(ids(bob), vec![open_id, claim_id]);
+        assert_eq!(ids(refund), vec![open_id, claim_id]);
+        assert_eq!(ids(crate::types::address_from_public_key(&carol)), vec![claim_id]);
+    }
+
+    #[test]
+    fn test_third_party_claim_indexes_lock_parties() {
+        exercise_third_party_claim(&MemoryStore::new());
+        let dir = tempfile::tempdir().unwrap();
+        exercise_third_party_claim(&Storage::new(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_moves_between_backends() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Storage::new(dir.path()).unwrap();
+        let mut world_state = WorldState::new();
+        world_state.insert(Address([4; 32]), Account { balance: 7, nonce: Nonce(1), authorized_key: None, vesting: None, hash_lock: None });
+        source.commit_block(&block_at(3, Hash([0; 32])), &world_state).unwrap();
+
+        let target = MemoryStore::new();
+        target.import_snapshot(&source.export_snapshot(3, DEFAULT_SNAPSHOT_CHUNK_BYTES).unwrap()).unwrap();
+        assert_eq!(target.load_world_state().unwrap(), world_state);
+        assert_eq!(target.get_chain_tip().unwrap(), source.get_chain_tip().unwrap());
+        assert_eq!(target.get_account_at(&Address([4; 32]), 3).unwrap().unwrap().balance, 7);
+    }
+}
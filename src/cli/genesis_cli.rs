@@ -0,0 +1,171 @@
+use clap::{Parser, Subcommand};
+use rustchain::genesis::{Genesis, GenesisData};
+use rustchain::keystore::KeyFile;
+use rustchain::types::{Address, PublicKey};
+use rustchain::wallet::parse_payouts_csv;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_GENESIS_MESSAGE: &str = "RustChain Genesis Block";
+
+#[derive(Parser, Debug)]
+pub struct GenesisArgs {
+    /// Also accept addresses as 64 hex characters. `create` writes them in rc1 form
+    #[clap(long, global = true)]
+    legacy_hex_addresses: bool,
+    #[clap(subcommand)]
+    action: GenesisAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum GenesisAction {
+    /// Write a genesis file from validator keys and initial allocations
+    Create {
+        /// Validator, as a hex public key or a key file. Repeat for each validator, in
+        /// proposer order; the first proposes the genesis block
+        #[clap(long = "validator", required = true)]
+        validators: Vec<String>,
+        /// Initial allocation as <ADDRESS>=<AMOUNT>. Can be repeated
+        #[clap(long = "alloc", value_parser = parse_allocation)]
+        allocations: Vec<(String, u64)>,
+        /// CSV file of `address,amount` allocations, as read by `wallet send-batch`
+        #[clap(long, value_parser)]
+        allocations_file: Option<PathBuf>,
+        /// Unix timestamp of the genesis block [default: now]
+        #[clap(long)]
+        timestamp: Option<u64>,
+        #[clap(long, default_value = DEFAULT_GENESIS_MESSAGE)]
+        message: String,
+        /// Path to write the genesis file to
+        #[clap(long, short, value_parser)]
+        out: PathBuf,
+        /// Replace the file at --out if it exists
+        #[clap(long)]
+        force: bool,
+    },
+    /// Check a genesis file and print what it sets up, including its hashes
+    Validate {
+        #[clap(value_parser)]
+        file: PathBuf,
+    },
+    /// Print the genesis block hash, which every node of the chain must share
+    Hash {
+        #[clap(value_parser)]
+        file: PathBuf,
+        /// Print the hash of the whole genesis, balances included, instead
+        #[clap(long)]
+        content: bool,
+    },
+}
+
+fn parse_allocation(s: &str) -> Result<(String, u64), String> {
+    let (address, amount) = s.split_once('=').ok_or_else(|| format!("expected <ADDRESS>=<AMOUNT>, got `{}`", s))?;
+    let amount = amount.trim().parse().map_err(|e| format!("invalid amount `{}`: {}", amount, e))?;
+    Ok((address.trim().to_string(), amount))
+}
+
+pub fn run_genesis_cli(args: GenesisArgs) -> anyhow::Result<()> {
+    let legacy_hex_addresses = args.legacy_hex_addresses;
+    match args.action {
+        GenesisAction::Create { validators, allocations, allocations_file, timestamp, message, out, force } => {
+            if out.exists() && !force {
+                return Err(anyhow::anyhow!("{} already exists. Pass --force to replace it", out.display()));
+            }
+            let validators = validators.iter().map(|validator| read_validator(validator)).collect::<anyhow::Result<Vec<_>>>()?;
+
+            let mut parsed = Vec::new();
+            for (address, amount) in allocations {
+                let address = Address::parse(&address, legacy_hex_addresses).map_err(|e| anyhow::anyhow!("--alloc: {}", e))?;
+                parsed.push((address, amount));
+            }
+            if let Some(path) = &allocations_file {
+                let contents = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+                let transfers = parse_payouts_csv(&contents, legacy_hex_addresses).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                parsed.extend(transfers.into_iter().map(|transfer| (transfer.recipient, transfer.amount)));
+            }
+            let mut initial_balances = BTreeMap::new();
+            for (address, amount) in parsed {
+                if initial_balances.insert(address.to_string(), amount).is_some() {
+                    return Err(anyhow::anyhow!("{} is allocated more than once", address));
+                }
+            }
+
+            let data = GenesisData {
+                validators: validators.iter().map(|public_key| public_key.to_string()).collect(),
+                initial_balances,
+                vesting: BTreeMap::new(),
+                timestamp: timestamp.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()),
+                message,
+            };
+            let genesis = data.validate(false).map_err(|e| anyhow::anyhow!("Invalid genesis: {}", e))?;
+            write_genesis_file(&out, &data, force).map_err(|e| anyhow::anyhow!("Failed to write {}: {}", out.display(), e))?;
+            println!("Genesis written to: {}", out.display());
+            print_genesis(&genesis)?;
+        }
+        GenesisAction::Validate { file } => {
+            let genesis = load_genesis(&file, legacy_hex_addresses)?;
+            println!("{} is a valid genesis file", file.display());
+            print_genesis(&genesis)?;
+        }
+        GenesisAction::Hash { file, content } => {
+            let genesis = load_genesis(&file, legacy_hex_addresses)?;
+            let hash = if content { genesis.content_hash()? } else { genesis.block_hash()? };
+            println!("{}", hash);
+        }
+    }
+    Ok(())
+}
+
+/// A validator given as a key file, or as a hex public key if no such file exists.
+fn read_validator(validator: &str) -> anyhow::Result<PublicKey> {
+    let path = Path::new(validator);
+    if path.exists() {
+        return match KeyFile::read(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))? {
+            KeyFile::Encrypted(keystore) => Ok(keystore.public_key()?),
+            KeyFile::Plain(signing_key) => Ok(PublicKey(signing_key.verifying_key())),
+        };
+    }
+    let bytes: [u8; 32] = hex::decode(validator.strip_prefix("0x").unwrap_or(validator))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Validator `{}` is neither a key file nor a 32-byte hex public key", validator))?;
+    let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+        .map_err(|e| anyhow::anyhow!("Validator `{}` is not a valid Ed25519 public key: {}", validator, e))?;
+    Ok(PublicKey(verifying_key))
+}
+
+fn load_genesis(path: &Path, legacy_hex_addresses: bool) -> anyhow::Result<Genesis> {
+    let data = GenesisData::load(path).map_err(|e| anyhow::anyhow!("Failed to load {}: {}", path.display(), e))?;
+    data.validate(legacy_hex_addresses).map_err(|e| anyhow::anyhow!("{} is not a valid genesis: {}", path.display(), e))
+}
+
+fn write_genesis_file(path: &Path, data: &GenesisData, force: bool) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(format!("{}\n", serde_json::to_string_pretty(data)?).as_bytes())?;
+    Ok(())
+}
+
+fn print_genesis(genesis: &Genesis) -> anyhow::Result<()> {
+    println!("  Validators: {}", genesis.validators.len());
+    for (index, public_key) in genesis.validators.iter().enumerate() {
+        println!("    {}: {}", index, public_key);
+    }
+    let vesting = genesis.accounts.values().filter(|account| account.vesting.is_some()).count();
+    println!("  Accounts: {} ({} with vesting)", genesis.accounts.len(), vesting);
+    println!("  Total supply: {}", genesis.total_supply);
+    println!("  Timestamp: {}", genesis.timestamp);
+    println!("  Message: {}", genesis.message);
+    println!("  Block hash: {}", genesis.block_hash()?);
+    println!("  Content hash: {}", genesis.content_hash()?);
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use clap::{Parser, Subcommand};
+use ed25519_dalek::SigningKey;
+use rustchain::keytool::{self, VanityPrefix};
+use rustchain::types::{address_from_public_key, PublicKey};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+pub struct KeytoolArgs {
+    #[clap(subcommand)]
+    action: KeytoolAction,
+}
+
+#[derive(Subcommand, Debug)]
+enum KeytoolAction {
+    /// Generate a random validator key
+    Generate {
+        #[clap(flatten)]
+        output: KeyOutput,
+    },
+    /// Derive a key from a seed phrase, so test fixtures can be recreated. Anyone who knows
+    /// the seed has the key: never use one for real funds
+    Derive {
+        /// Seed to hash into the key, e.g. rustchain_test_validator_seed_123
+        #[clap(long)]
+        seed: String,
+        #[clap(flatten)]
+        output: KeyOutput,
+    },
+    /// Search for a key whose address starts with rc1<PREFIX>. Each extra character makes
+    /// the search 32 times longer
+    Vanity {
+        /// Characters wanted after rc1, from qpzry9x8gf2tvdw0s3jn54khce6mua7l
+        #[clap(long)]
+        prefix: VanityPrefix,
+        /// Threads to search on [default: all CPU cores]
+        #[clap(long)]
+        threads: Option<usize>,
+        #[clap(flatten)]
+        output: KeyOutput,
+    },
+}
+
+/// Where a new key is written. Keys are written as raw 32-byte files, the format nodes read
+/// validator keys in; `wallet import` encrypts one for use as a wallet.
+#[derive(Parser, Debug)]
+struct KeyOutput {
+    /// Path to write the key to
+    #[clap(long, short, value_parser)]
+    out: PathBuf,
+    /// Replace the file at --out if it exists
+    #[clap(long)]
+    force: bool,
+}
+
+impl KeyOutput {
+    /// Checked before any work is done, so a long vanity search is not wasted on a path
+    /// that cannot be written.
+    fn check(&self) -> anyhow::Result<()> {
+        if self.out.exists() && !self.force {
+            return Err(anyhow::anyhow!("{} already exists. Pass --force to replace it", self.out.display()));
+        }
+        Ok(())
+    }
+
+    fn write(&self, signing_key: &SigningKey) -> anyhow::Result<()> {
+        write_key_file(&self.out, signing_key, self.force)
+            .map_err(|e| anyhow::anyhow!("Failed to write key to {}: {}", self.out.display(), e))
+    }
+}
+
+pub fn run_keytool_cli(args: KeytoolArgs) -> anyhow::Result<()> {
+    match args.action {
+        KeytoolAction::Generate { output } => {
+            output.check()?;
+            let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+            output.write(&signing_key)?;
+            println!("Generated validator key and saved to: {}", output.out.display());
+            print_key(&signing_key);
+        }
+        KeytoolAction::Derive { seed, output } => {
+            output.check()?;
+            let signing_key = keytool::signing_key_from_seed(&seed);
+            output.write(&signing_key)?;
+            println!("Derived key from seed and saved to: {}", output.out.display());
+            print_key(&signing_key);
+            println!("  Warning: anyone who knows the seed has this key. Use it only for tests.");
+        }
+        KeytoolAction::Vanity { prefix, threads, output } => {
+            output.check()?;
+            let threads = match threads {
+                Some(threads) => threads.max(1),
+                None => std::thread::available_parallelism().map(usize::from).unwrap_or(1),
+            };
+            println!(
+                "Searching for an address starting with {} on {} thread(s), about {:.0} keys on average...",
+                prefix,
+                threads,
+                prefix.expected_attempts()
+            );
+            let started = Instant::now();
+            let found = keytool::search_vanity(&prefix, threads);
+            let elapsed = started.elapsed();
+            output.write(&found.signing_key)?;
+            println!(
+                "Found after {} keys in {:.1}s ({:.0} keys/s) and saved to: {}",
+                found.attempts,
+                elapsed.as_secs_f64(),
+                found.attempts as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+                output.out.display()
+            );
+            print_key(&found.signing_key);
+            println!("  The key file is not encrypted. Run `wallet import --raw-key {}` to use it as a wallet.", output.out.display());
+        }
+    }
+    Ok(())
+}
+
+fn print_key(signing_key: &SigningKey) {
+    let public_key = PublicKey(signing_key.verifying_key());
+    println!("  Address: {}", address_from_public_key(&public_key));
+    println!("  Public Key: {}", public_key);
+}
+
+/// Writes the raw secret readable only by its owner. Unless `force` is set, an existing file
+/// is never replaced, even if it appeared after `KeyOutput::check`.
+fn write_key_file(path: &Path, signing_key: &SigningKey, force: bool) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(&signing_key.to_bytes())?;
+    file.sync_all()
+}
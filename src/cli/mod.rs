@@ -1 +1,3 @@
-pub mod wallet_cli; 
\ No newline at end of file
+pub mod wallet_cli;
+pub mod keytool_cli;
+pub mod genesis_cli;
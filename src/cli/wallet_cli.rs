@@ -1,10 +1,23 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
-use rustchain::wallet::Wallet; // Changed from rustchain::wallet
-use rustchain::types::{Address, Nonce};
+use clap::{Args, Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use rustchain::hd_wallet::{self, DerivationPath};
+use rustchain::keystore::{KeyFile, Keystore, ScryptParams};
+use rustchain::wallet::{parse_payouts_csv, Wallet};
+use rustchain::multisig::MultisigPolicy;
+use rustchain::networking;
+use rustchain::rpc::RpcClient;
+use rustchain::wallet_dir::WalletDir;
+use rustchain::transaction::{Transaction, TransactionKind, Transfer, TxSender, TxSignature, ValidityWindow};
+use rustchain::types::{address_from_public_key as account_address, Address, BlockHeight, Nonce, PublicKey, Signature};
 use bincode;
 use anyhow;
 use hex; // Added hex import
+use libp2p::Multiaddr;
+use std::time::{Duration, Instant};
+
+/// How often, and for how long, a submitted transaction is polled for until it is included.
+const INCLUSION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const INCLUSION_TIMEOUT: Duration = Duration::from_secs(60);
 
 // Main CLI structure if this module handles the entire `rustchain` command.
 // If `main.rs` has its own top-level Commands (e.g. for `node` vs `wallet`),
@@ -27,20 +40,218 @@ pub enum Commands {
 
 #[derive(Parser, Debug)]
 pub struct WalletCliArgs { // This struct now holds the sub-actions for the `wallet` command
+    #[clap(flatten)]
+    passphrase: PassphraseArgs,
+    /// Directory holding named accounts [default: ~/.rustchain/wallets]
+    #[clap(long, global = true, value_parser)]
+    wallet_dir: Option<PathBuf>,
+    /// RPC address of a running node, e.g. 127.0.0.1:9933. Commands that sign then fetch the
+    /// nonce, check the balance and submit the transaction instead of printing it
+    #[clap(long, global = true)]
+    node: Option<String>,
+    /// Also accept addresses as 64 hex characters. Hex has no checksum, so a typo sends
+    /// funds to a valid but unspendable address
+    #[clap(long, global = true)]
+    legacy_hex_addresses: bool,
     #[clap(subcommand)]
     action: WalletAction,
 }
 
-// Helper function to parse Address from hex string
-fn parse_address(s: &str) -> Result<Address, String> {
-    let s = s.strip_prefix("0x").unwrap_or(s);
-    if s.len() != 64 { // 32 bytes = 64 hex chars
-        return Err(format!("Address hex string must be 64 characters long, got {}", s.len()));
+/// Where the keystore passphrase comes from. Without `--passphrase-file` it is prompted for.
+#[derive(Args, Debug)]
+struct PassphraseArgs {
+    /// File whose first line is the keystore passphrase, for scripts
+    #[clap(long, global = true, value_parser)]
+    passphrase_file: Option<PathBuf>,
+}
+
+impl PassphraseArgs {
+    fn read(&self, prompt: &str) -> anyhow::Result<String> {
+        let passphrase = match &self.passphrase_file {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read passphrase from {}: {}", path.display(), e))?
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            None => rpassword::prompt_password(prompt)?,
+        };
+        if passphrase.is_empty() {
+            return Err(anyhow::anyhow!("The passphrase must not be empty"));
+        }
+        Ok(passphrase)
+    }
+
+    /// Reads the passphrase for a new keystore, asking twice when prompting.
+    fn read_new(&self) -> anyhow::Result<String> {
+        let passphrase = self.read("New passphrase: ")?;
+        if self.passphrase_file.is_none() && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+            return Err(anyhow::anyhow!("Passphrases do not match"));
+        }
+        Ok(passphrase)
+    }
+}
+
+/// Where a BIP39 recovery phrase comes from. Without `--mnemonic-file` it is prompted for,
+/// so it never ends up in the shell history.
+#[derive(Args, Debug)]
+struct MnemonicArgs {
+    /// File holding the recovery phrase, for scripts
+    #[clap(long, value_parser)]
+    mnemonic_file: Option<PathBuf>,
+}
+
+impl MnemonicArgs {
+    fn read(&self) -> anyhow::Result<bip39::Mnemonic> {
+        let phrase = match &self.mnemonic_file {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read recovery phrase from {}: {}", path.display(), e))?,
+            None => rpassword::prompt_password("Recovery phrase: ")?,
+        };
+        Ok(hd_wallet::parse_mnemonic(&phrase)?)
+    }
+}
+
+/// Options shared by the commands that sign: how to unlock the key, and the node, if any,
+/// that supplies the nonce and receives the transaction.
+struct Session<'a> {
+    passphrase: &'a PassphraseArgs,
+    node: Option<&'a str>,
+}
+
+impl Session<'_> {
+    fn connect(&self) -> anyhow::Result<Option<RpcClient>> {
+        self.node
+            .map(|node| RpcClient::connect(node).map_err(|e| anyhow::anyhow!("Failed to connect to node at {}: {}", node, e)))
+            .transpose()
+    }
+
+    /// `nonce` if given, otherwise the next nonce of `account` according to the node.
+    fn nonce(&self, nonce: Option<u64>, account: &Address) -> anyhow::Result<Nonce> {
+        if let Some(nonce) = nonce {
+            return Ok(Nonce(nonce));
+        }
+        let mut client = self.connect()?.ok_or_else(|| anyhow::anyhow!("Pass --nonce, or --node to fetch it"))?;
+        Ok(Nonce(client.get_balance(account)?.nonce))
+    }
+}
+
+/// The account a key signs for: `from` if it rotated its authorized key, or the key's own.
+fn signing_account(wallet: &Wallet, from: Option<Address>) -> Address {
+    from.unwrap_or_else(|| account_address(wallet.public_key()))
+}
+
+/// Which key signs: a key file, or a named account in the wallet directory.
+#[derive(Args, Debug)]
+struct KeyArgs {
+    /// Path to the key file to use. Defaults to the wallet directory's default account,
+    /// then to default_wallet.key
+    #[clap(short, long, value_parser)]
+    keyfile: Option<PathBuf>,
+    /// Account in the wallet directory to use
+    #[clap(long, conflicts_with = "keyfile")]
+    account: Option<String>,
+}
+
+impl KeyArgs {
+    fn path(&self, wallet_dir: &Path) -> anyhow::Result<PathBuf> {
+        if let Some(keyfile) = &self.keyfile {
+            return Ok(keyfile.clone());
+        }
+        let wallets = WalletDir::open(wallet_dir)?;
+        match self.account.as_deref().or(wallets.default_account()) {
+            Some(name) => Ok(wallets.keyfile(name)?),
+            None => Ok(PathBuf::from(DEFAULT_KEY_FILE)),
+        }
+    }
+}
+
+/// An address as given on the command line. Legacy hex is parsed too, so it can be
+/// refused with a clear message unless `--legacy-hex-addresses` is set.
+#[derive(Debug, Clone, Copy)]
+struct AddressArg {
+    address: Address,
+    hex: bool,
+}
+
+impl AddressArg {
+    fn resolve(&self, legacy_hex_addresses: bool) -> anyhow::Result<Address> {
+        if self.hex && !legacy_hex_addresses {
+            return Err(anyhow::anyhow!(
+                "0x{} is a hex address, which has no checksum. Use the address's rc1... form, or pass --legacy-hex-addresses",
+                hex::encode(self.address.0)
+            ));
+        }
+        Ok(self.address)
+    }
+}
+
+fn parse_address(s: &str) -> Result<AddressArg, String> {
+    match s.parse() {
+        Ok(address) => Ok(AddressArg { address, hex: false }),
+        Err(e) => Address::from_hex(s).map(|address| AddressArg { address, hex: true }).map_err(|_| e),
     }
+}
+
+// Helper function to parse a PublicKey from hex string
+fn parse_public_key(s: &str) -> Result<PublicKey, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
     let mut bytes = [0u8; 32];
     hex::decode_to_slice(s, &mut bytes)
-        .map_err(|e| format!("Invalid hex string for address: {}", e))?;
-    Ok(Address(bytes))
+        .map_err(|e| format!("Public key must be 64 hex characters: {}", e))?;
+    ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+        .map(PublicKey)
+        .map_err(|e| format!("Invalid public key: {}", e))
+}
+
+/// Optional block-height window a transaction may be included in.
+#[derive(Args, Debug)]
+struct ValidityArgs {
+    /// Only blocks above this height may include the transaction
+    #[clap(long)]
+    valid_after: Option<u64>,
+    /// Blocks above this height may no longer include the transaction
+    #[clap(long)]
+    valid_until: Option<u64>,
+}
+
+impl ValidityArgs {
+    fn window(&self) -> ValidityWindow {
+        ValidityWindow {
+            valid_after_height: self.valid_after.map(BlockHeight),
+            valid_until_height: self.valid_until.map(BlockHeight),
+        }
+    }
+}
+
+/// Optional memo, given as text or as hex for binary references.
+#[derive(Args, Debug)]
+struct MemoArgs {
+    /// Memo or payment reference to attach, as UTF-8 text
+    #[clap(long, conflicts_with = "memo_hex")]
+    memo: Option<String>,
+    /// Memo to attach, as hex bytes
+    #[clap(long)]
+    memo_hex: Option<String>,
+}
+
+impl MemoArgs {
+    fn bytes(&self) -> anyhow::Result<Vec<u8>> {
+        match (&self.memo, &self.memo_hex) {
+            (Some(text), _) => Ok(text.as_bytes().to_vec()),
+            (None, Some(hex_memo)) => hex::decode(hex_memo.strip_prefix("0x").unwrap_or(hex_memo))
+                .map_err(|e| anyhow::anyhow!("Invalid hex string for memo: {}", e)),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Shows a memo as text when it is printable UTF-8, and as hex otherwise.
+fn format_memo(memo: &[u8]) -> String {
+    match std::str::from_utf8(memo) {
+        Ok(text) if !text.chars().any(char::is_control) => format!("\"{}\"", text),
+        _ => format!("0x{}", hex::encode(memo)),
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -50,47 +261,391 @@ enum WalletAction {
         /// Optional: Path to save the generated key file
         #[clap(short, long, value_parser)]
         keyfile: Option<PathBuf>,
+        /// Generate a BIP39 recovery phrase and derive the key from it (account 0)
+        #[clap(long)]
+        mnemonic: bool,
+        /// Number of words in the recovery phrase
+        #[clap(long, default_value_t = 24, requires = "mnemonic")]
+        words: usize,
+        /// Replace the key file if it exists
+        #[clap(long)]
+        force: bool,
     },
-    /// Show wallet address and public key from a key file
-    Show {
-        /// Optional: Path to the key file to load
+    /// Restore a wallet from its BIP39 recovery phrase
+    Recover {
+        /// Account index to derive, at path m/44'/1'/<index>'
+        #[clap(long, default_value_t = 0)]
+        index: u32,
+        /// Optional: Path to save the recovered key file
+        #[clap(short, long, value_parser)]
+        keyfile: Option<PathBuf>,
+        /// Replace the key file if it exists
+        #[clap(long)]
+        force: bool,
+        #[clap(flatten)]
+        mnemonic: MnemonicArgs,
+    },
+    /// Show the account derived from a recovery phrase at an index, and optionally save it
+    Derive {
+        /// Account index to derive, at path m/44'/1'/<index>'
+        #[clap(long)]
+        index: u32,
+        /// Optional: Path to save the derived key file. Without it nothing is written
         #[clap(short, long, value_parser)]
         keyfile: Option<PathBuf>,
+        /// Replace the key file if it exists
+        #[clap(long)]
+        force: bool,
+        #[clap(flatten)]
+        mnemonic: MnemonicArgs,
+    },
+    /// Encrypt a raw key file written by earlier versions into a passphrase-protected keystore
+    Import {
+        /// Raw 32-byte key file to encrypt
+        #[clap(long, value_parser)]
+        raw_key: PathBuf,
+        /// Where to write the keystore. Defaults to replacing the raw key file
+        #[clap(short, long, value_parser)]
+        keyfile: Option<PathBuf>,
+    },
+    /// Show wallet address and public key from a key file
+    Show {
+        #[clap(flatten)]
+        key: KeyArgs,
     },
     /// Create and sign a transaction, then print it (serialized)
     Send {
-        /// Recipient's address (hex string, e.g., 0x...)
+        /// Recipient's address, e.g. rc1... (hex only with --legacy-hex-addresses)
         #[clap(long, value_parser = parse_address)]
-        to: Address,
+        to: AddressArg,
         /// Amount to send
         #[clap(long)]
         amount: u64,
-        /// Transaction nonce
+        /// Transaction nonce. Fetched from `--node` when omitted
         #[clap(long)]
-        nonce: u64, // Will be wrapped into Nonce type
-        /// Optional: Path to the key file to use for sending
-        #[clap(short, long, value_parser)]
-        keyfile: Option<PathBuf>,
+        nonce: Option<u64>,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to send from, if it rotated its authorized key to this key file's key
+        #[clap(long, value_parser = parse_address)]
+        from: Option<AddressArg>,
+        #[clap(flatten)]
+        validity: ValidityArgs,
+        #[clap(flatten)]
+        memo: MemoArgs,
+    },
+    /// Create and sign one transaction paying every recipient in a CSV file
+    SendBatch {
+        /// CSV file with one `recipient,amount` line per payout
+        #[clap(long, value_parser)]
+        csv: PathBuf,
+        /// Transaction nonce. Fetched from `--node` when omitted
+        #[clap(long)]
+        nonce: Option<u64>,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to send from, if it rotated its authorized key to this key file's key
+        #[clap(long, value_parser = parse_address)]
+        from: Option<AddressArg>,
+        #[clap(flatten)]
+        validity: ValidityArgs,
+    },
+    /// Create and sign a transaction making another key the only one allowed to sign for
+    /// the account, e.g. after the current key was compromised
+    RotateKey {
+        /// Public key (hex) that will control the account
+        #[clap(long, value_parser = parse_public_key)]
+        new_key: PublicKey,
+        /// Transaction nonce. Fetched from `--node` when omitted
+        #[clap(long)]
+        nonce: Option<u64>,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to rotate, if it rotated its authorized key to this key file's key
+        #[clap(long, value_parser = parse_address)]
+        from: Option<AddressArg>,
+        #[clap(flatten)]
+        validity: ValidityArgs,
+    },
+    /// Wrap a transaction signed by another account, e.g. the hex printed by `send`, and
+    /// sign it as its relayer under this key file's nonce
+    Relay {
+        /// Serialized signed transaction to relay (hex)
+        #[clap(long)]
+        tx: String,
+        /// Nonce of the relaying account. Fetched from `--node` when omitted
+        #[clap(long)]
+        nonce: Option<u64>,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to relay from, if it rotated its authorized key to this key file's key
+        #[clap(long, value_parser = parse_address)]
+        from: Option<AddressArg>,
+        #[clap(flatten)]
+        validity: ValidityArgs,
+    },
+    /// Show an account's balance and next nonce, as seen by `--node`
+    Balance {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to look up instead of the key's own account, e.g. a multisig or rotated one
+        #[clap(long, value_parser = parse_address)]
+        address: Option<AddressArg>,
+    },
+    /// List the committed transactions that touch an account, newest first, from `--node`
+    History {
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to look up instead of the key's own account, e.g. a multisig or rotated one
+        #[clap(long, value_parser = parse_address)]
+        address: Option<AddressArg>,
+        /// Number of transactions to show
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// List the accounts in the wallet directory
+    List,
+    /// Add a named account to the wallet directory, with a new key or an existing key file
+    Add {
+        /// Account name: letters, digits, `-` and `_`
+        name: String,
+        /// Free-form description shown by `wallet list`
+        #[clap(long)]
+        label: Option<String>,
+        /// Key file to add instead of generating a new key. Raw keys are encrypted first
+        #[clap(long, value_parser)]
+        import: Option<PathBuf>,
+    },
+    /// Rename an account in the wallet directory
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Remove an account from the wallet directory. Its key file is moved aside, not deleted
+    Remove {
+        name: String,
+    },
+    /// Make an account the one used when neither `--account` nor `--keyfile` is given
+    SetDefault {
+        name: String,
+    },
+    /// Write an unsigned transfer to a file, to be signed with `wallet sign` on a machine
+    /// that holds the key. Only the sender's public key is needed
+    BuildUnsigned {
+        /// Recipient's address, e.g. rc1... (hex only with --legacy-hex-addresses)
+        #[clap(long, value_parser = parse_address)]
+        to: AddressArg,
+        /// Amount to send
+        #[clap(long)]
+        amount: u64,
+        /// Transaction nonce. Fetched from `--node` when omitted
+        #[clap(long)]
+        nonce: Option<u64>,
+        // Parsed when used: a `PublicKey` field would make this the largest variant by far
+        /// Public key (hex) that will sign, instead of the one in the key file or account
+        #[clap(long, conflicts_with_all = ["keyfile", "account"])]
+        public_key: Option<String>,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// Account to send from, if it rotated its authorized key to the signing key
+        #[clap(long, value_parser = parse_address)]
+        from: Option<AddressArg>,
+        #[clap(flatten)]
+        validity: ValidityArgs,
+        #[clap(flatten)]
+        memo: MemoArgs,
+        /// File to write the unsigned transaction to
+        #[clap(long, value_parser)]
+        out: PathBuf,
+    },
+    /// Sign a transaction file written by `build-unsigned`. Needs no network access
+    Sign {
+        /// Transaction file to sign
+        #[clap(value_parser)]
+        file: PathBuf,
+        #[clap(flatten)]
+        key: KeyArgs,
+        /// File to write the signed transaction to [default: overwrite FILE]
+        #[clap(long, value_parser)]
+        out: Option<PathBuf>,
+    },
+    /// Publish a signed transaction file on the network's `transactions` gossip topic,
+    /// joining as a light peer without running a node
+    Broadcast {
+        /// Signed transaction file
+        #[clap(value_parser)]
+        file: PathBuf,
+        /// Multiaddr of a peer to connect to, e.g. /ip4/10.0.0.5/tcp/30333; repeat for
+        /// more. Peers on the local network are also found over mDNS
+        #[clap(long = "peer")]
+        peers: Vec<Multiaddr>,
+        /// Seconds to wait for a peer to connect
+        #[clap(long, default_value_t = 30)]
+        timeout: u64,
+    },
+    /// Decode a transaction file and show what it does and whether it is validly signed
+    Inspect {
+        /// Transaction file, or serialized transaction hex
+        #[clap(value_parser)]
+        file: PathBuf,
+    },
+    /// Work with M-of-N multisig accounts
+    Multisig {
+        #[clap(subcommand)]
+        action: MultisigAction,
+    },
+}
+
+/// Multisig transactions are passed between cosigners as the same serialized hex that
+/// `send` prints, so each key holder can sign on their own machine.
+#[derive(Subcommand, Debug)]
+enum MultisigAction {
+    /// Print the address controlled by a set of keys and a threshold
+    Address {
+        /// Number of signatures required
+        #[clap(long)]
+        threshold: u8,
+        /// Public key of a cosigner (hex); repeat for each key
+        #[clap(long = "pubkey", value_parser = parse_public_key, required = true)]
+        public_keys: Vec<PublicKey>,
+    },
+    /// Create an unsigned transfer from a multisig account
+    Create {
+        /// Number of signatures required
+        #[clap(long)]
+        threshold: u8,
+        /// Public key of a cosigner (hex); repeat for each key
+        #[clap(long = "pubkey", value_parser = parse_public_key, required = true)]
+        public_keys: Vec<PublicKey>,
+        /// Recipient's address, e.g. rc1... (hex only with --legacy-hex-addresses)
+        #[clap(long, value_parser = parse_address)]
+        to: AddressArg,
+        /// Amount to send
+        #[clap(long)]
+        amount: u64,
+        /// Nonce of the multisig account
+        #[clap(long)]
+        nonce: u64,
+        #[clap(flatten)]
+        validity: ValidityArgs,
+    },
+    /// Add this wallet's signature to a serialized multisig transaction
+    Sign {
+        /// Serialized transaction (hex)
+        #[clap(long)]
+        tx: String,
+        #[clap(flatten)]
+        key: KeyArgs,
+    },
+    /// Merge the signatures of partially signed copies of one transaction
+    Combine {
+        /// Serialized partially signed transaction (hex); repeat for each copy
+        #[clap(long = "tx", required = true)]
+        txs: Vec<String>,
     },
 }
 
 const DEFAULT_KEY_FILE: &str = "default_wallet.key";
 
-fn handle_generate_wallet(keyfile_opt: &Option<PathBuf>) -> anyhow::Result<()> {
-    let wallet = Wallet::new();
+/// Loads the wallet in `path`, asking for the passphrase if it is an encrypted keystore.
+/// Raw key files from earlier versions still load, with a reminder to encrypt them.
+fn load_wallet(path: &Path, passphrase: &PassphraseArgs) -> anyhow::Result<Wallet> {
+    match KeyFile::read(path)? {
+        KeyFile::Encrypted(keystore) => {
+            let passphrase = passphrase.read(&format!("Passphrase for {}: ", path.display()))?;
+            Ok(Wallet::from_signing_key(keystore.decrypt(&passphrase)?))
+        }
+        KeyFile::Plain(signing_key) => {
+            eprintln!(
+                "Warning: {} holds an unencrypted key. Encrypt it with `wallet import --raw-key {}`.",
+                path.display(),
+                path.display()
+            );
+            Ok(Wallet::from_signing_key(signing_key))
+        }
+    }
+}
+
+/// Refuses to replace an existing key file unless `force` is set. Checked before a key is
+/// generated or a passphrase asked for.
+fn check_keyfile_free(path: &Path, force: bool) -> anyhow::Result<()> {
+    if path.exists() && !force {
+        return Err(anyhow::anyhow!("{} already exists. Pass --force to replace it", path.display()));
+    }
+    Ok(())
+}
+
+fn handle_generate_wallet(keyfile_opt: &Option<PathBuf>, force: bool, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
     let keyfile_path: PathBuf = keyfile_opt.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_KEY_FILE));
+    check_keyfile_free(&keyfile_path, force)?;
+    let wallet = Wallet::new();
     
-    wallet.save_to_file(keyfile_path.to_str().unwrap_or(DEFAULT_KEY_FILE))
+    wallet.save_encrypted(&keyfile_path, &passphrase.read_new()?, ScryptParams::default())
         .map_err(|e| anyhow::anyhow!("Failed to save wallet to {}: {}", keyfile_path.display(), e))?;
     println!("Generated new wallet and saved to: {}", keyfile_path.display());
-    println!("  Address: {}", wallet.address());
+    println!("  Address: {}", account_address(wallet.public_key()));
     println!("  Public Key: {}", wallet.public_key());
     Ok(())
 }
 
-fn handle_show_wallet(keyfile_opt: &Option<PathBuf>) -> anyhow::Result<()> {
+fn handle_generate_mnemonic(words: usize, keyfile_opt: &Option<PathBuf>, force: bool, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
     let keyfile_path: PathBuf = keyfile_opt.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_KEY_FILE));
+    check_keyfile_free(&keyfile_path, force)?;
+    let mnemonic = hd_wallet::generate_mnemonic(words)?;
+    let wallet = Wallet::from_mnemonic(&mnemonic, 0)?;
+
+    wallet.save_encrypted(&keyfile_path, &passphrase.read_new()?, ScryptParams::default())
+        .map_err(|e| anyhow::anyhow!("Failed to save wallet to {}: {}", keyfile_path.display(), e))?;
+    println!("Recovery phrase ({} words):\n", mnemonic.word_count());
+    println!("  {}\n", mnemonic);
+    println!("Write it down and keep it offline. It restores every account of this wallet with");
+    println!("`wallet recover`, and it is not shown again.\n");
+    println!("Generated new wallet and saved to: {}", keyfile_path.display());
+    println!("  Path: {}", DerivationPath::for_account(0)?);
+    println!("  Address: {}", account_address(wallet.public_key()));
+    println!("  Public Key: {}", wallet.public_key());
+    Ok(())
+}
+
+/// Derives account `index` from `mnemonic` and prints it, saving it to `keyfile` if given.
+fn handle_derive(mnemonic: &bip39::Mnemonic, index: u32, keyfile: Option<&Path>, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
+    let path = DerivationPath::for_account(index)?;
+    let wallet = Wallet::from_mnemonic(mnemonic, index)?;
+
+    if let Some(keyfile_path) = keyfile {
+        wallet.save_encrypted(keyfile_path, &passphrase.read_new()?, ScryptParams::default())
+            .map_err(|e| anyhow::anyhow!("Failed to save wallet to {}: {}", keyfile_path.display(), e))?;
+        println!("Derived account {} and saved to: {}", index, keyfile_path.display());
+    } else {
+        println!("Derived account {}:", index);
+    }
+    println!("  Path: {}", path);
+    println!("  Address: {}", account_address(wallet.public_key()));
+    println!("  Public Key: {}", wallet.public_key());
+    Ok(())
+}
+
+fn handle_import_wallet(raw_key: &Path, keyfile_opt: &Option<PathBuf>, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
+    let KeyFile::Plain(signing_key) = KeyFile::read(raw_key)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", raw_key.display(), e))?
+    else {
+        return Err(anyhow::anyhow!("{} is already an encrypted keystore", raw_key.display()));
+    };
+    let wallet = Wallet::from_signing_key(signing_key);
+    let keyfile_path = keyfile_opt.clone().unwrap_or_else(|| raw_key.to_path_buf());
+
+    wallet.save_encrypted(&keyfile_path, &passphrase.read_new()?, ScryptParams::default())
+        .map_err(|e| anyhow::anyhow!("Failed to save wallet to {}: {}", keyfile_path.display(), e))?;
+    println!("Encrypted key from {} and saved to: {}", raw_key.display(), keyfile_path.display());
+    println!("  Address: {}", account_address(wallet.public_key()));
+    println!("  Public Key: {}", wallet.public_key());
+    if keyfile_path != raw_key {
+        println!("\nThe unencrypted key is still in {}. Delete it once the keystore is backed up.", raw_key.display());
+    }
+    Ok(())
+}
 
+fn handle_show_wallet(keyfile_path: &Path, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
     if !keyfile_path.exists() {
         return Err(anyhow::anyhow!(
             "Error: Key file not found at path: {}. Please generate a wallet or provide a valid --keyfile path.", 
@@ -98,22 +653,77 @@ fn handle_show_wallet(keyfile_opt: &Option<PathBuf>) -> anyhow::Result<()> {
         ));
     }
 
-    let wallet = Wallet::load_from_file(keyfile_path.to_str().unwrap_or(DEFAULT_KEY_FILE))
+    let wallet = load_wallet(keyfile_path, passphrase)
         .map_err(|e| anyhow::anyhow!("Failed to load wallet from {}: {}", keyfile_path.display(), e))?;
     println!("Wallet details from: {}", keyfile_path.display());
-    println!("  Address: {}", wallet.address());
-    println!("  Public Key: {}", wallet.public_key());
+    for line in wallet_details(&wallet) {
+        println!("  {}", line);
+    }
+    Ok(())
+}
+
+/// The lines `wallet show` prints for a wallet.
+fn wallet_details(wallet: &Wallet) -> Vec<String> {
+    vec![
+        format!("Address: {}", account_address(wallet.public_key())),
+        format!("Public Key: {}", wallet.public_key()),
+    ]
+}
+
+fn handle_list_accounts(wallet_dir: &Path) -> anyhow::Result<()> {
+    let wallets = WalletDir::open(wallet_dir)?;
+    if wallets.accounts().next().is_none() {
+        println!("No accounts in {}. Add one with `wallet add <name>`.", wallets.root().display());
+        return Ok(());
+    }
+    println!("Accounts in {}:", wallets.root().display());
+    for line in account_listing(&wallets) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// One line per account: a `*` for the default, the name, the account address and the label.
+fn account_listing(wallets: &WalletDir) -> Vec<String> {
+    wallets
+        .accounts()
+        .map(|(name, entry)| {
+            let marker = if wallets.default_account() == Some(name) { "*" } else { " " };
+            let address = match wallets.keystore(name).and_then(|keystore| Ok(keystore.public_key()?)) {
+                Ok(public_key) => account_address(&public_key).to_string(),
+                Err(e) => format!("<unreadable: {}>", e),
+            };
+            let line = format!("{} {:<16} {} {}", marker, name, address, entry.label.as_deref().unwrap_or_default());
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+fn handle_add_account(wallet_dir: &Path, name: &str, label: Option<String>, import: Option<&Path>, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
+    let keystore = match import.map(KeyFile::read).transpose()? {
+        Some(KeyFile::Encrypted(keystore)) => keystore,
+        Some(KeyFile::Plain(signing_key)) => Keystore::encrypt(&signing_key, &passphrase.read_new()?, ScryptParams::default())?,
+        None => Keystore::encrypt(Wallet::new().get_signing_key(), &passphrase.read_new()?, ScryptParams::default())?,
+    };
+    let mut wallets = WalletDir::open(wallet_dir)?;
+    let keyfile_path = wallets.add(name, label, &keystore)?;
+    println!("Added account `{}`, saved to: {}", name, keyfile_path.display());
+    println!("  Address: {}", account_address(&keystore.public_key()?));
+    if wallets.default_account() == Some(name) {
+        println!("  It is the default account.");
+    }
     Ok(())
 }
 
 fn handle_send_transaction(
-    to: &Address, 
-    amount: u64, 
-    nonce_val: u64, 
-    keyfile_opt: &Option<PathBuf>
+    transfer: Transfer,
+    nonce: Option<u64>,
+    keyfile_path: &Path,
+    from: Option<Address>,
+    validity: ValidityWindow,
+    memo: Vec<u8>,
+    session: &Session,
 ) -> anyhow::Result<()> {
-    let keyfile_path = keyfile_opt.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_KEY_FILE));
-
     if !keyfile_path.exists() {
         return Err(anyhow::anyhow!(
             "Error: Key file not found at path: {}. Cannot send transaction.", 
@@ -122,49 +732,538 @@ fn handle_send_transaction(
     }
 
     println!("Loading wallet from: {}", keyfile_path.display());
-    let wallet = Wallet::load_from_file(keyfile_path.to_str().unwrap_or(DEFAULT_KEY_FILE))
+    let wallet = load_wallet(keyfile_path, session.passphrase)
         .map_err(|e| anyhow::anyhow!("Failed to load wallet for sending: {}", e))?;
     
-    let nonce = Nonce(nonce_val);
+    let nonce = session.nonce(nonce, &signing_account(&wallet, from))?;
 
     println!("Creating transaction...");
-    println!("  Sender (from keyfile): {}", wallet.address());
-    println!("  Recipient: {}", to);
-    println!("  Amount: {}", amount);
+    println!("  Sender (from keyfile): {}", signing_account(&wallet, from));
+    println!("  Recipient: {}", transfer.recipient);
+    println!("  Amount: {}", transfer.amount);
     println!("  Nonce: {}", nonce.0);
 
-    let transaction = wallet.create_signed_transaction(*to, amount, nonce)
+    let mut transaction = Transaction::with_kind(*wallet.public_key(), nonce, TransactionKind::Transfer(transfer), Signature(vec![]));
+    if let Some(account) = from {
+        transaction = transaction.with_account(account);
+    }
+    let transaction = transaction
+        .with_validity(validity)
+        .with_memo(memo);
+    transaction.validate_intrinsic_properties()?;
+    let transaction = wallet.sign_transaction(transaction)
         .map_err(|e| anyhow::anyhow!("Failed to create signed transaction: {}", e))?;
 
     println!("\nSigned Transaction Details:");
+    println!("  Version: {}", transaction.version);
     println!("  Sender: {}", transaction.sender);
-    println!("  Recipient: {}", transaction.recipient);
-    println!("  Amount: {}", transaction.amount);
+    println!("  Kind: {}", transaction.kind);
     println!("  Nonce: {}", transaction.nonce.0);
+    if let Some(valid_after) = transaction.validity.valid_after_height {
+        println!("  Valid after height: {}", valid_after);
+    }
+    if let Some(valid_until) = transaction.validity.valid_until_height {
+        println!("  Valid until height: {}", valid_until);
+    }
+    if !transaction.memo.is_empty() {
+        println!("  Memo: {}", format_memo(&transaction.memo));
+    }
     println!("  Signature: {}", transaction.signature);
 
-    let config = bincode::config::standard();
-    let serialized_tx = bincode::encode_to_vec(&transaction, config)
-        .map_err(|e| anyhow::anyhow!("Failed to serialize final transaction: {}", e))?;
-    
-    println!("\nSerialized Signed Transaction (hex for broadcast/storage):");
-    println!("{}", hex::encode(serialized_tx));
+    publish(&transaction, session)
+}
+
+fn handle_send_batch(csv_path: &PathBuf, nonce: Option<u64>, keyfile_path: &Path, from: Option<Address>, validity: ValidityWindow, legacy_hex_addresses: bool, session: &Session) -> anyhow::Result<()> {
+    let wallet = load_wallet(keyfile_path, session.passphrase)
+        .map_err(|e| anyhow::anyhow!("Failed to load wallet from {}: {}", keyfile_path.display(), e))?;
+    let nonce = session.nonce(nonce, &signing_account(&wallet, from))?;
+
+    let contents = std::fs::read_to_string(csv_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", csv_path.display(), e))?;
+    let transfers = parse_payouts_csv(&contents, legacy_hex_addresses)?;
+
+    let mut transaction = Transaction::with_kind(*wallet.public_key(), nonce, TransactionKind::BatchTransfer(transfers), Signature(vec![]));
+    if let Some(account) = from {
+        transaction = transaction.with_account(account);
+    }
+    let transaction = transaction.with_validity(validity);
+    transaction.validate_intrinsic_properties()?;
+    let transaction = wallet.sign_transaction(transaction)
+        .map_err(|e| anyhow::anyhow!("Failed to create signed transaction: {}", e))?;
+
+    println!("Signed Batch Transaction Details:");
+    println!("  Sender: {}", transaction.sender_address());
+    println!("  Kind: {}", transaction.kind);
+    println!("  Nonce: {}", transaction.nonce.0);
+    println!("  Transaction ID: {}", transaction.id()?);
+
+    publish(&transaction, session)
+}
+
+fn handle_rotate_key(new_key: &PublicKey, nonce: Option<u64>, keyfile_path: &Path, from: Option<Address>, validity: ValidityWindow, session: &Session) -> anyhow::Result<()> {
+    let wallet = load_wallet(keyfile_path, session.passphrase)
+        .map_err(|e| anyhow::anyhow!("Failed to load wallet from {}: {}", keyfile_path.display(), e))?;
+    let nonce = session.nonce(nonce, &signing_account(&wallet, from))?;
+
+    let mut transaction = Transaction::with_kind(*wallet.public_key(), nonce, TransactionKind::RotateKey { new_key: *new_key }, Signature(vec![]));
+    if let Some(account) = from {
+        transaction = transaction.with_account(account);
+    }
+    let transaction = transaction.with_validity(validity);
+    transaction.validate_intrinsic_properties()?;
+    let transaction = wallet.sign_transaction(transaction)
+        .map_err(|e| anyhow::anyhow!("Failed to create signed transaction: {}", e))?;
+
+    println!("Signed Key Rotation Details:");
+    println!("  Account: {}", transaction.sender_address());
+    println!("  Signed by: {}", wallet.public_key());
+    println!("  New authorized key: {}", new_key);
+    println!("  Nonce: {}", transaction.nonce.0);
+    println!("  Transaction ID: {}", transaction.id()?);
+    println!("\nOnce included, only the new key can sign for this account. Sign later transactions");
+    println!("with the new key's file and `--from {}`.", transaction.sender_address());
+
+    publish(&transaction, session)
+}
+
+fn handle_relay(tx_hex: &str, nonce: Option<u64>, keyfile_path: &Path, from: Option<Address>, validity: ValidityWindow, session: &Session) -> anyhow::Result<()> {
+    let wallet = load_wallet(keyfile_path, session.passphrase)
+        .map_err(|e| anyhow::anyhow!("Failed to load wallet from {}: {}", keyfile_path.display(), e))?;
+    let nonce = session.nonce(nonce, &signing_account(&wallet, from))?;
+
+    let inner = decode_transaction_hex(tx_hex)?;
+    inner.validate()
+        .map_err(|e| anyhow::anyhow!("Transaction to relay is not valid: {}", e))?;
+    let inner_sender = inner.sender_address();
+    let inner_nonce = inner.nonce;
+
+    let mut transaction = Transaction::with_kind(*wallet.public_key(), nonce, TransactionKind::Relayed(Box::new(inner)), Signature(vec![]));
+    if let Some(account) = from {
+        transaction = transaction.with_account(account);
+    }
+    let transaction = transaction.with_validity(validity);
+    transaction.validate_intrinsic_properties()?;
+    let transaction = wallet.sign_transaction(transaction)
+        .map_err(|e| anyhow::anyhow!("Failed to create signed transaction: {}", e))?;
+
+    println!("Signed Relayed Transaction Details:");
+    println!("  Relayer: {}", transaction.sender_address());
+    println!("  Relayer nonce: {}", transaction.nonce.0);
+    println!("  Relayed sender: {}", inner_sender);
+    println!("  Relayed nonce: {}", inner_nonce.0);
+    println!("  Kind: {}", transaction.kind);
+    println!("  Transaction ID: {}", transaction.id()?);
+
+    publish(&transaction, session)
+}
+
+/// Submits `transaction` to the node and waits until it is included, or prints it for
+/// broadcasting elsewhere when there is no node.
+fn publish(transaction: &Transaction, session: &Session) -> anyhow::Result<()> {
+    let Some(mut client) = session.connect()? else {
+        println!("\nSerialized Signed Transaction (hex for broadcast/storage):");
+        return print_serialized_transaction(transaction);
+    };
+    let sender = transaction.sender_address();
+    let account = client.get_balance(&sender)?;
+    let total = transaction.kind.total_amount().unwrap_or(u64::MAX);
+    let spendable = account.balance.saturating_sub(account.locked);
+    if total > spendable {
+        return Err(anyhow::anyhow!("Account {} can spend {}, but the transaction needs {}", sender, spendable, total));
+    }
+
+    let tx_id = client.submit_transaction(transaction)?.tx_id;
+    println!("\nSubmitted transaction {} at height {}. Waiting for it to be included...", tx_id, account.height);
+    let started = Instant::now();
+    while started.elapsed() < INCLUSION_TIMEOUT {
+        std::thread::sleep(INCLUSION_POLL_INTERVAL);
+        if let Some(included) = client.get_account_history(&sender, None)?.into_iter().find(|entry| entry.tx_id == tx_id) {
+            println!("Included in block {}", included.height);
+            return Ok(());
+        }
+    }
+    println!("Not included after {} seconds. It stays in the node's mempool; check `wallet history` later.", INCLUSION_TIMEOUT.as_secs());
+    Ok(())
+}
 
+fn handle_balance(address: &Address, session: &Session) -> anyhow::Result<()> {
+    let mut client = session.connect()?.ok_or_else(|| anyhow::anyhow!("`wallet balance` needs --node"))?;
+    let account = client.get_balance(address)?;
+    println!("Account {} at height {}:", account.address, account.height);
+    println!("  Balance: {}", account.balance);
+    if account.locked > 0 {
+        println!("  Locked by vesting: {}", account.locked);
+        println!("  Spendable: {}", account.balance.saturating_sub(account.locked));
+    }
+    println!("  Next nonce: {}", account.nonce);
+    Ok(())
+}
+
+fn handle_history(address: &Address, limit: usize, session: &Session) -> anyhow::Result<()> {
+    let mut client = session.connect()?.ok_or_else(|| anyhow::anyhow!("`wallet history` needs --node"))?;
+    let history = client.get_account_history(address, Some(limit))?;
+    if history.is_empty() {
+        println!("No committed transactions touch {}", address);
+        return Ok(());
+    }
+    println!("Transactions touching {}, newest first:", address);
+    for entry in history {
+        match entry.transaction {
+            Some(tx) => println!("  {:>8}  {}  {} from {} (nonce {})", entry.height, entry.tx_id, tx.kind, tx.sender_address(), tx.nonce.0),
+            None => println!("  {:>8}  {}  (block body pruned)", entry.height, entry.tx_id),
+        }
+    }
+    Ok(())
+}
+
+/// The account to look up: `address` if given, otherwise the account of the selected key.
+/// Only the public key is needed, so keystores are not unlocked.
+fn lookup_address(address: Option<Address>, key: &KeyArgs, wallet_dir: &Path) -> anyhow::Result<Address> {
+    if let Some(address) = address {
+        return Ok(address);
+    }
+    Ok(account_address(&read_public_key(key, wallet_dir)?))
+}
+
+/// The public key of the selected key, read without unlocking its keystore.
+fn read_public_key(key: &KeyArgs, wallet_dir: &Path) -> anyhow::Result<PublicKey> {
+    let keyfile_path = key.path(wallet_dir)?;
+    match KeyFile::read(&keyfile_path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", keyfile_path.display(), e))? {
+        KeyFile::Encrypted(keystore) => Ok(keystore.public_key()?),
+        KeyFile::Plain(signing_key) => Ok(PublicKey(signing_key.verifying_key())),
+    }
+}
+
+fn decode_transaction_hex(tx_hex: &str) -> anyhow::Result<Transaction> {
+    let bytes = hex::decode(tx_hex.trim().strip_prefix("0x").unwrap_or(tx_hex.trim()))
+        .map_err(|e| anyhow::anyhow!("Transaction is not valid hex: {}", e))?;
+    let (transaction, _): (Transaction, usize) = bincode::decode_from_slice(&bytes, bincode::config::standard())
+        .map_err(|e| anyhow::anyhow!("Failed to decode transaction: {}", e))?;
+    Ok(transaction)
+}
+
+fn encode_transaction_hex(transaction: &Transaction) -> anyhow::Result<String> {
+    let serialized_tx = bincode::encode_to_vec(transaction, bincode::config::standard())
+        .map_err(|e| anyhow::anyhow!("Failed to serialize transaction: {}", e))?;
+    Ok(hex::encode(serialized_tx))
+}
+
+fn print_serialized_transaction(transaction: &Transaction) -> anyhow::Result<()> {
+    println!("{}", encode_transaction_hex(transaction)?);
+    Ok(())
+}
+
+/// Transaction files hold the same serialized hex that `send` prints, so either can be
+/// passed where the other is expected. A path that does not exist is read as hex itself.
+fn read_transaction_file(path: &Path) -> anyhow::Result<Transaction> {
+    if !path.exists() {
+        return decode_transaction_hex(&path.to_string_lossy())
+            .map_err(|e| anyhow::anyhow!("{} is neither a transaction file nor transaction hex: {}", path.display(), e));
+    }
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    decode_transaction_hex(&contents).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+}
+
+fn write_transaction_file(path: &Path, transaction: &Transaction) -> anyhow::Result<()> {
+    std::fs::write(path, format!("{}\n", encode_transaction_hex(transaction)?))
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Shows what a transaction does and who signed it, for checking before signing or
+/// broadcasting.
+fn print_transaction_details(transaction: &Transaction) -> anyhow::Result<()> {
+    println!("  Transaction ID: {}", transaction.id()?);
+    println!("  Version: {}", transaction.version);
+    println!("  Sender: {}", transaction.sender_address());
+    if let Some(public_key) = transaction.sender.signing_key() {
+        println!("  Signing key: {}", public_key);
+    }
+    match &transaction.kind {
+        TransactionKind::Transfer(transfer) => {
+            println!("  Recipient: {}", transfer.recipient);
+            println!("  Amount: {}", transfer.amount);
+        }
+        TransactionKind::BatchTransfer(transfers) => {
+            println!("  Kind: {}", transaction.kind);
+            for transfer in transfers {
+                println!("    {} to {}", transfer.amount, transfer.recipient);
+            }
+        }
+        kind => println!("  Kind: {}", kind),
+    }
+    println!("  Nonce: {}", transaction.nonce.0);
+    if let Some(valid_after) = transaction.validity.valid_after_height {
+        println!("  Valid after height: {}", valid_after);
+    }
+    if let Some(valid_until) = transaction.validity.valid_until_height {
+        println!("  Valid until height: {}", valid_until);
+    }
+    if !transaction.memo.is_empty() {
+        println!("  Memo: {}", format_memo(&transaction.memo));
+    }
+    match &transaction.signature {
+        TxSignature::Multisig(_) => print_multisig_status(transaction),
+        TxSignature::Single(signature) if signature.0.is_empty() => println!("  Signature: none, sign with `wallet sign`"),
+        TxSignature::Single(_) => match transaction.validate() {
+            Ok(()) => println!("  Signature: valid"),
+            Err(e) => println!("  Signature: INVALID ({})", e),
+        },
+    }
+    Ok(())
+}
+
+/// Builds a transfer from `public_key`'s account, or from `from` if it rotated its authorized
+/// key to `public_key`, for signing elsewhere.
+fn build_unsigned_transfer(transfer: Transfer, nonce: Option<u64>, public_key: PublicKey, from: Option<Address>, validity: ValidityWindow, memo: Vec<u8>, session: &Session) -> anyhow::Result<Transaction> {
+    let nonce = session.nonce(nonce, &from.unwrap_or_else(|| account_address(&public_key)))?;
+    let mut transaction = Transaction::with_kind(public_key, nonce, TransactionKind::Transfer(transfer), Signature(vec![]));
+    if let Some(account) = from {
+        transaction = transaction.with_account(account);
+    }
+    let transaction = transaction
+        .with_validity(validity)
+        .with_memo(memo);
+    transaction.validate_intrinsic_properties()?;
+    Ok(transaction)
+}
+
+fn handle_build_unsigned(transaction: &Transaction, out: &Path) -> anyhow::Result<()> {
+    write_transaction_file(out, transaction)?;
+    println!("Unsigned transaction written to {}:", out.display());
+    print_transaction_details(transaction)?;
+    println!("\nSign it with `wallet sign {}` on the machine holding the key.", out.display());
+    Ok(())
+}
+
+fn handle_sign_file(file: &Path, keyfile_path: &Path, out: &Path, passphrase: &PassphraseArgs) -> anyhow::Result<()> {
+    let transaction = read_transaction_file(file)?;
+    println!("Transaction in {}:", file.display());
+    print_transaction_details(&transaction)?;
+
+    let wallet = load_wallet(keyfile_path, passphrase)
+        .map_err(|e| anyhow::anyhow!("Failed to load wallet from {}: {}", keyfile_path.display(), e))?;
+    let transaction = match transaction.sender {
+        TxSender::Multisig(_) => {
+            let mut transaction = transaction;
+            wallet.cosign(&mut transaction)?;
+            transaction
+        }
+        _ => wallet.sign_transaction(transaction)?,
+    };
+    write_transaction_file(out, &transaction)?;
+
+    println!("\nSigned with key {} and written to {}", wallet.public_key(), out.display());
+    match transaction.validate() {
+        Ok(()) => println!("Publish it with `wallet broadcast {}` on a networked machine.", out.display()),
+        Err(e) => println!("Not yet valid: {}", e),
+    }
+    Ok(())
+}
+
+async fn handle_broadcast(file: &Path, peers: &[Multiaddr], timeout: Duration) -> anyhow::Result<()> {
+    let transaction = read_transaction_file(file)?;
+    transaction.validate()
+        .map_err(|e| anyhow::anyhow!("Refusing to broadcast {}: {}", file.display(), e))?;
+    let tx_id = transaction.id()?;
+    println!("Broadcasting transaction {} from {}...", tx_id, transaction.sender_address());
+    let reached = networking::publish_transaction(peers, transaction, timeout).await?;
+    println!("Sent to {} peer(s). Check `wallet history` to see when it is included.", reached);
+    Ok(())
+}
+
+/// Prints how many of the required cosignatures a multisig transaction carries.
+fn print_multisig_status(transaction: &Transaction) {
+    if let (TxSender::Multisig(policy), TxSignature::Multisig(cosignatures)) = (&transaction.sender, &transaction.signature) {
+        println!("  Account: {}", policy);
+        println!("  Signatures: {} of {} required", cosignatures.len(), policy.threshold());
+    }
+    match transaction.validate() {
+        Ok(()) => println!("  Status: fully signed, ready to broadcast"),
+        Err(e) => println!("  Status: not yet valid ({})", e),
+    }
+}
+
+fn handle_multisig(action: &MultisigAction, wallet_dir: &Path, passphrase: &PassphraseArgs, legacy_hex_addresses: bool) -> anyhow::Result<()> {
+    match action {
+        MultisigAction::Address { threshold, public_keys } => {
+            let policy = MultisigPolicy::new(*threshold, public_keys.clone())?;
+            println!("Multisig account ({}-of-{}):", policy.threshold(), policy.public_keys().len());
+            println!("  Address: {}", policy.address());
+            for (index, key) in policy.public_keys().iter().enumerate() {
+                println!("  Key {}: {}", index, key);
+            }
+        }
+        MultisigAction::Create { threshold, public_keys, to, amount, nonce, validity } => {
+            let policy = MultisigPolicy::new(*threshold, public_keys.clone())?;
+            let kind = TransactionKind::Transfer(Transfer { recipient: to.resolve(legacy_hex_addresses)?, amount: *amount });
+            let transaction = Transaction::new_multisig(policy, Nonce(*nonce), kind).with_validity(validity.window());
+            transaction.validate_intrinsic_properties()?;
+            println!("Unsigned multisig transaction:");
+            println!("  Kind: {}", transaction.kind);
+            println!("  Transaction ID: {}", transaction.id()?);
+            print_multisig_status(&transaction);
+            println!("\nSerialized Transaction (hex, pass to `wallet multisig sign`):");
+            print_serialized_transaction(&transaction)?;
+        }
+        MultisigAction::Sign { tx, key } => {
+            let keyfile_path = key.path(wallet_dir)?;
+            let wallet = load_wallet(&keyfile_path, passphrase)
+                .map_err(|e| anyhow::anyhow!("Failed to load wallet from {}: {}", keyfile_path.display(), e))?;
+            let mut transaction = decode_transaction_hex(tx)?;
+            wallet.cosign(&mut transaction)?;
+            println!("Signed transaction {} with key {}", transaction.id()?, wallet.public_key());
+            print_multisig_status(&transaction);
+            println!("\nSerialized Transaction (hex):");
+            print_serialized_transaction(&transaction)?;
+        }
+        MultisigAction::Combine { txs } => {
+            let mut copies = txs.iter().map(|tx| decode_transaction_hex(tx));
+            let mut transaction = copies.next().ok_or_else(|| anyhow::anyhow!("No transactions to combine"))??;
+            for copy in copies {
+                transaction.combine(&copy?)?;
+            }
+            println!("Combined transaction {}", transaction.id()?);
+            print_multisig_status(&transaction);
+            println!("\nSerialized Transaction (hex):");
+            print_serialized_transaction(&transaction)?;
+        }
+    }
     Ok(())
 }
 
 /// Main entry point for wallet CLI commands
-pub fn run_wallet_cli(cli_args: WalletCliArgs) -> anyhow::Result<()> {
+pub async fn run_wallet_cli(cli_args: WalletCliArgs) -> anyhow::Result<()> {
+    let passphrase = &cli_args.passphrase;
+    let wallet_dir = cli_args.wallet_dir.clone().unwrap_or_else(WalletDir::default_path);
+    let session = &Session { passphrase, node: cli_args.node.as_deref() };
+    let legacy_hex = cli_args.legacy_hex_addresses;
+    let address = |arg: &AddressArg| arg.resolve(legacy_hex);
+    let optional_address = |arg: &Option<AddressArg>| arg.as_ref().map(address).transpose();
     match &cli_args.action {
-        WalletAction::Generate { keyfile } => {
-            handle_generate_wallet(keyfile)?;
+        WalletAction::Generate { keyfile, mnemonic: false, force, .. } => {
+            handle_generate_wallet(keyfile, *force, passphrase)?;
+        }
+        WalletAction::Generate { keyfile, mnemonic: true, words, force } => {
+            handle_generate_mnemonic(*words, keyfile, *force, passphrase)?;
+        }
+        WalletAction::Recover { index, keyfile, force, mnemonic } => {
+            let keyfile_path = keyfile.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_KEY_FILE));
+            // Checked before the recovery phrase is asked for
+            check_keyfile_free(&keyfile_path, *force)?;
+            handle_derive(&mnemonic.read()?, *index, Some(&keyfile_path), passphrase)?;
+        }
+        WalletAction::Derive { index, keyfile, force, mnemonic } => {
+            if let Some(keyfile_path) = keyfile {
+                check_keyfile_free(keyfile_path, *force)?;
+            }
+            handle_derive(&mnemonic.read()?, *index, keyfile.as_deref(), passphrase)?;
+        }
+        WalletAction::Import { raw_key, keyfile } => {
+            handle_import_wallet(raw_key, keyfile, passphrase)?;
+        }
+        WalletAction::Show { key } => {
+            handle_show_wallet(&key.path(&wallet_dir)?, passphrase)?;
+        }
+        WalletAction::Send { to, amount, nonce, key, from, validity, memo } => {
+            let transfer = Transfer { recipient: address(to)?, amount: *amount };
+            handle_send_transaction(transfer, *nonce, &key.path(&wallet_dir)?, optional_address(from)?, validity.window(), memo.bytes()?, session)?;
+        }
+        WalletAction::SendBatch { csv, nonce, key, from, validity } => {
+            handle_send_batch(csv, *nonce, &key.path(&wallet_dir)?, optional_address(from)?, validity.window(), legacy_hex, session)?;
+        }
+        WalletAction::RotateKey { new_key, nonce, key, from, validity } => {
+            handle_rotate_key(new_key, *nonce, &key.path(&wallet_dir)?, optional_address(from)?, validity.window(), session)?;
+        }
+        WalletAction::Relay { tx, nonce, key, from, validity } => {
+            handle_relay(tx, *nonce, &key.path(&wallet_dir)?, optional_address(from)?, validity.window(), session)?;
+        }
+        WalletAction::Balance { key, address } => {
+            handle_balance(&lookup_address(optional_address(address)?, key, &wallet_dir)?, session)?;
         }
-        WalletAction::Show { keyfile } => {
-            handle_show_wallet(keyfile)?;
+        WalletAction::History { key, address, limit } => {
+            handle_history(&lookup_address(optional_address(address)?, key, &wallet_dir)?, *limit, session)?;
         }
-        WalletAction::Send { to, amount, nonce, keyfile } => {
-            handle_send_transaction(to, *amount, *nonce, keyfile)?;
+        WalletAction::List => {
+            handle_list_accounts(&wallet_dir)?;
+        }
+        WalletAction::Add { name, label, import } => {
+            handle_add_account(&wallet_dir, name, label.clone(), import.as_deref(), passphrase)?;
+        }
+        WalletAction::Rename { name, new_name } => {
+            WalletDir::open(&wallet_dir)?.rename(name, new_name)?;
+            println!("Renamed account `{}` to `{}`", name, new_name);
+        }
+        WalletAction::Remove { name } => {
+            let removed_path = WalletDir::open(&wallet_dir)?.remove(name)?;
+            println!("Removed account `{}`. Its key file was moved to {}", name, removed_path.display());
+        }
+        WalletAction::SetDefault { name } => {
+            WalletDir::open(&wallet_dir)?.set_default(name)?;
+            println!("`{}` is now the default account", name);
+        }
+        WalletAction::BuildUnsigned { to, amount, nonce, public_key, key, from, validity, memo, out } => {
+            let public_key = match public_key {
+                Some(public_key) => parse_public_key(public_key).map_err(|e| anyhow::anyhow!(e))?,
+                None => read_public_key(key, &wallet_dir)?,
+            };
+            let transfer = Transfer { recipient: address(to)?, amount: *amount };
+            let transaction = build_unsigned_transfer(transfer, *nonce, public_key, optional_address(from)?, validity.window(), memo.bytes()?, session)?;
+            handle_build_unsigned(&transaction, out)?;
+        }
+        WalletAction::Sign { file, key, out } => {
+            handle_sign_file(file, &key.path(&wallet_dir)?, out.as_deref().unwrap_or(file), passphrase)?;
+        }
+        WalletAction::Broadcast { file, peers, timeout } => {
+            handle_broadcast(file, peers, Duration::from_secs(*timeout)).await?;
+        }
+        WalletAction::Inspect { file } => {
+            let transaction = read_transaction_file(file)?;
+            println!("Transaction in {}:", file.display());
+            print_transaction_details(&transaction)?;
+        }
+        WalletAction::Multisig { action } => {
+            handle_multisig(action, &wallet_dir, passphrase, legacy_hex)?;
         }
     }
     Ok(())
-} 
\ No newline at end of file
+} 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_address_matches_wallet_show() {
+        let dir = tempfile::tempdir().unwrap();
+        let wallet = Wallet::new();
+        let keystore = Keystore::encrypt(wallet.get_signing_key(), "passphrase", ScryptParams { log_n: 4, r: 8, p: 1 }).unwrap();
+        let mut wallets = WalletDir::open(dir.path()).unwrap();
+        wallets.add("savings", None, &keystore).unwrap();
+
+        let shown = wallet_details(&wallet);
+        let shown_address = shown[0].strip_prefix("Address: ").unwrap();
+        let listing = account_listing(&wallets);
+        let listed_address = listing[0].split_whitespace().nth(2).unwrap();
+        assert_eq!(listed_address, shown_address);
+        assert_eq!(listed_address, account_address(wallet.public_key()).to_string());
+    }
+
+    #[test]
+    fn key_files_are_not_replaced_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let keyfile = dir.path().join("wallet.key");
+        std::fs::write(&keyfile, b"existing key").unwrap();
+        let passphrase_file = dir.path().join("passphrase");
+        std::fs::write(&passphrase_file, "passphrase\n").unwrap();
+        let passphrase = PassphraseArgs { passphrase_file: Some(passphrase_file) };
+
+        let error = handle_generate_mnemonic(12, &Some(keyfile.clone()), false, &passphrase).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        assert!(handle_generate_wallet(&Some(keyfile.clone()), false, &passphrase).is_err());
+        assert_eq!(std::fs::read(&keyfile).unwrap(), b"existing key");
+
+        handle_generate_mnemonic(12, &Some(keyfile.clone()), true, &passphrase).unwrap();
+        assert!(Wallet::load_encrypted(&keyfile, "passphrase").is_ok());
+    }
+}
@@ -42,7 +42,7 @@ impl ConsensusEngine {
         for (i, pk) in validators.iter().enumerate() {
             tracing::info!("  Validator {} public key bytes: {}", i, hex::encode(pk.0.to_bytes()));
             let address = address_from_public_key(pk);
-            tracing::info!("  Validator {}: address {}", i, hex::encode(address.0));
+            tracing::info!("  Validator {}: address {}", i, address);
         }
         Self { validators }
     }
@@ -55,7 +55,7 @@ impl ConsensusEngine {
         let proposer_index = (height.0 as usize) % self.validators.len();
         let proposer_pk = &self.validators[proposer_index];
         let proposer_address = address_from_public_key(proposer_pk);
-        tracing::info!("get_proposer for height {}: index {}, address {}", height.0, proposer_index, hex::encode(proposer_address.0));
+        tracing::info!("get_proposer for height {}: index {}, address {}", height.0, proposer_index, proposer_address);
         Ok(proposer_pk)
     }
 
@@ -99,20 +99,26 @@ impl ConsensusEngine {
 
     /// Validates the entire block according to consensus rules.
     pub fn validate_block(&self, block: &Block) -> Result<(), ConsensusError> {
+        self.validate_header(&block.header)
+    }
+
+    /// Validates a block header's proposer and signature.
+    /// Used directly when only the header is available, e.g. for snapshot manifests.
+    pub fn validate_header(&self, header: &BlockHeader) -> Result<(), ConsensusError> {
         // 1. Validate the proposer
-        self.validate_proposer(&block.header)?;
+        self.validate_proposer(header)?;
 
         // 2. Verify the block signature
         let proposer_pk = self
-            .get_proposer_pk_for_address(&block.header.validator)
+            .get_proposer_pk_for_address(&header.validator)
             .ok_or(ConsensusError::ProposerNotInValidatorSet)?;
-        let header_hash = block.header.calculate_hash()?;
+        let header_hash = header.calculate_hash()?;
         
-        // The public key of the validator is in block.header.validator
-        // The signature is in block.header.signature
+        // The public key of the validator is in header.validator
+        // The signature is in header.signature
         // The data that was signed is the header_hash
         
-        let signature_bytes: &[u8; 64] = block.header.signature.0.as_slice().try_into()
+        let signature_bytes: &[u8; 64] = header.signature.0.as_slice().try_into()
             .map_err(|_| ConsensusError::InvalidSignatureFormat)?;
 
         let dalek_signature = ed25519_dalek::Signature::from_bytes(signature_bytes);
@@ -290,7 +296,7 @@ mod tests {
                 timestamp: Timestamp(1234567890),
                 tx_root: Hash([1u8; 32]),
                 validator: address_from_public_key(other_wallet.public_key()), // block signed by other wallet
-                signature: transaction.signature.clone(),
+                signature: sender_wallet.sign(transaction.id().unwrap().as_ref()).unwrap(),
             },
             transactions: vec![transaction],
         };
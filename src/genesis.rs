@@ -0,0 +1,301 @@
+use crate::block::{calculate_merkle_root, Block, BlockHeader};
+use crate::state_machine::{Account, WorldState};
+use crate::types::{Address, BlockHeight, Hash, Nonce, PublicKey, Signature, Timestamp};
+use crate::vesting::{VestingError, VestingSchedule};
+use crate::wallet::address_from_public_key as validator_address;
+use bincode::Encode;
+use serde::de::{Deserializer, Error as _, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GenesisError {
+    #[error("Genesis file is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Genesis has no validators")]
+    NoValidators,
+    #[error("Invalid validator public key `{key}`: {reason}")]
+    InvalidValidatorKey { key: String, reason: String },
+    #[error("Validator {0} is listed more than once")]
+    DuplicateValidator(String),
+    #[error("Invalid address `{address}`: {reason}")]
+    InvalidAddress { address: String, reason: String },
+    #[error("Account {0} is listed more than once, e.g. in both hex and rc1 form")]
+    DuplicateAccount(Address),
+    #[error("Vesting schedule for {0} has no initial balance")]
+    VestingWithoutBalance(String),
+    #[error("Invalid vesting schedule for {address}: {source}")]
+    InvalidVesting { address: String, source: VestingError },
+    #[error("Initial balances add up to more than the maximum supply of {}", u64::MAX)]
+    SupplyOverflow,
+    #[error("Failed to encode genesis block: {0}")]
+    Encode(String),
+}
+
+/// A genesis file, as written by `genesis create` and read by nodes. Nothing is checked
+/// until `validate`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisData {
+    /// Hex public keys of the initial validators. The first one is the genesis proposer
+    pub validators: Vec<String>,
+    /// Address -> balance
+    #[serde(deserialize_with = "unique_keys")]
+    pub initial_balances: BTreeMap<String, u64>,
+    /// Address -> schedule locking part of that address's initial balance
+    #[serde(default, deserialize_with = "unique_keys", skip_serializing_if = "BTreeMap::is_empty")]
+    pub vesting: BTreeMap<String, VestingSchedule>,
+    /// Unix timestamp of the genesis block
+    pub timestamp: u64,
+    pub message: String,
+}
+
+impl GenesisData {
+    /// A chain of one validator that holds the whole supply, for a node started without a
+    /// genesis file. Every such node has its own chain.
+    pub fn development(validator: &PublicKey, timestamp: u64) -> Self {
+        let account = crate::types::address_from_public_key(validator);
+        GenesisData {
+            validators: vec![validator.to_string()],
+            initial_balances: BTreeMap::from([(account.to_string(), 1_000_000)]),
+            vesting: BTreeMap::new(),
+            timestamp,
+            message: "RustChain Genesis Block".to_string(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, GenesisError> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Checks every key, address and vesting schedule, and that the total supply fits in a
+    /// `u64`. `legacy_hex_addresses` also accepts addresses as unchecksummed hex.
+    pub fn validate(&self, legacy_hex_addresses: bool) -> Result<Genesis, GenesisError> {
+        if self.validators.is_empty() {
+            return Err(GenesisError::NoValidators);
+        }
+        let mut validators: Vec<PublicKey> = Vec::with_capacity(self.validators.len());
+        for key in &self.validators {
+            let public_key = parse_validator_key(key)?;
+            if validators.contains(&public_key) {
+                return Err(GenesisError::DuplicateValidator(public_key.to_string()));
+            }
+            validators.push(public_key);
+        }
+
+        let parse_address = |address: &String| {
+            Address::parse(address, legacy_hex_addresses)
+                .map_err(|e| GenesisError::InvalidAddress { address: address.clone(), reason: e.to_string() })
+        };
+        let mut accounts = BTreeMap::new();
+        let mut total_supply: u64 = 0;
+        for (address_text, balance) in &self.initial_balances {
+            let address = parse_address(address_text)?;
+            if accounts.contains_key(&address) {
+                return Err(GenesisError::DuplicateAccount(address));
+            }
+            total_supply = total_supply.checked_add(*balance).ok_or(GenesisError::SupplyOverflow)?;
+            accounts.insert(address, Account { balance: *balance, nonce: Nonce(0), ..Account::default() });
+        }
+        for (address_text, schedule) in &self.vesting {
+            let account = accounts
+                .get_mut(&parse_address(address_text)?)
+                .ok_or_else(|| GenesisError::VestingWithoutBalance(address_text.clone()))?;
+            if account.vesting.is_some() {
+                return Err(GenesisError::DuplicateAccount(parse_address(address_text)?));
+            }
+            schedule
+                .check(account.balance)
+                .map_err(|source| GenesisError::InvalidVesting { address: address_text.clone(), source })?;
+            account.vesting = Some(*schedule);
+        }
+
+        Ok(Genesis { validators, accounts, total_supply, timestamp: self.timestamp, message: self.message.clone() })
+    }
+}
+
+/// A validated genesis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Genesis {
+    pub validators: Vec<PublicKey>,
+    pub accounts: BTreeMap<Address, Account>,
+    pub total_supply: u64,
+    pub timestamp: u64,
+    pub message: String,
+}
+
+/// Everything a genesis defines, in a canonical encoding for `Genesis::content_hash`.
+#[derive(Encode)]
+struct GenesisContent {
+    validators: Vec<[u8; 32]>,
+    accounts: Vec<(Address, u64, Option<VestingSchedule>)>,
+    timestamp: u64,
+    message: String,
+}
+
+impl Genesis {
+    /// The unsigned block at height 0, proposed by the first validator.
+    pub fn block(&self) -> Result<Block, GenesisError> {
+        let transactions = Vec::new();
+        let tx_root = calculate_merkle_root(&transactions).map_err(|e| GenesisError::Encode(e.to_string()))?;
+        let header = BlockHeader {
+            parent_hash: Hash([0u8; 32]),
+            block_number: BlockHeight(0),
+            timestamp: Timestamp(self.timestamp),
+            tx_root,
+            validator: validator_address(&self.validators[0]),
+            signature: Signature(vec![0u8; 64]),
+        };
+        Ok(Block { header, transactions })
+    }
+
+    /// The hash of the genesis block, which every block of the chain builds on. It covers
+    /// only the timestamp and the first validator; compare `content_hash` as well to be sure
+    /// two nodes start from the same balances.
+    pub fn block_hash(&self) -> Result<Hash, GenesisError> {
+        self.block()?.header.calculate_hash().map_err(|e| GenesisError::Encode(e.to_string()))
+    }
+
+    /// A hash over the validators, balances, vesting schedules, timestamp and message. It
+    /// does not depend on how the file is formatted or in which form addresses are written.
+    pub fn content_hash(&self) -> Result<Hash, GenesisError> {
+        let content = GenesisContent {
+            validators: self.validators.iter().map(|public_key| public_key.0.to_bytes()).collect(),
+            accounts: self.accounts.iter().map(|(address, account)| (*address, account.balance, account.vesting)).collect(),
+            timestamp: self.timestamp,
+            message: self.message.clone(),
+        };
+        let encoded = bincode::encode_to_vec(&content, bincode::config::standard()).map_err(|e| GenesisError::Encode(e.to_string()))?;
+        Ok(Hash(Sha256::digest(encoded).into()))
+    }
+
+    pub fn world_state(&self) -> WorldState {
+        self.accounts.iter().map(|(address, account)| (*address, account.clone())).collect()
+    }
+}
+
+fn parse_validator_key(key: &str) -> Result<PublicKey, GenesisError> {
+    let invalid = |reason: String| GenesisError::InvalidValidatorKey { key: key.to_string(), reason };
+    let bytes = hex::decode(key).map_err(|e| invalid(e.to_string()))?;
+    let bytes: [u8; 32] = bytes.try_into().map_err(|bytes: Vec<u8>| invalid(format!("expected 32 bytes, got {}", bytes.len())))?;
+    ed25519_dalek::VerifyingKey::from_bytes(&bytes).map(PublicKey).map_err(|e| invalid(e.to_string()))
+}
+
+/// Deserializes a map, failing on a repeated key instead of keeping the last value as serde
+/// does by default, so an allocation cannot be silently overwritten.
+fn unique_keys<'de, D, V>(deserializer: D) -> Result<BTreeMap<String, V>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    struct UniqueKeys<V>(PhantomData<V>);
+
+    impl<'de, V: Deserialize<'de>> Visitor<'de> for UniqueKeys<V> {
+        type Value = BTreeMap<String, V>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a map with unique keys")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut entries = BTreeMap::new();
+            while let Some((key, value)) = map.next_entry::<String, V>()? {
+                if entries.contains_key(&key) {
+                    return Err(A::Error::custom(format!("`{}` is listed more than once", key)));
+                }
+                entries.insert(key, value);
+            }
+            Ok(entries)
+        }
+    }
+
+    deserializer.deserialize_map(UniqueKeys(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn validator(seed: u8) -> PublicKey {
+        PublicKey(SigningKey::from_bytes(&[seed; 32]).verifying_key())
+    }
+
+    fn genesis_json(balances: &str) -> String {
+        format!(
+            r#"{{"validators": ["{}", "{}"], "initial_balances": {{{}}}, "timestamp": 1640995200, "message": "test"}}"#,
+            validator(1),
+            validator(2),
+            balances
+        )
+    }
+
+    #[test]
+    fn test_validate_genesis() {
+        let alice = Address([7; 32]);
+        let data: GenesisData = serde_json::from_str(&genesis_json(&format!(r#""{}": 600, "{}": 400"#, alice, Address([8; 32])))).unwrap();
+        let genesis = data.validate(false).unwrap();
+        assert_eq!(genesis.validators, vec![validator(1), validator(2)]);
+        assert_eq!(genesis.total_supply, 1000);
+        assert_eq!(genesis.accounts[&alice].balance, 600);
+        assert_eq!(genesis.block().unwrap().header.validator, validator_address(&validator(1)));
+
+        // The content hash sees balances, the block hash does not
+        let mut richer = data.clone();
+        richer.initial_balances.insert(alice.to_string(), 601);
+        let richer = richer.validate(false).unwrap();
+        assert_eq!(richer.block_hash().unwrap(), genesis.block_hash().unwrap());
+        assert_ne!(richer.content_hash().unwrap(), genesis.content_hash().unwrap());
+
+        // Writing an address in hex instead changes nothing
+        let mut hex_form = data.clone();
+        hex_form.initial_balances.remove(&alice.to_string());
+        hex_form.initial_balances.insert(hex::encode(alice.0), 600);
+        assert!(matches!(hex_form.validate(false), Err(GenesisError::InvalidAddress { .. })));
+        assert_eq!(hex_form.validate(true).unwrap().content_hash().unwrap(), genesis.content_hash().unwrap());
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_genesis() {
+        let alice = Address([7; 32]);
+        let data: GenesisData = serde_json::from_str(&genesis_json(&format!(r#""{}": 600"#, alice))).unwrap();
+
+        let duplicate_key = genesis_json(&format!(r#""{}": 600, "{}": 700"#, alice, alice));
+        assert!(serde_json::from_str::<GenesisData>(&duplicate_key).unwrap_err().to_string().contains("more than once"));
+
+        let mut both_forms = data.clone();
+        both_forms.initial_balances.insert(hex::encode(alice.0), 1);
+        assert!(matches!(both_forms.validate(true), Err(GenesisError::DuplicateAccount(address)) if address == alice));
+
+        let mut overflow = data.clone();
+        overflow.initial_balances.insert(Address([8; 32]).to_string(), u64::MAX);
+        assert!(matches!(overflow.validate(false), Err(GenesisError::SupplyOverflow)));
+
+        let mut no_validators = data.clone();
+        no_validators.validators.clear();
+        assert!(matches!(no_validators.validate(false), Err(GenesisError::NoValidators)));
+
+        let mut repeated_validator = data.clone();
+        repeated_validator.validators.push(validator(1).to_string());
+        assert!(matches!(repeated_validator.validate(false), Err(GenesisError::DuplicateValidator(_))));
+
+        let mut short_key = data.clone();
+        short_key.validators[1] = "68e8dfa9".to_string();
+        assert!(matches!(short_key.validate(false), Err(GenesisError::InvalidValidatorKey { .. })));
+
+        let mut unfunded_vesting = data.clone();
+        let schedule = VestingSchedule::Cliff { amount: 100, unlock_height: BlockHeight(10) };
+        unfunded_vesting.vesting.insert(Address([8; 32]).to_string(), schedule);
+        assert!(matches!(unfunded_vesting.validate(false), Err(GenesisError::VestingWithoutBalance(_))));
+
+        let mut over_vested = data.clone();
+        over_vested.vesting.insert(alice.to_string(), VestingSchedule::Cliff { amount: 601, unlock_height: BlockHeight(10) });
+        assert!(matches!(over_vested.validate(false), Err(GenesisError::InvalidVesting { .. })));
+    }
+}
@@ -0,0 +1,159 @@
+use bip39::Mnemonic;
+use ed25519_dalek::SigningKey;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha512;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// RustChain has no registered SLIP-0044 coin type, so account paths use 1, the type
+/// reserved for testnets of all coins.
+pub const COIN_TYPE: u32 = 1;
+
+/// Indices at or above this are hardened. SLIP-0010 only defines hardened derivation for
+/// Ed25519, so every path component is hardened.
+const HARDENED: u32 = 0x8000_0000;
+
+/// Key of the HMAC that turns a seed into the master key.
+const ED25519_SEED_KEY: &[u8] = b"ed25519 seed";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HdWalletError {
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+    #[error("A mnemonic has 12, 15, 18, 21 or 24 words, not {0}")]
+    InvalidWordCount(usize),
+    #[error("Invalid derivation path `{0}`: expected e.g. m/44'/1'/0' with hardened components only")]
+    InvalidPath(String),
+    #[error("Index {0} is out of range, hardened indices must be below 2^31")]
+    IndexOutOfRange(u32),
+}
+
+/// A SLIP-0010 derivation path for Ed25519, e.g. `m/44'/1'/0'`. Indices are stored without
+/// the hardened bit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// The path of account `index`: `m/44'/COIN_TYPE'/index'`.
+    pub fn for_account(index: u32) -> Result<Self, HdWalletError> {
+        if index >= HARDENED {
+            return Err(HdWalletError::IndexOutOfRange(index));
+        }
+        Ok(DerivationPath(vec![44, COIN_TYPE, index]))
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = HdWalletError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || HdWalletError::InvalidPath(s.to_string());
+        let mut components = s.split('/');
+        if components.next() != Some("m") {
+            return Err(invalid());
+        }
+        components
+            .map(|component| {
+                let index = component.strip_suffix('\'').or_else(|| component.strip_suffix('h')).ok_or_else(invalid)?;
+                index.parse::<u32>().ok().filter(|index| *index < HARDENED).ok_or_else(invalid)
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generates a fresh English BIP39 mnemonic of `word_count` words.
+pub fn generate_mnemonic(word_count: usize) -> Result<Mnemonic, HdWalletError> {
+    if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+        return Err(HdWalletError::InvalidWordCount(word_count));
+    }
+    // Every three words carry 32 bits of entropy and one checksum bit per word
+    let mut entropy = vec![0u8; word_count / 3 * 4];
+    OsRng.fill_bytes(&mut entropy);
+    Mnemonic::from_entropy(&entropy).map_err(|e| HdWalletError::InvalidMnemonic(e.to_string()))
+}
+
+/// Parses an English BIP39 mnemonic, checking its checksum. Extra whitespace is ignored.
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, HdWalletError> {
+    let normalized = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    Mnemonic::parse(normalized).map_err(|e| HdWalletError::InvalidMnemonic(e.to_string()))
+}
+
+/// Derives the Ed25519 key at `path` from a BIP39 seed, following SLIP-0010.
+pub fn derive_signing_key(seed: &[u8], path: &DerivationPath) -> SigningKey {
+    let (mut key, mut chain_code) = split(hmac_sha512(ED25519_SEED_KEY, &[seed]));
+    for index in &path.0 {
+        let hardened = (index | HARDENED).to_be_bytes();
+        (key, chain_code) = split(hmac_sha512(&chain_code, &[&[0], &key, &hardened]));
+    }
+    SigningKey::from_bytes(&key)
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Splits an HMAC output into the key (left half) and the chain code (right half).
+fn split(output: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut key = [0u8; 32];
+    let mut chain_code = [0u8; 32];
+    key.copy_from_slice(&output[..32]);
+    chain_code.copy_from_slice(&output[32..]);
+    (key, chain_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slip10_ed25519_vectors() {
+        // Test vector 1 from SLIP-0010
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = derive_signing_key(&seed, &"m".parse().unwrap());
+        assert_eq!(hex::encode(master.to_bytes()), "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+        let child = derive_signing_key(&seed, &"m/0'".parse().unwrap());
+        assert_eq!(hex::encode(child.to_bytes()), "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3");
+        let deep = derive_signing_key(&seed, &"m/0'/1'/2'/2'/1000000000'".parse().unwrap());
+        assert_eq!(hex::encode(deep.to_bytes()), "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793");
+
+        assert_eq!(DerivationPath::for_account(3).unwrap().to_string(), "m/44'/1'/3'");
+        assert!("m/44'/1".parse::<DerivationPath>().is_err(), "unhardened components are not defined for Ed25519");
+        assert_eq!(DerivationPath::for_account(HARDENED), Err(HdWalletError::IndexOutOfRange(HARDENED)));
+    }
+
+    #[test]
+    fn test_mnemonic_generation_and_recovery() {
+        let mnemonic = generate_mnemonic(24).unwrap();
+        assert_eq!(mnemonic.word_count(), 24);
+        let recovered = parse_mnemonic(&format!("  {}\n", mnemonic)).unwrap();
+        assert_eq!(recovered.to_seed(""), mnemonic.to_seed(""));
+        assert_eq!(generate_mnemonic(13).unwrap_err(), HdWalletError::InvalidWordCount(13));
+
+        // Seed from the BIP39 reference vectors
+        let abandon = parse_mnemonic("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").unwrap();
+        assert_eq!(
+            hex::encode(abandon.to_seed("TREZOR")),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        let bad_checksum = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(matches!(parse_mnemonic(bad_checksum), Err(HdWalletError::InvalidMnemonic(_))));
+    }
+}
@@ -0,0 +1,53 @@
+use crate::types::{Address, BlockHeight, Hash, Nonce};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Domain separator for escrow addresses, so they can never collide with the address of a
+/// public key or a multisig policy.
+const HTLC_ADDRESS_DOMAIN: &[u8] = b"rustchain/htlc/v1";
+
+/// Length of a claim preimage. Swap counterparts on other ledgers commonly require exactly
+/// 32 bytes, and fixing the length here rules out a preimage that opens the lock on one
+/// chain but is rejected on the other.
+pub const PREIMAGE_LEN: usize = 32;
+
+/// The terms of a hash-time-locked escrow. Until `timelock` the funds go to `recipient`
+/// for the SHA-256 preimage of `hashlock`; from `timelock` on they can only be refunded to
+/// `refund_address`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct HashTimeLock {
+    pub hashlock: Hash,
+    pub timelock: BlockHeight,
+    pub recipient: Address,
+    pub refund_address: Address,
+}
+
+impl HashTimeLock {
+    /// The escrow account holding the funds locked by `sender`'s transaction with `nonce`:
+    /// `sha256(domain || sender || nonce)`. Nonces are never reused, so every lock gets a
+    /// fresh account.
+    pub fn lock_address(sender: &Address, nonce: Nonce) -> Address {
+        let mut hasher = Sha256::new();
+        hasher.update(HTLC_ADDRESS_DOMAIN);
+        hasher.update(sender.0);
+        hasher.update(nonce.0.to_be_bytes());
+        Address(hasher.finalize().into())
+    }
+
+    /// Whether a block at `height` falls after the claim period, when only refunds are
+    /// allowed.
+    pub fn is_expired(&self, height: BlockHeight) -> bool {
+        height >= self.timelock
+    }
+
+    /// Whether `preimage` hashes to the lock's `hashlock`.
+    pub fn opens_with(&self, preimage: &[u8; PREIMAGE_LEN]) -> bool {
+        hashlock_of(preimage) == self.hashlock
+    }
+}
+
+/// The hashlock committing to `preimage`, as the party that picked the secret publishes it.
+pub fn hashlock_of(preimage: &[u8; PREIMAGE_LEN]) -> Hash {
+    Hash(Sha256::digest(preimage).into())
+}
@@ -0,0 +1,250 @@
+use crate::types::PublicKey;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce as AeadNonce};
+use ed25519_dalek::{SigningKey, SECRET_KEY_LENGTH};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use thiserror::Error;
+
+/// Version of the keystore container written by this build.
+pub const KEYSTORE_VERSION: u32 = 1;
+
+/// Upper bound on `log_n` accepted from a keystore file. 2^20 rounds with `r = 8` already
+/// needs 1 GiB of memory, so anything above is more likely a crafted file than a real one.
+const MAX_LOG_N: u8 = 20;
+
+const SALT_LEN: usize = 32;
+const AEAD_NONCE_LEN: usize = 12;
+const AEAD_KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("Unsupported keystore version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid keystore field `{field}`: {reason}")]
+    InvalidField { field: &'static str, reason: String },
+    #[error("Invalid scrypt parameters: {0}")]
+    InvalidKdfParams(String),
+    #[error("Wrong passphrase, or the keystore has been modified")]
+    WrongPassphrase,
+    #[error("Decrypted key does not belong to public key {0}")]
+    PublicKeyMismatch(String),
+    #[error("Keystore is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Cost parameters for scrypt. The defaults take about a tenth of a second and 32 MiB on a
+/// current machine, which is cheap for a user unlocking a wallet but expensive for anyone
+/// guessing passphrases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    fn default() -> Self {
+        ScryptParams { log_n: 15, r: 8, p: 1 }
+    }
+}
+
+/// How the encryption key is derived from the passphrase.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum Kdf {
+    Scrypt { log_n: u8, r: u32, p: u32, salt: String },
+}
+
+/// How the secret key is encrypted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "name")]
+pub enum Cipher {
+    #[serde(rename = "chacha20-poly1305")]
+    ChaCha20Poly1305 { nonce: String },
+}
+
+/// A passphrase-encrypted Ed25519 secret key, stored as JSON. Binary fields are hex. The
+/// public key stays readable without the passphrase and is authenticated as associated
+/// data, so it cannot be swapped for another key's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub public_key: String,
+    pub kdf: Kdf,
+    pub cipher: Cipher,
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    /// Encrypts `signing_key` under a key derived from `passphrase` with a fresh salt.
+    pub fn encrypt(signing_key: &SigningKey, passphrase: &str, params: ScryptParams) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; AEAD_NONCE_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let public_key = signing_key.verifying_key();
+        let key = derive_key(passphrase, &salt, params)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(AeadNonce::from_slice(&nonce), Payload { msg: &signing_key.to_bytes(), aad: public_key.as_bytes() })
+            .map_err(|_| KeystoreError::InvalidField { field: "ciphertext", reason: "encryption failed".to_string() })?;
+
+        Ok(Keystore {
+            version: KEYSTORE_VERSION,
+            public_key: hex::encode(public_key.as_bytes()),
+            kdf: Kdf::Scrypt { log_n: params.log_n, r: params.r, p: params.p, salt: hex::encode(salt) },
+            cipher: Cipher::ChaCha20Poly1305 { nonce: hex::encode(nonce) },
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Recovers the secret key. A wrong passphrase and a tampered file look the same.
+    pub fn decrypt(&self, passphrase: &str) -> Result<SigningKey, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+        let public_key = self.public_key()?;
+        let Kdf::Scrypt { log_n, r, p, salt } = &self.kdf;
+        let Cipher::ChaCha20Poly1305 { nonce } = &self.cipher;
+        let salt = decode_hex("kdf.salt", salt)?;
+        let nonce: [u8; AEAD_NONCE_LEN] = decode_array("cipher.nonce", nonce)?;
+        let ciphertext = decode_hex("ciphertext", &self.ciphertext)?;
+
+        let key = derive_key(passphrase, &salt, ScryptParams { log_n: *log_n, r: *r, p: *p })?;
+        let secret = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(AeadNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: public_key.0.as_bytes() })
+            .map_err(|_| KeystoreError::WrongPassphrase)?;
+        let secret: [u8; SECRET_KEY_LENGTH] = secret
+            .try_into()
+            .map_err(|_| KeystoreError::InvalidField { field: "ciphertext", reason: "wrong secret key length".to_string() })?;
+
+        let signing_key = SigningKey::from_bytes(&secret);
+        if signing_key.verifying_key() != public_key.0 {
+            return Err(KeystoreError::PublicKeyMismatch(self.public_key.clone()));
+        }
+        Ok(signing_key)
+    }
+
+    /// The public key of the encrypted secret, available without the passphrase.
+    pub fn public_key(&self) -> Result<PublicKey, KeystoreError> {
+        let bytes: [u8; 32] = decode_array("public_key", &self.public_key)?;
+        ed25519_dalek::VerifyingKey::from_bytes(&bytes)
+            .map(PublicKey)
+            .map_err(|e| KeystoreError::InvalidField { field: "public_key", reason: e.to_string() })
+    }
+
+    /// Writes the keystore as JSON, readable by the owner only. The file is replaced
+    /// atomically, so an interrupted write never leaves a half-written key behind.
+    pub fn save(&self, path: &Path) -> Result<(), KeystoreError> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// The contents of a key file: a keystore, or the raw 32-byte secret written by earlier
+/// versions and still used for validator keys.
+pub enum KeyFile {
+    Plain(SigningKey),
+    Encrypted(Keystore),
+}
+
+impl KeyFile {
+    pub fn read(path: &Path) -> Result<Self, KeystoreError> {
+        let bytes = fs::read(path)?;
+        // A JSON keystore is never exactly as long as a raw secret
+        match <[u8; SECRET_KEY_LENGTH]>::try_from(bytes.as_slice()) {
+            Ok(secret) => Ok(KeyFile::Plain(SigningKey::from_bytes(&secret))),
+            Err(_) => Ok(KeyFile::Encrypted(serde_json::from_slice(&bytes)?)),
+        }
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: ScryptParams) -> Result<[u8; AEAD_KEY_LEN], KeystoreError> {
+    if params.log_n > MAX_LOG_N {
+        return Err(KeystoreError::InvalidKdfParams(format!("log_n {} is above the limit of {}", params.log_n, MAX_LOG_N)));
+    }
+    let scrypt_params = scrypt::Params::new(params.log_n, params.r, params.p, AEAD_KEY_LEN)
+        .map_err(|e| KeystoreError::InvalidKdfParams(e.to_string()))?;
+    let mut key = [0u8; AEAD_KEY_LEN];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &scrypt_params, &mut key)
+        .map_err(|e| KeystoreError::InvalidKdfParams(e.to_string()))?;
+    Ok(key)
+}
+
+fn decode_hex(field: &'static str, value: &str) -> Result<Vec<u8>, KeystoreError> {
+    hex::decode(value).map_err(|e| KeystoreError::InvalidField { field, reason: e.to_string() })
+}
+
+fn decode_array<const N: usize>(field: &'static str, value: &str) -> Result<[u8; N], KeystoreError> {
+    decode_hex(field, value)?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| KeystoreError::InvalidField { field, reason: format!("expected {} bytes, got {}", N, bytes.len()) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Cheap parameters so the tests do not spend their time in scrypt.
+    const TEST_PARAMS: ScryptParams = ScryptParams { log_n: 4, r: 8, p: 1 };
+
+    #[test]
+    fn test_keystore_round_trip() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let keystore = Keystore::encrypt(&signing_key, "correct horse", TEST_PARAMS).unwrap();
+        assert!(!keystore.ciphertext.contains(&hex::encode(signing_key.to_bytes())));
+        assert_eq!(keystore.public_key().unwrap().0, signing_key.verifying_key());
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wallet.key");
+        keystore.save(&path).unwrap();
+        let KeyFile::Encrypted(loaded) = KeyFile::read(&path).unwrap() else {
+            panic!("keystore was read back as a raw key");
+        };
+        assert_eq!(loaded.decrypt("correct horse").unwrap().to_bytes(), signing_key.to_bytes());
+
+        fs::write(&path, signing_key.to_bytes()).unwrap();
+        assert!(matches!(KeyFile::read(&path).unwrap(), KeyFile::Plain(key) if key.to_bytes() == signing_key.to_bytes()));
+    }
+
+    #[test]
+    fn test_keystore_rejects_wrong_passphrase_and_tampering() {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let keystore = Keystore::encrypt(&signing_key, "correct horse", TEST_PARAMS).unwrap();
+        assert!(matches!(keystore.decrypt("battery staple"), Err(KeystoreError::WrongPassphrase)));
+
+        // The public key is authenticated, so it cannot be swapped for another one
+        let mut swapped = keystore.clone();
+        swapped.public_key = hex::encode(SigningKey::generate(&mut OsRng).verifying_key().as_bytes());
+        assert!(matches!(swapped.decrypt("correct horse"), Err(KeystoreError::WrongPassphrase)));
+
+        let mut expensive = keystore.clone();
+        expensive.kdf = Kdf::Scrypt { log_n: 40, r: 8, p: 1, salt: String::new() };
+        assert!(matches!(expensive.decrypt("correct horse"), Err(KeystoreError::InvalidKdfParams(_))));
+
+        let mut future = keystore;
+        future.version = KEYSTORE_VERSION + 1;
+        assert!(matches!(future.decrypt("correct horse"), Err(KeystoreError::UnsupportedVersion(_))));
+    }
+}
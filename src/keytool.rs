@@ -0,0 +1,170 @@
+use crate::types::{address_from_public_key, Address, PublicKey};
+use ed25519_dalek::SigningKey;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// The characters of a bech32 data part, in the order of the 5-bit values they encode.
+pub const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+/// An address's 256 bits fill 51 whole characters of the data part.
+const MAX_PREFIX_LEN: usize = 256 / 5;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum KeytoolError {
+    #[error("The vanity prefix is empty")]
+    EmptyPrefix,
+    #[error("Vanity prefix `{prefix}` contains `{character}`, which never appears in an address. Use only {}", BECH32_CHARSET)]
+    InvalidPrefixCharacter { prefix: String, character: char },
+    #[error("Vanity prefix `{0}` is longer than an address")]
+    PrefixTooLong(String),
+}
+
+/// Derives a signing key from `seed` by hashing it with SHA-256. Anyone who knows the seed
+/// has the key, so this is only for reproducible test fixtures.
+pub fn signing_key_from_seed(seed: &str) -> SigningKey {
+    SigningKey::from_bytes(&Sha256::digest(seed.as_bytes()).into())
+}
+
+/// The first characters wanted after `rc1` in a vanity address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VanityPrefix {
+    text: String,
+    /// The 5-bit value of each character, compared against the address bits directly so the
+    /// search never has to encode whole addresses.
+    values: Vec<u8>,
+}
+
+impl VanityPrefix {
+    /// Whether the bech32m form of `address` starts with `rc1` followed by this prefix.
+    pub fn matches(&self, address: &Address) -> bool {
+        self.values.iter().enumerate().all(|(i, value)| five_bits(&address.0, i * 5) == *value)
+    }
+
+    /// How many keys have to be tried on average to find a match.
+    pub fn expected_attempts(&self) -> f64 {
+        32f64.powi(self.values.len() as i32)
+    }
+}
+
+impl FromStr for VanityPrefix {
+    type Err = KeytoolError;
+
+    /// Accepts the prefix with or without the leading `rc1`, in either case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.to_ascii_lowercase();
+        let text = text.strip_prefix("rc1").unwrap_or(&text).to_string();
+        if text.is_empty() {
+            return Err(KeytoolError::EmptyPrefix);
+        }
+        if text.len() > MAX_PREFIX_LEN {
+            return Err(KeytoolError::PrefixTooLong(text));
+        }
+        let values = text
+            .chars()
+            .map(|character| {
+                BECH32_CHARSET
+                    .find(character)
+                    .map(|value| value as u8)
+                    .ok_or_else(|| KeytoolError::InvalidPrefixCharacter { prefix: text.clone(), character })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(VanityPrefix { text, values })
+    }
+}
+
+impl fmt::Display for VanityPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rc1{}", self.text)
+    }
+}
+
+/// The 5 bits of `bytes` starting at bit `offset`, most significant first.
+fn five_bits(bytes: &[u8; 32], offset: usize) -> u8 {
+    let byte = offset / 8;
+    let pair = u16::from(bytes[byte]) << 8 | u16::from(bytes.get(byte + 1).copied().unwrap_or(0));
+    ((pair >> (11 - offset % 8)) & 0x1f) as u8
+}
+
+pub struct VanityMatch {
+    pub signing_key: SigningKey,
+    /// Keys tried across all threads, including the match.
+    pub attempts: u64,
+}
+
+/// Generates random keys on `threads` threads until one's account address starts with
+/// `prefix`. The search runs until it succeeds, so callers should check
+/// `expected_attempts` first.
+pub fn search_vanity(prefix: &VanityPrefix, threads: usize) -> VanityMatch {
+    let found = AtomicBool::new(false);
+    let attempts = AtomicU64::new(0);
+    let result = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                let mut rng = rand::thread_rng();
+                let mut tried = 0;
+                while !found.load(Ordering::Relaxed) {
+                    let signing_key = SigningKey::generate(&mut rng);
+                    tried += 1;
+                    let address = address_from_public_key(&PublicKey(signing_key.verifying_key()));
+                    if prefix.matches(&address) && !found.swap(true, Ordering::Relaxed) {
+                        *result.lock().unwrap() = Some(signing_key);
+                    }
+                }
+                attempts.fetch_add(tried, Ordering::Relaxed);
+            });
+        }
+    });
+
+    VanityMatch {
+        signing_key: result.into_inner().unwrap().expect("the search only stops once a key is found"),
+        attempts: attempts.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_of(signing_key: &SigningKey) -> Address {
+        address_from_public_key(&PublicKey(signing_key.verifying_key()))
+    }
+
+    #[test]
+    fn test_seed_keys_are_reproducible() {
+        let key = signing_key_from_seed("rustchain_test_validator_seed_123");
+        assert_eq!(key.to_bytes(), signing_key_from_seed("rustchain_test_validator_seed_123").to_bytes());
+        // The dev genesis names this key as its validator
+        assert_eq!(hex::encode(key.verifying_key().to_bytes()), "68e8dfa9999a7d1de46d9ddbae29ebdca13fba0f8011661976e62bb69c133fb2");
+        assert_ne!(key.to_bytes(), signing_key_from_seed("rustchain_test_validator_seed_124").to_bytes());
+    }
+
+    #[test]
+    fn test_vanity_prefix_matches_encoded_address() {
+        let address = account_of(&signing_key_from_seed("fixture"));
+        let encoded = address.to_string();
+        for len in [1, 2, 7, MAX_PREFIX_LEN] {
+            let prefix: VanityPrefix = encoded[..3 + len].parse().unwrap();
+            assert!(prefix.matches(&address), "{} should match {}", prefix, encoded);
+        }
+        let other = if encoded.as_bytes()[3] == b'q' { "rc1p" } else { "rc1q" };
+        assert!(!other.parse::<VanityPrefix>().unwrap().matches(&address));
+
+        assert_eq!("ACE".parse::<VanityPrefix>().unwrap().to_string(), "rc1ace");
+        assert_eq!("rc1".parse::<VanityPrefix>(), Err(KeytoolError::EmptyPrefix));
+        assert!(matches!("rc1abc".parse::<VanityPrefix>(), Err(KeytoolError::InvalidPrefixCharacter { character: 'b', .. })));
+        assert!(matches!("q".repeat(MAX_PREFIX_LEN + 1).parse::<VanityPrefix>(), Err(KeytoolError::PrefixTooLong(_))));
+    }
+
+    #[test]
+    fn test_vanity_search_finds_prefix() {
+        let prefix: VanityPrefix = "rc1x".parse().unwrap();
+        let found = search_vanity(&prefix, 2);
+        assert!(account_of(&found.signing_key).to_string().starts_with("rc1x"));
+        assert!(found.attempts >= 1);
+    }
+}
@@ -1,10 +1,25 @@
 pub mod block;
+pub mod block_signer;
+pub mod chain_store;
 pub mod consensus;
+pub mod genesis;
+pub mod hd_wallet;
+pub mod htlc;
+pub mod keystore;
+pub mod keytool;
 pub mod mempool;
+pub mod migrations;
+pub mod multisig;
 pub mod networking;
+pub mod node;
+pub mod rpc;
+pub mod signer_server;
+pub mod snapshot;
 pub mod state_machine;
 pub mod storage;
 pub mod transaction;
 pub mod types;
 pub mod validator;
+pub mod vesting;
 pub mod wallet;
+pub mod wallet_dir;
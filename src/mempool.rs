@@ -1,5 +1,6 @@
+use crate::block::Block;
 use crate::transaction::{Transaction, TxValidationError};
-use crate::types::Hash;
+use crate::types::{BlockHeight, Hash};
 use std::collections::{HashMap, VecDeque};
 use std::sync::RwLock;
 use thiserror::Error;
@@ -87,16 +88,20 @@ impl Mempool {
             return Err(MempoolError::TransactionExists(tx_id));
         }
 
-        // Basic mempool-specific validation: prevent zero-amount transactions.
-        // More comprehensive stateless validation (like signature) should ideally be done before calling this.
-        if transaction.amount == 0 {
-            debug!("Transaction {} has zero amount, rejecting.", tx_id);
-            return Err(MempoolError::ZeroAmountTransaction);
+        // Stateless validation: reject zero-amount transfers, unknown envelope versions and
+        // transactions whose signatures do not satisfy their sender.
+        match transaction.validate() {
+            Ok(()) => {}
+            Err(TxValidationError::ZeroAmount) => {
+                debug!("Transaction {} has zero amount, rejecting.", tx_id);
+                return Err(MempoolError::ZeroAmountTransaction);
+            }
+            Err(e) => {
+                debug!("Transaction {} failed stateless validation: {}", tx_id, e);
+                return Err(MempoolError::StatelessValidationFailed(e));
+            }
         }
 
-        // TODO: Consider further stateless validation if needed, e.g. transaction.validate_intrinsic_properties()
-        // For now, we assume prior validation or that the state machine will do more thorough checks.
-
         inner.transactions.insert(tx_id, transaction);
         inner.pending_queue.push_back(tx_id);
 
@@ -132,6 +137,47 @@ impl Mempool {
         selected_transactions
     }
 
+    /// Like `get_pending_transactions`, but skips transactions whose validity window does not
+    /// include `height`, the height of the block being built.
+    pub fn get_pending_transactions_for_height(&self, max_txs: usize, height: BlockHeight) -> Vec<Transaction> {
+        let inner = self.inner.read().expect("Failed to acquire read lock on mempool");
+        inner
+            .pending_queue
+            .iter()
+            .filter_map(|tx_hash| inner.transactions.get(tx_hash))
+            .filter(|transaction| transaction.validity.contains(height))
+            .take(max_txs)
+            .cloned()
+            .collect()
+    }
+
+    /// Removes transactions that can no longer be included in any block above `height`.
+    /// Returns the hashes of the evicted transactions.
+    pub fn evict_expired(&self, height: BlockHeight) -> Vec<Hash> {
+        let expired: Vec<Hash> = {
+            let inner = self.inner.read().expect("Failed to acquire read lock on mempool");
+            inner
+                .transactions
+                .iter()
+                .filter(|(_, transaction)| transaction.validity.is_expired_at(BlockHeight(height.0.saturating_add(1))))
+                .map(|(tx_hash, _)| *tx_hash)
+                .collect()
+        };
+        if !expired.is_empty() {
+            debug!("Evicting {} expired transactions at height {}", expired.len(), height);
+            self.remove_transactions(&expired);
+        }
+        expired
+    }
+
+    /// Drops the transactions included in a newly committed block and evicts everything
+    /// that expired with it.
+    pub fn on_block_committed(&self, block: &Block) {
+        let included: Vec<Hash> = block.transactions.iter().filter_map(|tx| tx.id().ok()).collect();
+        self.remove_transactions(&included);
+        self.evict_expired(block.header.block_number);
+    }
+
     /// Removes transactions from the mempool, typically after they have been included in a block.
     ///
     /// # Arguments
@@ -198,26 +244,14 @@ mod tests {
         let sender_pk = PublicKey(verifying_key);
         let recipient_address = Address([1u8; 32]); // Dummy recipient
 
-        let tx_to_sign = Transaction {
-            sender: sender_pk,
-            recipient: recipient_address,
-            amount,
-            nonce: Nonce(nonce_val),
-            signature: TypesSignature(signing_key.sign(&[]).to_bytes().to_vec()), // Dummy signature, will be replaced
-        };
+        let tx_to_sign = Transaction::new(sender_pk, recipient_address, amount, Nonce(nonce_val), TypesSignature(signing_key.sign(&[]).to_bytes().to_vec()));
 
         // Calculate data_to_sign_hash
         let data_hash = tx_to_sign.data_to_sign_hash().expect("Failed to hash tx for signing");
         let signature = TypesSignature(signing_key.sign(data_hash.as_ref()).to_bytes().to_vec());
 
         (
-            Transaction {
-                sender: sender_pk,
-                recipient: recipient_address,
-                amount,
-                nonce: Nonce(nonce_val),
-                signature,
-            },
+            Transaction::new(sender_pk, recipient_address, amount, Nonce(nonce_val), signature),
             sender_pk,
         )
     }
@@ -474,6 +508,75 @@ mod tests {
         assert_eq!(inner.transactions.len(), 3);
     }
 
+    #[test]
+    fn test_validity_windows_in_mempool() {
+        use crate::block::BlockHeader;
+        use crate::transaction::ValidityWindow;
+        use crate::types::Timestamp;
+
+        let mempool = Mempool::new(MempoolConfig::default());
+        let wallet = crate::wallet::Wallet::new();
+        // The window is signed over, so it is set before signing
+        let windowed = |nonce, valid_after_height, valid_until_height| {
+            let tx = Transaction::new(*wallet.public_key(), Address([1u8; 32]), 10, Nonce(nonce), TypesSignature(vec![]))
+                .with_validity(ValidityWindow { valid_after_height, valid_until_height });
+            wallet.sign_transaction(tx).unwrap()
+        };
+        let open = windowed(1, None, None);
+        let expires_at_5 = windowed(2, None, Some(BlockHeight(5)));
+        let starts_after_5 = windowed(3, Some(BlockHeight(5)), None);
+        let ids: Vec<Hash> = [&open, &expires_at_5, &starts_after_5].iter().map(|tx| tx.id().unwrap()).collect();
+        for tx in [open, expires_at_5, starts_after_5] {
+            mempool.add_transaction(tx).unwrap();
+        }
+
+        let selected = |height| -> Vec<Hash> {
+            mempool.get_pending_transactions_for_height(10, BlockHeight(height)).iter().map(|tx| tx.id().unwrap()).collect()
+        };
+        assert_eq!(selected(5), vec![ids[0], ids[1]]);
+        assert_eq!(selected(6), vec![ids[0], ids[2]]);
+
+        // Committing block 4 leaves the transaction valid until 5 in place; block 5 evicts it
+        let block_at = |height| Block {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(height),
+                timestamp: Timestamp(0),
+                tx_root: Hash([0; 32]),
+                validator: Address([0; 32]),
+                signature: TypesSignature(vec![]),
+            },
+            transactions: vec![],
+        };
+        mempool.on_block_committed(&block_at(4));
+        assert!(mempool.contains_transaction(&ids[1]));
+        mempool.on_block_committed(&block_at(5));
+        assert!(!mempool.contains_transaction(&ids[1]));
+        assert_eq!(mempool.status().pending_transactions_count, 2);
+    }
+
+    #[test]
+    fn test_add_transaction_rejects_bad_signatures() {
+        use crate::multisig::MultisigPolicy;
+        use crate::transaction::{TransactionKind, Transfer};
+
+        let mempool = Mempool::new(MempoolConfig::default());
+        let (mut forged, _) = dummy_test_transaction(100, 1);
+        let TransactionKind::Transfer(transfer) = &mut forged.kind else { unreachable!() };
+        transfer.amount = 1_000;
+        assert_eq!(mempool.add_transaction(forged), Err(MempoolError::StatelessValidationFailed(TxValidationError::InvalidSignature)));
+
+        // A multisig transaction without its threshold of cosignatures is not a spend yet
+        let keys = (0..3).map(|_| PublicKey(SigningKey::generate(&mut OsRng).verifying_key())).collect();
+        let policy = MultisigPolicy::new(2, keys).unwrap();
+        let unsigned = Transaction::new_multisig(policy, Nonce(0), TransactionKind::Transfer(Transfer { recipient: Address([1u8; 32]), amount: 5 }));
+        assert_eq!(
+            mempool.add_transaction(unsigned),
+            Err(MempoolError::StatelessValidationFailed(TxValidationError::InsufficientSignatures { have: 0, need: 2 }))
+        );
+        assert_eq!(mempool.status().pending_transactions_count, 0);
+    }
+
     // More tests for add_transaction, get_pending_transactions, remove_transactions, etc.,
     // will be added as these functions are implemented.
 }
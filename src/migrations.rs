@@ -0,0 +1,605 @@
+use crate::block::{Block, BlockHeader};
+use crate::chain_store::ChainStore;
+use crate::storage::{
+    address_entries, history_index_key, history_key, location_index_key, lock_entries, memo_entries, split_history_key, Storage,
+    StorageError, ADDRESS_INDEX_CF, BLOCKS_CF, HEADERS_CF, HISTORY_HEIGHT_KEY, HISTORY_INDEX_CF, MEMO_INDEX_CF, META_CF, SCHEMA_VERSION,
+    SCHEMA_VERSION_KEY, STATE_CF, STATE_HEIGHT_KEY, STATE_HISTORY_CF,
+};
+use crate::state_machine::Account;
+use crate::transaction::{Transaction, TransactionKind, Transfer, TxSender, TxSignature, ValidityWindow, LEGACY_TRANSACTION_VERSION};
+use crate::types::{Address, Nonce, PublicKey, Signature};
+use crate::vesting::VestingSchedule;
+use bincode::{Decode, Encode};
+use rocksdb::{IteratorMode, WriteBatch};
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Database not found at {0}")]
+    DatabaseNotFound(String),
+    #[error("Database schema version {found} is newer than version {supported} supported by this node")]
+    UnsupportedVersion { found: u32, supported: u32 },
+    #[error("No migration registered from schema version {0}")]
+    MissingStep(u32),
+}
+
+/// A single upgrade step from `from_version` to `from_version + 1`.
+#[derive(Debug)]
+pub struct Migration {
+    pub from_version: u32,
+    pub description: &'static str,
+    apply: fn(&Storage, &mut WriteBatch) -> Result<(), StorageError>,
+}
+
+impl Migration {
+    pub fn to_version(&self) -> u32 {
+        self.from_version + 1
+    }
+}
+
+/// Every upgrade step, ordered by `from_version`. Each step is written in a single batch
+/// together with the new schema version, so an interrupted migration resumes cleanly.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from_version: 1,
+        description: "Seed state_history from the current state at the chain tip",
+        apply: seed_state_history,
+    },
+    Migration {
+        from_version: 2,
+        description: "Record which height the stored state belongs to",
+        apply: record_state_height,
+    },
+    Migration {
+        from_version: 3,
+        description: "Wrap stored transactions in versioned transfer envelopes",
+        apply: wrap_transaction_envelopes,
+    },
+    Migration {
+        from_version: 4,
+        description: "Index the memos of stored transactions",
+        apply: index_transaction_memos,
+    },
+    Migration {
+        from_version: 5,
+        description: "Add an authorized key to stored accounts",
+        apply: add_authorized_keys,
+    },
+    Migration {
+        from_version: 6,
+        description: "Add a vesting schedule to stored accounts",
+        apply: add_vesting_schedules,
+    },
+    Migration {
+        from_version: 7,
+        description: "Add a hash-time lock to stored accounts",
+        apply: add_hash_locks,
+    },
+    Migration {
+        from_version: 8,
+        description: "Index stored transactions by the addresses they touch",
+        apply: index_transaction_addresses,
+    },
+    Migration {
+        from_version: 9,
+        description: "Index state history records by height",
+        apply: index_state_history,
+    },
+];
+
+/// Result of planning or running migrations against a database.
+#[derive(Debug)]
+pub struct MigrationReport {
+    /// Schema version found on disk (`None` for a fresh database)
+    pub from_version: Option<u32>,
+    /// Schema version after the run (unchanged for a dry run)
+    pub to_version: Option<u32>,
+    /// Steps that were applied, or would be applied for a dry run
+    pub steps: Vec<&'static Migration>,
+}
+
+/// Returns the steps needed to bring a database at `version` up to `SCHEMA_VERSION`.
+pub fn plan(version: u32) -> Result<Vec<&'static Migration>, MigrationError> {
+    if version > SCHEMA_VERSION {
+        return Err(MigrationError::UnsupportedVersion { found: version, supported: SCHEMA_VERSION });
+    }
+    (version..SCHEMA_VERSION)
+        .map(|from| {
+            MIGRATIONS
+                .iter()
+                .find(|step| step.from_version == from)
+                .ok_or(MigrationError::MissingStep(from))
+        })
+        .collect()
+}
+
+/// Upgrades the database at `path` to the current schema version. With `dry_run` set, the
+/// pending steps are reported but nothing is written.
+pub fn migrate<P: AsRef<Path>>(path: P, dry_run: bool) -> Result<MigrationReport, MigrationError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err(MigrationError::DatabaseNotFound(path.display().to_string()));
+    }
+    let storage = Storage::open_unchecked(path)?;
+    let from_version = storage.get_schema_version()?;
+    let Some(version) = from_version else {
+        // Fresh databases are stamped with the current version on first open
+        return Ok(MigrationReport { from_version, to_version: from_version, steps: Vec::new() });
+    };
+
+    let steps = plan(version)?;
+    if dry_run {
+        return Ok(MigrationReport { from_version, to_version: from_version, steps });
+    }
+
+    let meta_cf = storage.get_cf(META_CF)?;
+    for step in &steps {
+        let mut batch = WriteBatch::default();
+        (step.apply)(&storage, &mut batch)?;
+        let version_bytes = bincode::encode_to_vec(step.to_version(), bincode::config::standard())
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(meta_cf, SCHEMA_VERSION_KEY, version_bytes);
+        storage.db().write(batch).map_err(StorageError::from)?;
+        tracing::info!("Migrated database schema from version {} to {}", step.from_version, step.to_version());
+    }
+
+    Ok(MigrationReport { from_version, to_version: Some(SCHEMA_VERSION), steps })
+}
+
+/// v1 -> v2: older heights cannot be reconstructed without replaying the chain, so history
+/// starts at the current tip and earlier heights are reported as pruned. Accounts are
+/// copied byte for byte, since they still have the v1 layout that v5 -> v6 rewrites.
+fn seed_state_history(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    let tip_height = storage.get_chain_tip()?.map(|(_, height)| height).unwrap_or(0);
+    let state_cf = storage.get_cf(STATE_CF)?;
+    let history_cf = storage.get_cf(STATE_HISTORY_CF)?;
+    let meta_cf = storage.get_cf(META_CF)?;
+
+    for item in storage.db().iterator_cf(state_cf, IteratorMode::Start) {
+        let (key, account_bytes) = item?;
+        let address_bytes: [u8; 32] = key.as_ref().try_into()
+            .map_err(|_| StorageError::DeserializationError(format!("Invalid state key length: {}", key.len())))?;
+        batch.put_cf(history_cf, history_key(&Address(address_bytes), tip_height), account_bytes);
+    }
+
+    let height_bytes = bincode::encode_to_vec(tip_height, bincode::config::standard())
+        .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+    batch.put_cf(meta_cf, HISTORY_HEIGHT_KEY, height_bytes);
+    Ok(())
+}
+
+/// v2 -> v3: state written by older versions is assumed to match the tip. If no account was
+/// ever written, the marker is left unset so the integrity check treats the chain as an
+/// interrupted genesis.
+fn record_state_height(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    let Some((_, tip_height)) = storage.get_chain_tip()? else {
+        return Ok(());
+    };
+    let state_cf = storage.get_cf(STATE_CF)?;
+    if storage.db().iterator_cf(state_cf, IteratorMode::Start).next().is_none() {
+        return Ok(());
+    }
+    let meta_cf = storage.get_cf(META_CF)?;
+    let height_bytes = bincode::encode_to_vec(tip_height, bincode::config::standard())
+        .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+    batch.put_cf(meta_cf, STATE_HEIGHT_KEY, height_bytes);
+    Ok(())
+}
+
+/// A block as encoded up to schema v3, before transactions had an envelope.
+#[derive(Encode, Decode)]
+struct LegacyBlock {
+    header: BlockHeader,
+    transactions: Vec<LegacyTransaction>,
+}
+
+#[derive(Encode, Decode)]
+struct LegacyTransaction {
+    sender: PublicKey,
+    recipient: Address,
+    amount: u64,
+    nonce: Nonce,
+    signature: Signature,
+}
+
+/// v3 -> v4: legacy transfers become version 0 envelopes, which are signed and hashed over
+/// the original fields, so transaction ids and block tx roots are unchanged.
+fn wrap_transaction_envelopes(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    let blocks_cf = storage.get_cf(BLOCKS_CF)?;
+    for item in storage.db().iterator_cf(blocks_cf, IteratorMode::Start) {
+        let (key, value) = item?;
+        let (legacy, _): (LegacyBlock, usize) = bincode::decode_from_slice(&value, bincode::config::standard())
+            .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+        let block = Block {
+            header: legacy.header,
+            transactions: legacy
+                .transactions
+                .into_iter()
+                .map(|tx| Transaction {
+                    version: LEGACY_TRANSACTION_VERSION,
+                    sender: TxSender::Single(tx.sender),
+                    nonce: tx.nonce,
+                    kind: TransactionKind::Transfer(Transfer { recipient: tx.recipient, amount: tx.amount }),
+                    validity: ValidityWindow::default(),
+                    memo: Vec::new(),
+                    signature: TxSignature::Single(tx.signature),
+                })
+                .collect(),
+        };
+        let block_bytes = bincode::encode_to_vec(&block, bincode::config::standard())
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(blocks_cf, key, block_bytes);
+    }
+    Ok(())
+}
+
+/// v4 -> v5: builds `memo_index` from the retained block bodies. Bodies that were already
+/// pruned are not indexed.
+fn index_transaction_memos(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    let blocks_cf = storage.get_cf(BLOCKS_CF)?;
+    let memo_cf = storage.get_cf(MEMO_INDEX_CF)?;
+    for item in storage.db().iterator_cf(blocks_cf, IteratorMode::Start) {
+        let (_, value) = item?;
+        let (block, _): (Block, usize) = bincode::decode_from_slice(&value, bincode::config::standard())
+            .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+        for (memo_hash, location) in memo_entries(&block)? {
+            batch.put_cf(memo_cf, location_index_key(&memo_hash.0, &location), []);
+        }
+    }
+    Ok(())
+}
+
+/// An account as encoded up to schema v5, before key rotation.
+#[derive(Encode, Decode)]
+struct LegacyAccount {
+    balance: u64,
+    nonce: Nonce,
+}
+
+/// An account as encoded in schema v6, before vesting schedules.
+#[derive(Encode, Decode)]
+struct UnvestedAccount {
+    balance: u64,
+    nonce: Nonce,
+    authorized_key: Option<PublicKey>,
+}
+
+/// An account as encoded in schema v7, before hash-time-locked escrows.
+#[derive(Encode, Decode)]
+struct VestedAccount {
+    balance: u64,
+    nonce: Nonce,
+    authorized_key: Option<PublicKey>,
+    vesting: Option<VestingSchedule>,
+}
+
+/// Re-encodes every current and historical account from one layout to the next.
+fn rewrite_accounts<Old: Decode<()>, New: Encode>(
+    storage: &Storage,
+    batch: &mut WriteBatch,
+    convert: fn(Old) -> New,
+) -> Result<(), StorageError> {
+    for cf in [storage.get_cf(STATE_CF)?, storage.get_cf(STATE_HISTORY_CF)?] {
+        for item in storage.db().iterator_cf(cf, IteratorMode::Start) {
+            let (key, value) = item?;
+            let (old, _): (Old, usize) = bincode::decode_from_slice(&value, bincode::config::standard())
+                .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+            let account_bytes = bincode::encode_to_vec(convert(old), bincode::config::standard())
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            batch.put_cf(cf, key, account_bytes);
+        }
+    }
+    Ok(())
+}
+
+/// v5 -> v6: no account could have rotated its key yet, so every account, current and
+/// historical, gets the default authorized key.
+fn add_authorized_keys(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    rewrite_accounts(storage, batch, |legacy: LegacyAccount| UnvestedAccount {
+        balance: legacy.balance,
+        nonce: legacy.nonce,
+        authorized_key: None,
+    })
+}
+
+/// v6 -> v7: vesting can only be configured in genesis, and every existing chain was
+/// started without it.
+fn add_vesting_schedules(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    rewrite_accounts(storage, batch, |account: UnvestedAccount| VestedAccount {
+        balance: account.balance,
+        nonce: account.nonce,
+        authorized_key: account.authorized_key,
+        vesting: None,
+    })
+}
+
+/// v7 -> v8: escrow accounts did not exist yet, so no account holds a lock.
+fn add_hash_locks(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    rewrite_accounts(storage, batch, |account: VestedAccount| Account {
+        balance: account.balance,
+        nonce: account.nonce,
+        authorized_key: account.authorized_key,
+        vesting: account.vesting,
+        hash_lock: None,
+    })
+}
+
+/// Blocks indexed between writes by the v8 -> v9 migration.
+const ADDRESS_INDEX_BLOCKS_PER_WRITE: usize = 1_000;
+
+/// v8 -> v9: builds `address_index` from the retained block bodies, like the memo index
+/// in v5. Closed escrow accounts no longer hold their terms, so claims and refunds are
+/// resolved against the locks opened so far.
+fn index_transaction_addresses(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    index_addresses_by_height(storage, batch, ADDRESS_INDEX_BLOCKS_PER_WRITE)
+}
+
+/// Walks the chain in height order, so a lock is always seen before its claim or refund,
+/// and writes the index every `blocks_per_write` blocks instead of holding the whole chain.
+/// The entries are plain keys, so a run interrupted between writes is simply repeated.
+fn index_addresses_by_height(storage: &Storage, batch: &mut WriteBatch, blocks_per_write: usize) -> Result<(), StorageError> {
+    let headers_cf = storage.get_cf(HEADERS_CF)?;
+    let address_cf = storage.get_cf(ADDRESS_INDEX_CF)?;
+    let mut locks = BTreeMap::new();
+    let mut blocks_in_batch = 0;
+    for item in storage.db().iterator_cf(headers_cf, IteratorMode::Start) {
+        let (_, value) = item?;
+        let (header, _): (BlockHeader, usize) = bincode::decode_from_slice(&value, bincode::config::standard())
+            .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+        let hash = header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        // Pruned heights keep only their header
+        let Some(block) = storage.get_block(&hash)? else { continue };
+        locks.extend(lock_entries(&block));
+        for (address, location) in address_entries(&block, |lock_id| Ok(locks.get(lock_id).copied()))? {
+            batch.put_cf(address_cf, location_index_key(&address.0, &location), []);
+        }
+
+        blocks_in_batch += 1;
+        if blocks_in_batch == blocks_per_write {
+            storage.db().write(std::mem::take(batch))?;
+            blocks_in_batch = 0;
+        }
+    }
+    Ok(())
+}
+
+/// v9 -> v10: builds `history_index` from the existing state history, so the next prune
+/// collapses records that older versions left behind.
+fn index_state_history(storage: &Storage, batch: &mut WriteBatch) -> Result<(), StorageError> {
+    let history_cf = storage.get_cf(STATE_HISTORY_CF)?;
+    let index_cf = storage.get_cf(HISTORY_INDEX_CF)?;
+    for item in storage.db().iterator_cf(history_cf, IteratorMode::Start) {
+        let (key, _) = item?;
+        let (address, height) = split_history_key(&key)?;
+        batch.put_cf(index_cf, history_index_key(height, &address), []);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::htlc::{hashlock_of, HashTimeLock};
+    use crate::state_machine::WorldState;
+    use crate::types::{BlockHeight, Hash, Timestamp};
+    use crate::wallet::Wallet;
+    use sha2::{Digest, Sha256};
+    use tempfile::tempdir;
+
+    /// Writes the records a node stored before schema versioning existed, in their v1 encoding.
+    fn legacy_database(path: &Path) {
+        let storage = Storage::open_unchecked(path).unwrap();
+        let block = LegacyBlock {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(3),
+                timestamp: Timestamp(123),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![],
+        };
+        let hash = block.header.calculate_hash().unwrap();
+        let db = storage.db();
+        db.put_cf(storage.get_cf(BLOCKS_CF).unwrap(), hash.0, bincode::encode_to_vec(&block, bincode::config::standard()).unwrap()).unwrap();
+        db.put_cf(storage.get_cf(HEADERS_CF).unwrap(), 3u64.to_be_bytes(), bincode::encode_to_vec(&block.header, bincode::config::standard()).unwrap()).unwrap();
+        let account = LegacyAccount { balance: 500, nonce: Nonce(2) };
+        db.put_cf(storage.get_cf(STATE_CF).unwrap(), [1; 32], bincode::encode_to_vec(account, bincode::config::standard()).unwrap()).unwrap();
+        let meta_cf = storage.get_cf(META_CF).unwrap();
+        db.put_cf(meta_cf, b"tip", bincode::encode_to_vec(hash, bincode::config::standard()).unwrap()).unwrap();
+        db.put_cf(meta_cf, b"height", bincode::encode_to_vec(3u64, bincode::config::standard()).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_plan_covers_every_version() {
+        assert_eq!(plan(1).unwrap().len(), (SCHEMA_VERSION - 1) as usize);
+        assert!(plan(SCHEMA_VERSION).unwrap().is_empty());
+        assert!(matches!(plan(SCHEMA_VERSION + 1), Err(MigrationError::UnsupportedVersion { .. })));
+    }
+
+    #[test]
+    fn test_legacy_database_requires_migration() {
+        let dir = tempdir().unwrap();
+        legacy_database(dir.path());
+
+        assert!(matches!(
+            Storage::new(dir.path()),
+            Err(StorageError::SchemaOutdated { found: 1, expected: SCHEMA_VERSION })
+        ));
+
+        let report = migrate(dir.path(), true).unwrap();
+        assert_eq!(report.from_version, Some(1));
+        assert_eq!(report.to_version, Some(1));
+        assert_eq!(report.steps.len(), 9);
+        assert!(Storage::new(dir.path()).is_err());
+
+        let report = migrate(dir.path(), false).unwrap();
+        assert_eq!(report.to_version, Some(SCHEMA_VERSION));
+
+        let storage = Storage::new(dir.path()).unwrap();
+        assert_eq!(storage.get_state_height().unwrap(), Some(3));
+        assert_eq!(storage.get_account_at(&Address([1; 32]), 3).unwrap().unwrap().balance, 500);
+        assert!(matches!(
+            storage.get_account_at(&Address([1; 32]), 2),
+            Err(StorageError::Pruned { height: 2, horizon: 3 })
+        ));
+        drop(storage);
+        assert!(migrate(dir.path(), false).unwrap().steps.is_empty());
+    }
+
+    /// Migrates copies of the databases checked in at the repository root, which were written
+    /// by a v1 node.
+    #[test]
+    fn test_tracked_v1_databases_migrate_to_latest() {
+        for name in ["rustchain_db", "dev_rustchain_db", "test_node_1_db", "test_node_2_db", "test_node_3_db"] {
+            let source = Path::new(env!("CARGO_MANIFEST_DIR")).join(name);
+            let dir = tempdir().unwrap();
+            for entry in std::fs::read_dir(&source).unwrap() {
+                let entry = entry.unwrap();
+                std::fs::copy(entry.path(), dir.path().join(entry.file_name())).unwrap();
+            }
+
+            let report = migrate(dir.path(), false).unwrap();
+            assert_eq!(report.from_version, Some(1), "{}", name);
+            let storage = Storage::new(dir.path()).unwrap();
+            let (_, tip_height) = storage.get_chain_tip().unwrap().unwrap();
+            assert_eq!(storage.get_state_height().unwrap(), Some(tip_height), "{}", name);
+            let accounts = storage.load_accounts().unwrap();
+            assert!(!accounts.is_empty(), "{}", name);
+            for (address, account) in accounts {
+                assert_eq!(storage.get_account_at(&address, tip_height).unwrap(), Some(account), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_newer_database_is_refused() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::new(dir.path()).unwrap();
+        let meta_cf = storage.get_cf(META_CF).unwrap();
+        let bytes = bincode::encode_to_vec(SCHEMA_VERSION + 1, bincode::config::standard()).unwrap();
+        storage.db().put_cf(meta_cf, SCHEMA_VERSION_KEY, bytes).unwrap();
+        drop(storage);
+
+        assert!(matches!(Storage::new(dir.path()), Err(StorageError::UnsupportedSchemaVersion { .. })));
+        assert!(matches!(migrate(dir.path(), true), Err(MigrationError::UnsupportedVersion { .. })));
+    }
+
+    #[test]
+    fn test_legacy_transactions_keep_their_ids() {
+        let dir = tempdir().unwrap();
+        let wallet = Wallet::new();
+        let legacy_tx = LegacyTransaction {
+            sender: *wallet.public_key(),
+            recipient: Address([9; 32]),
+            amount: 25,
+            nonce: Nonce(4),
+            signature: Signature(vec![7; 64]),
+        };
+        let legacy_fields = (legacy_tx.sender, legacy_tx.recipient, legacy_tx.amount, legacy_tx.nonce);
+        let legacy_id = Hash(Sha256::digest(bincode::encode_to_vec(legacy_fields, bincode::config::standard()).unwrap()).into());
+        let legacy_block = LegacyBlock {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(1),
+                timestamp: Timestamp(5),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![legacy_tx],
+        };
+        let block_hash = legacy_block.header.calculate_hash().unwrap();
+
+        {
+            let storage = Storage::new(dir.path()).unwrap();
+            let blocks_cf = storage.get_cf(BLOCKS_CF).unwrap();
+            let bytes = bincode::encode_to_vec(&legacy_block, bincode::config::standard()).unwrap();
+            storage.db().put_cf(blocks_cf, block_hash.0, bytes).unwrap();
+            let header_bytes = bincode::encode_to_vec(&legacy_block.header, bincode::config::standard()).unwrap();
+            storage.db().put_cf(storage.get_cf(HEADERS_CF).unwrap(), 1u64.to_be_bytes(), header_bytes).unwrap();
+            let meta_cf = storage.get_cf(META_CF).unwrap();
+            storage.db().put_cf(meta_cf, SCHEMA_VERSION_KEY, bincode::encode_to_vec(3u32, bincode::config::standard()).unwrap()).unwrap();
+        }
+
+        assert_eq!(migrate(dir.path(), false).unwrap().steps.len(), 7);
+        let storage = Storage::new(dir.path()).unwrap();
+        let block = storage.get_block(&block_hash).unwrap().unwrap();
+        let tx = &block.transactions[0];
+        assert_eq!(tx.version, LEGACY_TRANSACTION_VERSION);
+        assert_eq!(tx.kind, TransactionKind::Transfer(Transfer { recipient: Address([9; 32]), amount: 25 }));
+        assert_eq!(tx.id().unwrap(), legacy_id);
+        // Retained bodies are indexed by address
+        let location = crate::storage::TransactionLocation { height: 1, tx_id: legacy_id };
+        assert_eq!(storage.get_transactions_by_address(&Address([9; 32])).unwrap(), vec![location]);
+    }
+
+    #[test]
+    fn test_legacy_accounts_gain_new_fields() {
+        let dir = tempdir().unwrap();
+        let address = Address([3; 32]);
+        {
+            let storage = Storage::new(dir.path()).unwrap();
+            let legacy = bincode::encode_to_vec(LegacyAccount { balance: 42, nonce: Nonce(7) }, bincode::config::standard()).unwrap();
+            storage.db().put_cf(storage.get_cf(STATE_CF).unwrap(), address.0, &legacy).unwrap();
+            storage.db().put_cf(storage.get_cf(STATE_HISTORY_CF).unwrap(), history_key(&address, 2), &legacy).unwrap();
+            let meta_cf = storage.get_cf(META_CF).unwrap();
+            storage.db().put_cf(meta_cf, SCHEMA_VERSION_KEY, bincode::encode_to_vec(5u32, bincode::config::standard()).unwrap()).unwrap();
+        }
+
+        assert_eq!(migrate(dir.path(), false).unwrap().steps.len(), 5);
+        let storage = Storage::new(dir.path()).unwrap();
+        let expected = Account { balance: 42, nonce: Nonce(7), authorized_key: None, vesting: None, hash_lock: None };
+        assert_eq!(storage.get_account(&address).unwrap(), Some(expected.clone()));
+        assert_eq!(storage.get_account_at(&address, 2).unwrap(), Some(expected));
+    }
+
+    #[test]
+    fn test_address_index_is_rebuilt_across_writes() {
+        let dir = tempdir().unwrap();
+        let storage = Storage::new(dir.path()).unwrap();
+        let alice = Wallet::new();
+        let alice_address = crate::types::address_from_public_key(alice.public_key());
+        let (bob, refund) = (Address([7; 32]), Address([8; 32]));
+        let lock = HashTimeLock { hashlock: hashlock_of(&[3; 32]), timelock: BlockHeight(10), recipient: bob, refund_address: refund };
+        let lock_id = HashTimeLock::lock_address(&alice_address, Nonce(0));
+        let open = Transaction::with_kind(*alice.public_key(), Nonce(0), TransactionKind::LockFunds { amount: 40, lock }, Signature(vec![]));
+        let claim = Transaction::with_kind(*Wallet::new().public_key(), Nonce(0), TransactionKind::ClaimLock { lock_id, preimage: [3; 32] }, Signature(vec![]));
+
+        // The lock opens at height 1 and is claimed at height 3, after its escrow has closed
+        let mut parent_hash = Hash([0; 32]);
+        for (height, transactions) in [(1, vec![open.clone()]), (2, vec![]), (3, vec![claim.clone()])] {
+            let block = Block {
+                header: BlockHeader {
+                    parent_hash,
+                    block_number: BlockHeight(height),
+                    timestamp: Timestamp(height),
+                    tx_root: Hash([1; 32]),
+                    validator: Address([2; 32]),
+                    signature: Signature(vec![0; 64]),
+                },
+                transactions,
+            };
+            let escrow = if height == 1 { Account { balance: 40, hash_lock: Some(lock), ..Default::default() } } else { Account::default() };
+            storage.commit_block(&block, &WorldState::from([(lock_id, escrow)])).unwrap();
+            parent_hash = block.header.calculate_hash().unwrap();
+        }
+        let address_cf = storage.get_cf(ADDRESS_INDEX_CF).unwrap();
+        for item in storage.db().iterator_cf(address_cf, IteratorMode::Start) {
+            storage.db().delete_cf(address_cf, item.unwrap().0).unwrap();
+        }
+
+        let mut batch = WriteBatch::default();
+        index_addresses_by_height(&storage, &mut batch, 2).unwrap();
+        storage.db().write(batch).unwrap();
+        let ids = |address| storage.get_transactions_by_address(&address).unwrap().into_iter().map(|l| l.tx_id).collect::<Vec<_>>();
+        let (open_id, claim_id) = (open.id().unwrap(), claim.id().unwrap());
+        assert_eq!(ids(bob), vec![open_id, claim_id]);
+        assert_eq!(ids(refund), vec![open_id, claim_id]);
+    }
+}
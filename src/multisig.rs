@@ -0,0 +1,160 @@
+use crate::types::{Address, PublicKey, Signature};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use thiserror::Error;
+
+/// Upper bound on the number of keys in a policy, which keeps cosignature indices in a `u8`
+/// and bounds verification work per transaction.
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+/// Domain separator for multisig addresses, so they can never collide with the hash of a
+/// single public key.
+const MULTISIG_ADDRESS_DOMAIN: &[u8] = b"rustchain/multisig/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MultisigError {
+    #[error("A multisig policy needs at least one key")]
+    NoKeys,
+    #[error("A multisig policy can hold at most {max} keys, got {got}")]
+    TooManyKeys { got: usize, max: usize },
+    #[error("Threshold {threshold} must be between 1 and the number of keys ({keys})")]
+    InvalidThreshold { threshold: u8, keys: usize },
+    #[error("Public key {0} appears more than once")]
+    DuplicateKey(String),
+    #[error("Public keys must be sorted")]
+    UnsortedKeys,
+}
+
+/// An M-of-N authorization policy. The account address commits to both the threshold and
+/// the full key set, so neither can be changed without moving to a new address.
+///
+/// Keys are kept sorted by their bytes, so the same set always yields the same address
+/// regardless of the order the keys were supplied in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct MultisigPolicy {
+    threshold: u8,
+    public_keys: Vec<PublicKey>,
+}
+
+impl MultisigPolicy {
+    /// Builds a policy requiring `threshold` signatures out of `public_keys`.
+    pub fn new(threshold: u8, mut public_keys: Vec<PublicKey>) -> Result<Self, MultisigError> {
+        public_keys.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+        if let Some(pair) = public_keys.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MultisigError::DuplicateKey(pair[0].to_string()));
+        }
+        let policy = MultisigPolicy { threshold, public_keys };
+        policy.check()?;
+        Ok(policy)
+    }
+
+    /// Checks the invariants `new` establishes. Policies decoded from the network have not
+    /// been through `new`, so transaction validation calls this explicitly.
+    pub fn check(&self) -> Result<(), MultisigError> {
+        let keys = self.public_keys.len();
+        if keys == 0 {
+            return Err(MultisigError::NoKeys);
+        }
+        if keys > MAX_MULTISIG_KEYS {
+            return Err(MultisigError::TooManyKeys { got: keys, max: MAX_MULTISIG_KEYS });
+        }
+        if self.threshold == 0 || self.threshold as usize > keys {
+            return Err(MultisigError::InvalidThreshold { threshold: self.threshold, keys });
+        }
+        for pair in self.public_keys.windows(2) {
+            match pair[0].0.as_bytes().cmp(pair[1].0.as_bytes()) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => return Err(MultisigError::DuplicateKey(pair[0].to_string())),
+                std::cmp::Ordering::Greater => return Err(MultisigError::UnsortedKeys),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    pub fn public_keys(&self) -> &[PublicKey] {
+        &self.public_keys
+    }
+
+    /// Position of `public_key` in the policy, used as the index of its cosignature.
+    pub fn key_index(&self, public_key: &PublicKey) -> Option<u8> {
+        self.public_keys.iter().position(|key| key == public_key).map(|index| index as u8)
+    }
+
+    /// The account address controlled by this policy:
+    /// `sha256(domain || threshold || key_1 || .. || key_n)`.
+    pub fn address(&self) -> Address {
+        let mut hasher = Sha256::new();
+        hasher.update(MULTISIG_ADDRESS_DOMAIN);
+        hasher.update([self.threshold]);
+        for key in &self.public_keys {
+            hasher.update(key.0.as_bytes());
+        }
+        Address(hasher.finalize().into())
+    }
+}
+
+impl fmt::Display for MultisigPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-of-{} multisig {}", self.threshold, self.public_keys.len(), self.address())
+    }
+}
+
+/// A signature from the key at `key_index` in the sender's `MultisigPolicy`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct Cosignature {
+    pub key_index: u8,
+    pub signature: Signature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    fn keys(n: usize) -> Vec<PublicKey> {
+        (0..n).map(|_| PublicKey(SigningKey::generate(&mut OsRng).verifying_key())).collect()
+    }
+
+    #[test]
+    fn test_address_commits_to_keys_and_threshold() {
+        let keys = keys(3);
+        let policy = MultisigPolicy::new(2, keys.clone()).unwrap();
+
+        let mut reordered = keys.clone();
+        reordered.reverse();
+        assert_eq!(MultisigPolicy::new(2, reordered).unwrap().address(), policy.address());
+
+        assert_ne!(MultisigPolicy::new(3, keys.clone()).unwrap().address(), policy.address());
+        assert_ne!(MultisigPolicy::new(2, keys[..2].to_vec()).unwrap().address(), policy.address());
+        assert_ne!(
+            MultisigPolicy::new(1, vec![keys[0]]).unwrap().address(),
+            crate::types::address_from_public_key(&keys[0])
+        );
+    }
+
+    #[test]
+    fn test_invalid_policies_are_rejected() {
+        let keys = keys(2);
+        assert_eq!(MultisigPolicy::new(1, vec![]), Err(MultisigError::NoKeys));
+        assert_eq!(
+            MultisigPolicy::new(3, keys.clone()),
+            Err(MultisigError::InvalidThreshold { threshold: 3, keys: 2 })
+        );
+        assert_eq!(
+            MultisigPolicy::new(0, keys.clone()),
+            Err(MultisigError::InvalidThreshold { threshold: 0, keys: 2 })
+        );
+        assert_eq!(MultisigPolicy::new(1, vec![keys[0], keys[0]]), Err(MultisigError::DuplicateKey(keys[0].to_string())));
+
+        let mut policy = MultisigPolicy::new(1, keys).unwrap();
+        policy.public_keys.reverse();
+        assert_eq!(policy.check(), Err(MultisigError::UnsortedKeys));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::block::Block;
+use crate::snapshot::{SnapshotChunk, SnapshotManifest};
 use crate::transaction::Transaction;
 use crate::types::Hash;
 
@@ -27,7 +28,14 @@ pub use libp2p::gossipsub::IdentTopic as Topic;
 
 const TRANSACTION_TOPIC: &str = "transactions";
 const BLOCK_TOPIC: &str = "blocks";
+/// Gossip topic carrying state snapshot requests, manifests and chunks.
+pub const SNAPSHOT_TOPIC: &str = "snapshots";
+/// Gossip topic carrying block sync requests and responses.
+pub const SYNC_TOPIC: &str = "sync";
 const SYNC_PROTOCOL: &str = "/rustchain/sync/1.0.0";
+/// How long a light peer stays connected after publishing, so the message is sent out
+/// before its connections close.
+const PUBLISH_LINGER: Duration = Duration::from_secs(2);
 
 /// Configuration for the NetworkService.
 #[derive(Debug, Clone)]
@@ -49,7 +57,7 @@ impl Default for NetworkConfig {
 /// Messages that nodes can send to each other over the network.
 #[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode)]
 pub enum NetworkMessage {
-    NewTransaction(Transaction),
+    NewTransaction(Box<Transaction>),
     NewBlock(Block),
     SyncRequest { 
         from_height: u64, 
@@ -59,6 +67,18 @@ pub enum NetworkMessage {
         blocks: Vec<Block> 
     },
     SyncResponseNoBlocks,
+    /// Asks peers to advertise a snapshot of their latest state.
+    SnapshotRequest,
+    /// Describes a snapshot a peer is able to serve.
+    SnapshotManifest(SnapshotManifest),
+    SnapshotChunkRequest {
+        block_hash: Hash,
+        index: u32,
+    },
+    SnapshotChunk {
+        block_hash: Hash,
+        chunk: SnapshotChunk,
+    },
 }
 
 /// Sync request/response types for libp2p request-response protocol
@@ -122,6 +142,8 @@ pub enum NetworkError {
     CommandSendError(String),
     #[error("Transport build error: {0}")]
     TransportBuildError(String),
+    #[error("Failed to publish message: {0}")]
+    PublishError(String),
 }
 
 /// The NetworkService handles all peer-to-peer communication for the node.
@@ -145,7 +167,7 @@ pub enum NetworkCommand {
         message: NetworkMessage 
     },
     BroadcastBlock(Block),
-    BroadcastTransaction(Transaction),
+    BroadcastTransaction(Box<Transaction>),
 }
 
 impl NetworkService {
@@ -161,45 +183,12 @@ impl NetworkService {
         let transaction_topic = IdentTopic::new(TRANSACTION_TOPIC);
         let block_topic = IdentTopic::new(BLOCK_TOPIC);
 
-        let transport = TokioTcpTransport::new(libp2p::tcp::Config::default().nodelay(true))
-            .upgrade(libp2p::core::upgrade::Version::V1Lazy)
-            .authenticate(libp2p::noise::Config::new(&local_keypair).map_err(|e| NetworkError::TransportBuildError(format!("Noise config error: {:?}",e)))?)
-            .multiplex(libp2p::yamux::Config::default())
-            .timeout(std::time::Duration::from_secs(20))
-            .boxed();
-
-        let message_id_fn = |message: &gossipsub::Message| {
-            let mut s = DefaultHasher::new();
-            message.data.hash(&mut s);
-            gossipsub::MessageId::from(s.finish().to_string())
-        };
-
-        let gossipsub_config = gossipsub::ConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(10))
-            .validation_mode(ValidationMode::Strict)
-            .message_id_fn(message_id_fn)
-            .build()
-            .map_err(|e| NetworkError::SwarmBuildError(format!("Failed to build gossipsub config: {}", e)))?;
-
-        let mut gossipsub = gossipsub::Behaviour::new(
-            MessageAuthenticity::Signed(local_keypair.clone()),
-            gossipsub_config,
-        ).map_err(|e| NetworkError::SwarmBuildError(format!("Failed to build gossipsub: {}", e.to_string())))?;
-        
+        let mut swarm = build_swarm(&local_keypair)?;
+        let gossipsub = &mut swarm.behaviour_mut().gossipsub;
         gossipsub.subscribe(&transaction_topic).map_err(|e| NetworkError::SwarmBuildError(format!("Failed to subscribe to transaction topic: {}", e)))?;
         gossipsub.subscribe(&block_topic).map_err(|e| NetworkError::SwarmBuildError(format!("Failed to subscribe to block topic: {}", e)))?;
-
-        let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
-            .map_err(|e| NetworkError::SwarmBuildError(format!("Failed to create mDNS: {}",e)))?;
-        
-        let behaviour = RustchainNetworkBehaviour { 
-            gossipsub, 
-            mdns,
-        };
-
-        // Using direct Swarm::new with swarm::Config
-        let swarm_network_config = SwarmNetworkConfig::with_tokio_executor();
-        let swarm = Swarm::new(transport, behaviour, local_peer_id, swarm_network_config);
+        gossipsub.subscribe(&IdentTopic::new(SNAPSHOT_TOPIC)).map_err(|e| NetworkError::SwarmBuildError(format!("Failed to subscribe to snapshot topic: {}", e)))?;
+        gossipsub.subscribe(&IdentTopic::new(SYNC_TOPIC)).map_err(|e| NetworkError::SwarmBuildError(format!("Failed to subscribe to sync topic: {}", e)))?;
         
         let (command_sender, command_receiver) = mpsc::channel(100);
 
@@ -226,7 +215,7 @@ impl NetworkService {
 
     /// Broadcasts a transaction to the network via gossipsub.
     pub async fn broadcast_transaction(&self, transaction: Transaction) {
-        let network_message = NetworkMessage::NewTransaction(transaction);
+        let network_message = NetworkMessage::NewTransaction(Box::new(transaction));
         let command = NetworkCommand::BroadcastMessage {
             topic: self.transaction_topic.clone(),
             message: network_message,
@@ -278,7 +267,14 @@ impl NetworkService {
                             let bincode_cfg = bincode::config::standard();
                             match bincode::decode_from_slice(&message.data, bincode_cfg) { 
                                 Ok((network_message, _len)) => { 
-                                    if let Err(e) = self.incoming_message_sender.send(network_message).await {
+                                    // Light peers publish straight to the topic, so nothing checked the signatures yet
+                                    let invalid = match &network_message {
+                                        NetworkMessage::NewTransaction(transaction) => transaction.validate().err(),
+                                        _ => None,
+                                    };
+                                    if let Some(e) = invalid {
+                                        warn!("Dropping invalid gossiped transaction: {}", e);
+                                    } else if let Err(e) = self.incoming_message_sender.send(network_message).await {
                                         error!("Failed to send incoming message to handler: {}", e);
                                     }
                                 }
@@ -392,6 +388,115 @@ impl NetworkService {
     }
 }
 
+/// Builds the transport and behaviour every RustChain peer uses. Messages are only accepted
+/// between peers with the same gossipsub configuration, so nodes and light peers share it.
+fn build_swarm(local_keypair: &identity::Keypair) -> Result<Swarm<RustchainNetworkBehaviour>, NetworkError> {
+    let local_peer_id = Libp2pPeerId::from(local_keypair.public());
+    let transport = TokioTcpTransport::new(libp2p::tcp::Config::default().nodelay(true))
+        .upgrade(libp2p::core::upgrade::Version::V1Lazy)
+        .authenticate(libp2p::noise::Config::new(local_keypair).map_err(|e| NetworkError::TransportBuildError(format!("Noise config error: {:?}",e)))?)
+        .multiplex(libp2p::yamux::Config::default())
+        .timeout(std::time::Duration::from_secs(20))
+        .boxed();
+
+    // Identical messages from different peers are distinct: two nodes asking for the same
+    // blocks must both be answered
+    let message_id_fn = |message: &gossipsub::Message| {
+        let mut s = DefaultHasher::new();
+        message.source.hash(&mut s);
+        message.data.hash(&mut s);
+        gossipsub::MessageId::from(s.finish().to_string())
+    };
+
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(Duration::from_secs(10))
+        .validation_mode(ValidationMode::Strict)
+        .message_id_fn(message_id_fn)
+        .build()
+        .map_err(|e| NetworkError::SwarmBuildError(format!("Failed to build gossipsub config: {}", e)))?;
+
+    let gossipsub = gossipsub::Behaviour::new(
+        MessageAuthenticity::Signed(local_keypair.clone()),
+        gossipsub_config,
+    ).map_err(|e| NetworkError::SwarmBuildError(format!("Failed to build gossipsub: {}", e.to_string())))?;
+
+    let mdns = mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
+        .map_err(|e| NetworkError::SwarmBuildError(format!("Failed to create mDNS: {}",e)))?;
+    
+    let behaviour = RustchainNetworkBehaviour { 
+        gossipsub, 
+        mdns,
+    };
+
+    // Using direct Swarm::new with swarm::Config
+    let swarm_network_config = SwarmNetworkConfig::with_tokio_executor();
+    Ok(Swarm::new(transport, behaviour, local_peer_id, swarm_network_config))
+}
+
+/// Joins the network as a light peer, publishes `transaction` on the transaction topic and
+/// disconnects. Bootstrap peers are dialed, and peers on the local network are found over
+/// mDNS. Returns the number of peers it was sent to, or an error when no peer subscribed to
+/// the topic within `timeout`.
+pub async fn publish_transaction(
+    bootstrap_peers: &[Multiaddr],
+    transaction: Transaction,
+    timeout: Duration,
+) -> Result<usize, NetworkError> {
+    let mut swarm = build_swarm(&identity::Keypair::generate_ed25519())?;
+    let transaction_topic = IdentTopic::new(TRANSACTION_TOPIC);
+    swarm.behaviour_mut().gossipsub.subscribe(&transaction_topic)
+        .map_err(|e| NetworkError::SwarmBuildError(format!("Failed to subscribe to transaction topic: {}", e)))?;
+    for peer_addr in bootstrap_peers {
+        info!("Dialing bootstrap peer: {}", peer_addr);
+        swarm.dial(peer_addr.clone())?;
+    }
+
+    // Peers announce their subscriptions right after connecting
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let event = tokio::time::timeout_at(deadline, swarm.select_next_some()).await.map_err(|_| {
+            NetworkError::PublishError(format!("no peer subscribed to `{}` within {} seconds", TRANSACTION_TOPIC, timeout.as_secs()))
+        })?;
+        match event {
+            SwarmEvent::Behaviour(RustchainNetworkEvent::Gossipsub(gossipsub::Event::Subscribed { peer_id, topic }))
+                if topic == transaction_topic.hash() =>
+            {
+                debug!("Peer {} subscribed to {}", peer_id, topic);
+                break;
+            }
+            SwarmEvent::Behaviour(RustchainNetworkEvent::Mdns(mdns::Event::Discovered(list))) => {
+                for (peer_id, multiaddr) in list {
+                    info!("mDNS discovered peer: {} at {}", peer_id, multiaddr);
+                    if let Err(e) = swarm.dial(multiaddr) {
+                        warn!("Failed to dial {}: {}", peer_id, e);
+                    }
+                }
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                warn!("Failed to dial {:?}: {}", peer_id, error);
+            }
+            _ => {}
+        }
+    }
+
+    let encoded_message = bincode::encode_to_vec(NetworkMessage::NewTransaction(Box::new(transaction)), bincode::config::standard())
+        .map_err(|e| NetworkError::SerializationError(e.to_string()))?;
+    swarm.behaviour_mut().gossipsub.publish(transaction_topic.clone(), encoded_message)
+        .map_err(|e| NetworkError::PublishError(e.to_string()))?;
+    let peers = swarm.behaviour().gossipsub.all_peers()
+        .filter(|(_, topics)| topics.contains(&&transaction_topic.hash()))
+        .count();
+
+    // Keep polling until the message has been written out to the connections
+    let _ = tokio::time::timeout(PUBLISH_LINGER, async {
+        loop {
+            swarm.select_next_some().await;
+        }
+    })
+    .await;
+    Ok(peers)
+}
+
 // Basic tests (more comprehensive tests will require running multiple instances or mocking)
 #[cfg(test)]
 mod tests {
@@ -419,6 +524,31 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_light_peer_publishes_transaction() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let node_address: Multiaddr = format!("/ip4/127.0.0.1/tcp/{}", port).parse().unwrap();
+        let config = NetworkConfig { listen_address: node_address.clone(), bootstrap_peers: Vec::new() };
+        let (incoming_sender, mut incoming_receiver) = mpsc::channel(10);
+        let (service, _command_sender) = NetworkService::new(config, generate_keypair(), incoming_sender).await.unwrap();
+        tokio::spawn(service.run());
+        sleep(Duration::from_millis(200)).await;
+
+        let wallet = crate::wallet::Wallet::new();
+        let unsigned = Transaction::new(*wallet.public_key(), Address([1u8; 32]), 5, Nonce(0), TypesSignature(vec![]));
+        let transaction = wallet.sign_transaction(unsigned.clone()).unwrap();
+
+        // The unsigned copy reaches the node but is dropped before the mempool sees it
+        let reached = publish_transaction(std::slice::from_ref(&node_address), unsigned, Duration::from_secs(10)).await.unwrap();
+        assert!(reached >= 1);
+        let reached = publish_transaction(&[node_address], transaction.clone(), Duration::from_secs(10)).await.unwrap();
+        assert!(reached >= 1);
+
+        let received = tokio::time::timeout(Duration::from_secs(10), incoming_receiver.recv()).await.unwrap();
+        assert!(matches!(received, Some(NetworkMessage::NewTransaction(tx)) if *tx == transaction));
+        assert!(tokio::time::timeout(Duration::from_secs(1), incoming_receiver.recv()).await.is_err());
+    }
+
     #[test]
     fn test_network_message_serialization() {
         let keypair = generate_keypair();
@@ -450,14 +580,8 @@ mod tests {
 
         let signature_bytes: Vec<u8> = keypair.sign(b"test_tx_data").expect("Signing failed").to_vec();
 
-        let transaction = Transaction {
-            sender: PublicKey(ed25519_public_key),
-            recipient: recipient_address,
-            amount: 100,
-            nonce: Nonce(1),
-            signature: crate::types::Signature(signature_bytes),
-        };
-        let network_msg_tx = NetworkMessage::NewTransaction(transaction.clone());
+        let transaction = Transaction::new(PublicKey(ed25519_public_key), recipient_address, 100, Nonce(1), crate::types::Signature(signature_bytes));
+        let network_msg_tx = NetworkMessage::NewTransaction(Box::new(transaction.clone()));
 
         let bincode_cfg = bincode::config::standard();
         let serialized_tx = bincode::encode_to_vec(&network_msg_tx, bincode_cfg)
@@ -467,7 +591,7 @@ mod tests {
                 .expect("Failed to deserialize transaction message");
 
         match deserialized_tx {
-            NetworkMessage::NewTransaction(dtx) => assert_eq!(dtx, transaction),
+            NetworkMessage::NewTransaction(dtx) => assert_eq!(*dtx, transaction),
             _ => panic!("Deserialized to wrong message type"),
         }
 
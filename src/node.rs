@@ -0,0 +1,1067 @@
+//! Wires the consensus engine, state machine, mempool, storage and networking together
+//! into a running node.
+
+use crate::block::{calculate_merkle_root, Block, BlockHeader};
+use crate::block_signer::{read_secret, BlockSigner, LocalSigner, RemoteSigner};
+use crate::chain_store::ChainStore;
+use crate::consensus::ConsensusEngine;
+use crate::genesis::{Genesis, GenesisData};
+use crate::mempool::{Mempool, MempoolConfig};
+use crate::networking::{Libp2pPeerId, NetworkConfig, NetworkMessage, NetworkService};
+use crate::rpc::{RpcServer, DEFAULT_RPC_LISTEN_ADDR};
+use crate::snapshot::{SnapshotDownload, StateSnapshot, DEFAULT_SNAPSHOT_CHUNK_BYTES};
+use crate::state_machine::StateMachine;
+use crate::storage::{PruningMode, StorageError};
+use crate::types::{BlockHeight, Hash, Signature, Timestamp};
+use crate::wallet::address_from_public_key;
+use libp2p::{identity, Multiaddr};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+
+/// Node configuration structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeConfiguration {
+    /// Network configuration
+    pub network: NodeNetworkConfig,
+    /// Storage configuration
+    pub storage: NodeStorageConfig,
+    /// Consensus configuration
+    pub consensus: NodeConsensusConfig,
+    /// Validator configuration (optional)
+    pub validator: Option<NodeValidatorConfig>,
+    /// Chain synchronization configuration
+    #[serde(default)]
+    pub sync: NodeSyncConfig,
+    /// JSON RPC server configuration
+    #[serde(default)]
+    pub rpc: NodeRpcConfig,
+    /// Genesis file path
+    pub genesis_file: Option<String>,
+    /// Accept unchecksummed hex addresses in the genesis file and RPC requests
+    #[serde(default)]
+    pub legacy_hex_addresses: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeNetworkConfig {
+    /// Port to listen on for P2P connections
+    pub listen_port: u16,
+    /// Listen address for P2P connections
+    pub listen_addr: String,
+    /// List of bootstrap peer addresses
+    pub bootstrap_peers: Vec<String>,
+    /// Maximum number of peers
+    pub max_peers: usize,
+}
+
+impl NodeNetworkConfig {
+    /// The libp2p listen address and bootstrap peers. Port 0 picks a free port.
+    pub fn network_config(&self) -> anyhow::Result<NetworkConfig> {
+        let listen_address: Multiaddr = format!("/ip4/{}/tcp/{}", self.listen_addr, self.listen_port).parse()
+            .map_err(|e| anyhow::anyhow!("Invalid listen address {}:{}: {}", self.listen_addr, self.listen_port, e))?;
+        let bootstrap_peers = self.bootstrap_peers.iter()
+            .map(|peer| peer.parse().map_err(|e| anyhow::anyhow!("Invalid bootstrap peer {}: {}", peer, e)))
+            .collect::<anyhow::Result<Vec<Multiaddr>>>()?;
+        Ok(NetworkConfig { listen_address, bootstrap_peers })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeStorageConfig {
+    /// Database directory path
+    pub db_path: String,
+    /// Whether to create database if it doesn't exist
+    pub create_if_missing: bool,
+    /// Which historical block bodies to keep: "archive", "state_only" or { keep_last = N }
+    #[serde(default)]
+    pub pruning: PruningMode,
+    /// How often the background pruning task runs, in seconds
+    #[serde(default = "default_prune_interval_secs")]
+    pub prune_interval_secs: u64,
+}
+
+fn default_prune_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeConsensusConfig {
+    /// Block production interval in seconds
+    pub block_interval: u64,
+    /// Maximum transactions per block
+    pub max_txs_per_block: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeSyncConfig {
+    /// Bootstrap a fresh node from a peer's state snapshot instead of replaying from genesis.
+    /// Trusted peers only: block headers do not commit to a state root, so the imported
+    /// balances are whatever the serving peer claims
+    pub fast_sync: bool,
+    /// Largest encoded size, in bytes, of a snapshot chunk served to peers. Each chunk is one
+    /// gossip message, so this must stay below gossipsub's 64 KiB limit
+    pub snapshot_chunk_bytes: usize,
+}
+
+impl Default for NodeSyncConfig {
+    fn default() -> Self {
+        Self {
+            fast_sync: false,
+            snapshot_chunk_bytes: DEFAULT_SNAPSHOT_CHUNK_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeRpcConfig {
+    /// Whether to serve the line-delimited JSON RPC interface
+    pub enabled: bool,
+    /// Socket address the RPC server listens on
+    pub listen_addr: String,
+}
+
+impl Default for NodeRpcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_addr: DEFAULT_RPC_LISTEN_ADDR.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeValidatorConfig {
+    /// Path to validator private key file. Not read when `remote_signer` is set
+    #[serde(default)]
+    pub private_key_path: String,
+    /// Whether this node should act as a validator
+    pub enabled: bool,
+    /// Address of a `rustchain-signer` holding the validator key, e.g. 127.0.0.1:9940 or
+    /// unix:/run/rustchain/signer.sock
+    #[serde(default)]
+    pub remote_signer: Option<String>,
+    /// File holding the secret shared with the remote signer
+    #[serde(default)]
+    pub remote_signer_secret_path: Option<String>,
+}
+
+impl Default for NodeConfiguration {
+    fn default() -> Self {
+        Self {
+            network: NodeNetworkConfig {
+                listen_port: 9000,
+                listen_addr: "127.0.0.1".to_string(),
+                bootstrap_peers: Vec::new(),
+                max_peers: 50,
+            },
+            storage: NodeStorageConfig {
+                db_path: "rustchain_db".to_string(),
+                create_if_missing: true,
+                pruning: PruningMode::Archive,
+                prune_interval_secs: default_prune_interval_secs(),
+            },
+            consensus: NodeConsensusConfig {
+                block_interval: 5,
+                max_txs_per_block: 10,
+            },
+            validator: None,
+            sync: NodeSyncConfig::default(),
+            rpc: NodeRpcConfig::default(),
+            genesis_file: None,
+            legacy_hex_addresses: false,
+        }
+    }
+}
+
+impl NodeConfiguration {
+    /// Loads the configuration from a TOML file, or the defaults when no file is given.
+    pub fn load(config_path: Option<&str>) -> anyhow::Result<Self> {
+        let Some(path) = config_path else {
+            return Ok(NodeConfiguration::default());
+        };
+        tracing::info!("Loading configuration from: {}", path);
+        let config_content = fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config file: {}", e))?;
+        let parsed_config = toml::from_str::<NodeConfiguration>(&config_content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse config TOML: {}", e))?;
+        tracing::info!("Loaded config genesis_file: {:?}", parsed_config.genesis_file);
+        Ok(parsed_config)
+    }
+}
+
+/// Initialize genesis state from a validated genesis
+async fn initialize_genesis_state<S: ChainStore>(
+    genesis: &Genesis,
+    storage: &Arc<Mutex<S>>,
+    state_machine: &Arc<Mutex<StateMachine>>,
+) -> anyhow::Result<()> {
+    tracing::info!("Initializing genesis state...");
+
+    // Set initial account balances
+    let mut state_machine_lock = state_machine.lock().await;
+    for (address, account) in &genesis.accounts {
+        state_machine_lock.set_account(*address, account.clone())
+            .map_err(|e| anyhow::anyhow!("Invalid genesis balance for {}: {}", address, e))?;
+        match account.vesting {
+            Some(schedule) => tracing::info!("Genesis account: {} -> balance: {}, vesting: {:?}", address, account.balance, schedule),
+            None => tracing::info!("Genesis account: {} -> balance: {}", address, account.balance),
+        }
+    }
+
+    // Create genesis block
+    let genesis_block = genesis.block()?;
+    tracing::info!("Created genesis block with hash: {}", genesis_block.header.calculate_hash()?);
+
+    // Store genesis block, header index, initial accounts and tip in a single batch
+    let storage_lock = storage.lock().await;
+    storage_lock.commit_block(&genesis_block, &state_machine_lock.world_state)
+        .map_err(|e| anyhow::anyhow!("Failed to store genesis state: {}", e))?;
+    drop(storage_lock);
+    drop(state_machine_lock);
+
+    tracing::info!("Genesis state initialized successfully!");
+    Ok(())
+}
+
+// Main entry point needs to be async if we call async functions directly within it.
+// Or, we can keep main sync and use a tokio runtime builder if needed for more control.
+
+/// The validator's signer: the remote signer when one is configured, otherwise the local key file.
+fn connect_block_signer(validator_config: &NodeValidatorConfig) -> anyhow::Result<Arc<dyn BlockSigner>> {
+    let Some(remote_signer) = &validator_config.remote_signer else {
+        tracing::info!("Loading validator key from: {}", validator_config.private_key_path);
+        let wallet = crate::wallet::Wallet::load_from_file(&validator_config.private_key_path)
+            .map_err(|e| anyhow::anyhow!("Failed to load validator key: {}", e))?;
+        return Ok(Arc::new(LocalSigner::new(wallet)));
+    };
+    let secret_path = validator_config.remote_signer_secret_path.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("remote_signer is set but remote_signer_secret_path is not"))?;
+    let secret = read_secret(Path::new(secret_path))
+        .map_err(|e| anyhow::anyhow!("Failed to read the remote signer secret from {}: {}", secret_path, e))?;
+    let signer = RemoteSigner::connect(remote_signer.parse()?, secret)
+        .map_err(|e| anyhow::anyhow!("Failed to connect to remote signer {}: {}", remote_signer, e))?;
+    tracing::info!("Signing blocks through remote signer {} (validator {})", signer.address(), signer.public_key());
+    Ok(Arc::new(signer))
+}
+
+/// Most blocks sent in answer to a single sync request.
+const MAX_SYNC_BLOCKS: u64 = 50;
+
+/// Where a received block stands relative to our chain tip.
+enum NextBlock {
+    /// The block builds on our tip.
+    Extends,
+    /// The block is past the next height, so the blocks from `from_height` are missing.
+    Ahead { from_height: u64 },
+    /// The block is at or below our tip, or does not build on it.
+    Stale,
+}
+
+fn next_block_status<S: ChainStore>(storage: &S, block: &Block) -> Result<NextBlock, StorageError> {
+    let Some((tip_hash, tip_height)) = storage.get_chain_tip()? else {
+        return Ok(NextBlock::Stale);
+    };
+    let height = block.header.block_number.0;
+    Ok(if height == tip_height + 1 && block.header.parent_hash == tip_hash {
+        NextBlock::Extends
+    } else if height > tip_height + 1 {
+        NextBlock::Ahead { from_height: tip_height + 1 }
+    } else {
+        NextBlock::Stale
+    })
+}
+
+/// Asks peers for their blocks from `from_height` onwards.
+async fn request_blocks(network_sender: &mpsc::Sender<crate::networking::NetworkCommand>, from_height: u64) {
+    if let Err(e) = network_sender.send(crate::networking::NetworkCommand::BroadcastMessage {
+        topic: crate::networking::Topic::new(crate::networking::SYNC_TOPIC),
+        message: NetworkMessage::SyncRequest { from_height, to_hash: None },
+    }).await {
+        tracing::error!("Failed to send sync request from height {}: {}", from_height, e);
+    } else {
+        tracing::info!("Requested blocks from height {}", from_height);
+    }
+}
+
+/// A node started by `Node::start`. Its tasks run in the background until it is dropped.
+pub struct Node<S: ChainStore> {
+    storage: Arc<Mutex<S>>,
+    state_machine: Arc<Mutex<StateMachine>>,
+    mempool: Arc<Mutex<Mempool>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl<S: ChainStore> Node<S> {
+    /// Starts a node on top of any `ChainStore`, so the same node logic can run against
+    /// RocksDB or against an in-memory store, and several nodes can share a process.
+    pub async fn start(config: NodeConfiguration, storage: S) -> anyhow::Result<Self> {
+        tracing::info!("Starting RustChain node with configuration: {:?}", config);
+
+        // Set up block signing, with the configured key file or remote signer
+        let block_signer: Arc<dyn BlockSigner> = match &config.validator {
+            Some(validator_config) if validator_config.enabled => connect_block_signer(validator_config)?,
+            Some(_) => {
+                tracing::info!("Validator mode disabled, creating dummy wallet");
+                Arc::new(LocalSigner::new(crate::wallet::Wallet::new()))
+            }
+            None => {
+                tracing::info!("No validator configuration, creating dummy wallet");
+                Arc::new(LocalSigner::new(crate::wallet::Wallet::new()))
+            }
+        };
+
+        // 1. Load and validate the genesis configuration
+        let genesis_data = if let Some(ref genesis_path) = config.genesis_file {
+            tracing::info!("Loading genesis from file: {}", genesis_path);
+            GenesisData::load(Path::new(genesis_path))
+                .map_err(|e| anyhow::anyhow!("Failed to load genesis file {}: {}", genesis_path, e))?
+        } else {
+            tracing::info!("No genesis file specified, using a development genesis with this node as the only validator");
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            GenesisData::development(block_signer.public_key(), timestamp)
+        };
+        let genesis = genesis_data.validate(config.legacy_hex_addresses)
+            .map_err(|e| anyhow::anyhow!("Invalid genesis: {}", e))?;
+        tracing::info!("Genesis loaded with {} validators and {} initial accounts, block hash {}",
+            genesis.validators.len(),
+            genesis.accounts.len(),
+            genesis.block_hash()?
+        );
+
+        // 2. Share the storage between tasks
+        let storage = Arc::new(Mutex::new(storage));
+
+        // 3. Repair any inconsistency left by an interrupted write, then check if genesis needs to be initialized
+        let needs_genesis = {
+            let storage_lock = storage.lock().await;
+            for repair in storage_lock.check_integrity()
+                .map_err(|e| anyhow::anyhow!("Storage integrity check failed: {}", e))? {
+                tracing::warn!("Storage integrity check: {}", repair);
+            }
+            match storage_lock.get_chain_tip() {
+                Ok(None) => {
+                    tracing::info!("No chain tip found, initializing genesis block");
+                    true
+                }
+                Ok(Some((_, height))) => {
+                    tracing::info!("Existing chain found with height: {}", height);
+                    false
+                }
+                Err(e) => {
+                    tracing::warn!("Error checking chain tip: {}. Assuming fresh database.", e);
+                    true
+                }
+            }
+        };
+
+        // 4. Initialize StateMachine with genesis state, or with the state persisted by an earlier run
+        let state_machine = if needs_genesis {
+            let state_machine = Arc::new(Mutex::new(StateMachine::new()));
+            initialize_genesis_state(&genesis, &storage, &state_machine).await?;
+            state_machine
+        } else {
+            let world_state = storage.lock().await.load_world_state()
+                .map_err(|e| anyhow::anyhow!("Failed to load world state: {}", e))?;
+            let state_machine = StateMachine::from_world_state(world_state)
+                .map_err(|e| anyhow::anyhow!("Invalid stored world state: {}", e))?;
+            // Transactions only move balances around, so the supply restored from the stored
+            // accounts is the genesis supply unless the store is damaged
+            tracing::info!("Loaded {} accounts from storage with total supply {}",
+                state_machine.world_state.len(), state_machine.total_supply());
+            if state_machine.total_supply() != genesis.total_supply {
+                tracing::warn!("Stored total supply {} differs from the genesis supply {}",
+                    state_machine.total_supply(), genesis.total_supply);
+            }
+            Arc::new(Mutex::new(state_machine))
+        };
+        tracing::info!("StateMachine initialized.");
+
+        // 5. Initialize Mempool
+        let mempool_config = MempoolConfig::default();
+        let mempool = Arc::new(Mutex::new(Mempool::new(mempool_config)));
+        tracing::info!("Mempool initialized with capacity: {}", mempool_config.max_transactions);
+
+        // 6. Initialize ConsensusEngine with the genesis validators
+        let validator_public_keys = genesis.validators.clone();
+        for (i, public_key) in validator_public_keys.iter().enumerate() {
+            tracing::info!("Genesis validator {}: {} -> derived address: {}", i, public_key, address_from_public_key(public_key));
+        }
+
+        let consensus_engine = Arc::new(Mutex::new(ConsensusEngine::new(validator_public_keys.clone())));
+        tracing::info!(
+            "ConsensusEngine initialized with {} validator(s). Our validator address: {}", 
+            validator_public_keys.len(),
+            address_from_public_key(block_signer.public_key())
+        );
+
+        // 7. Initialize NetworkConfig
+        let network_config = config.network.network_config()?;
+        tracing::info!("NetworkConfig: {:?}", network_config);
+
+        // 8. Generate Node Identity (Keypair)
+        let local_keypair = identity::Keypair::generate_ed25519();
+        let local_peer_id = Libp2pPeerId::from(local_keypair.public());
+        tracing::info!("Generated local Peer ID: {}", local_peer_id);
+
+        // 9. Create MPSC channel for incoming network messages
+        let (incoming_message_sender, mut incoming_message_receiver) = mpsc::channel::<NetworkMessage>(128);
+
+        // 10. Instantiate NetworkService
+        tracing::info!("Initializing NetworkService...");
+        let (network_service, network_command_sender) = 
+            NetworkService::new(network_config.clone(), local_keypair, incoming_message_sender).await
+            .map_err(|e| anyhow::anyhow!("Failed to create NetworkService: {}", e))?;
+        tracing::info!("NetworkService initialized.");
+
+        // 11. Spawn NetworkService::run() as a Tokio task
+        let mut tasks = vec![tokio::spawn(async move {
+            if let Err(e) = network_service.run().await {
+                tracing::error!("NetworkService stopped: {}", e);
+            }
+        })];
+
+        // 12. Initial chain synchronization - request missing blocks from peers
+        let sync_storage = storage.clone();
+        let sync_network_sender = network_command_sender.clone();
+        // A node that only has its genesis block may bootstrap from a peer's snapshot.
+        let awaiting_snapshot = config.sync.fast_sync && needs_genesis;
+        if awaiting_snapshot {
+            tracing::warn!("Fast sync trusts the state served by peers; only use it when every reachable peer is trusted");
+        }
+        
+        tasks.push(tokio::spawn(async move {
+            // Wait a bit for network to connect to peers
+            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            
+            tracing::info!("Starting initial chain synchronization...");
+            
+            // Get our current chain tip
+            let storage_lock = sync_storage.lock().await;
+            let (current_tip_hash, current_height) = match storage_lock.get_chain_tip() {
+                Ok(Some((hash, height))) => {
+                    tracing::info!("Current chain height: {}", height);
+                    (hash, height)
+                }
+                Ok(None) => {
+                    tracing::info!("Empty chain, requesting blocks from height 1");
+                    (Hash([0u8; 32]), 0)
+                }
+                Err(e) => {
+                    tracing::error!("Failed to get chain tip for sync: {}", e);
+                    return;
+                }
+            };
+            drop(storage_lock);
+
+            if awaiting_snapshot {
+                // Block sync resumes once the snapshot has been imported
+                if let Err(e) = sync_network_sender.send(crate::networking::NetworkCommand::BroadcastMessage {
+                    topic: crate::networking::Topic::new(crate::networking::SNAPSHOT_TOPIC),
+                    message: NetworkMessage::SnapshotRequest,
+                }).await {
+                    tracing::error!("Failed to send snapshot request: {}", e);
+                } else {
+                    tracing::info!("Fast sync enabled, requested state snapshots from peers");
+                }
+                return;
+            }
+            
+            // Request blocks starting from our next block
+            let sync_request = NetworkMessage::SyncRequest {
+                from_height: current_height + 1,
+                to_hash: None, // Request all available blocks
+            };
+            
+            // Broadcast sync request to peers
+            if let Err(e) = sync_network_sender.send(crate::networking::NetworkCommand::BroadcastMessage {
+                topic: crate::networking::Topic::new(crate::networking::SYNC_TOPIC),
+                message: sync_request,
+            }).await {
+                tracing::error!("Failed to send initial sync request: {}", e);
+            } else {
+                tracing::info!("Sent initial sync request for blocks starting from height {}", current_height + 1);
+            }
+            
+            // Note: Responses will be handled by the message handler above
+        }));
+
+        // Clone Arcs for the message handling task
+        let consensus_engine_clone = consensus_engine.clone();
+        let state_machine_clone = state_machine.clone();
+        let storage_clone = storage.clone();
+        let mempool_clone = mempool.clone();
+        let network_command_sender_clone = network_command_sender.clone();
+        let snapshot_chunk_bytes = config.sync.snapshot_chunk_bytes;
+
+        // 13. Task to handle incoming messages from the NetworkService
+        tasks.push(tokio::spawn(async move {
+            tracing::info!("Incoming message handler task started.");
+            // Snapshot we most recently advertised, kept so chunk requests can be answered
+            let mut served_snapshot: Option<StateSnapshot> = None;
+            // Snapshot being downloaded while fast-syncing
+            let mut snapshot_download: Option<SnapshotDownload> = None;
+            let mut awaiting_snapshot = awaiting_snapshot;
+            // Height we last asked peers to send blocks from, so gaps are only requested once
+            let mut requested_sync_from: Option<u64> = None;
+            let snapshot_topic = crate::networking::Topic::new(crate::networking::SNAPSHOT_TOPIC);
+            while let Some(message) = incoming_message_receiver.recv().await {
+                match message {
+                    NetworkMessage::NewTransaction(tx) => {
+                        tracing::info!("Received NewTransaction: {}", tx.id().unwrap());
+                        
+                        // Add transaction to mempool
+                        let mut mempool_lock = mempool_clone.lock().await;
+                        match mempool_lock.add_transaction(*tx) {
+                            Ok(tx_hash) => {
+                                tracing::info!("Transaction {} added to mempool", tx_hash);
+                            }
+                            Err(e) => {
+                                tracing::warn!("Failed to add transaction to mempool: {}", e);
+                            }
+                        }
+                    }
+                    NetworkMessage::NewBlock(block) => {
+                        tracing::info!("Received NewBlock: height {}, hash {}", 
+                            block.header.block_number.0, 
+                            block.header.calculate_hash().unwrap_or_default()
+                        );
+
+                        // Validate block through consensus
+                        let consensus_engine = consensus_engine_clone.lock().await;
+                        if let Err(e) = consensus_engine.validate_block(&block) {
+                            tracing::warn!("Invalid block received: {}", e);
+                            continue;
+                        }
+                        drop(consensus_engine);
+
+                        // Only the block on top of our tip can be applied; a block further ahead
+                        // means we missed some, so fetch them first
+                        match next_block_status(&*storage_clone.lock().await, &block) {
+                            Ok(NextBlock::Extends) => {}
+                            Ok(NextBlock::Ahead { from_height }) => {
+                                if !awaiting_snapshot && requested_sync_from != Some(from_height) {
+                                    requested_sync_from = Some(from_height);
+                                    request_blocks(&network_command_sender_clone, from_height).await;
+                                }
+                                continue;
+                            }
+                            Ok(NextBlock::Stale) => {
+                                tracing::debug!("Ignoring block {} that does not extend our tip", block.header.block_number.0);
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to get chain tip for new block: {}", e);
+                                continue;
+                            }
+                        }
+
+                        // Apply block to state machine
+                        let mut state_machine = state_machine_clone.lock().await;
+                        let changed_accounts = match state_machine.apply_block(&block) {
+                            Ok(changed_accounts) => changed_accounts,
+                            Err(e) => {
+                                tracing::warn!("Failed to apply block to state machine: {}", e);
+                                continue;
+                            }
+                        };
+
+                        // Remove included and expired transactions from mempool
+                        let mempool_lock = mempool_clone.lock().await;
+                        mempool_lock.on_block_committed(&block);
+                        drop(mempool_lock);
+
+                        // Persist block and updated state to storage
+                        let storage = storage_clone.lock().await;
+                        if let Err(e) = storage.commit_block(&block, &changed_accounts) {
+                            tracing::error!("Failed to commit block to storage: {}", e);
+                            continue;
+                        }
+
+                        tracing::info!("Successfully processed and committed new block: height {}", block.header.block_number.0);
+                    }
+                    NetworkMessage::SyncRequest { from_height, to_hash } => {
+                        tracing::info!("Received SyncRequest: from_height {}, to_hash {:?}", from_height, to_hash);
+                        
+                        // Respond with the requested range of blocks, at most `MAX_SYNC_BLOCKS` at a time
+                        let storage_lock = storage_clone.lock().await;
+                        let current_height = match storage_lock.get_chain_tip() {
+                            Ok(Some((_, height))) => height,
+                            Ok(None) => {
+                                tracing::warn!("Cannot respond to sync request: no chain tip");
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to get chain tip for sync response: {}", e);
+                                continue;
+                            }
+                        };
+
+                        let mut blocks_to_send = Vec::new();
+                        let end_height = current_height.min(from_height.saturating_add(MAX_SYNC_BLOCKS - 1));
+                        for height in from_height..=end_height {
+                            // Stops at the pruning horizon, below which only headers are kept
+                            match storage_lock.get_block_by_height(height) {
+                                Ok(Some(block)) => blocks_to_send.push(block),
+                                Ok(None) => break,
+                                Err(e) => {
+                                    tracing::error!("Failed to read block {} for sync response: {}", height, e);
+                                    break;
+                                }
+                            }
+                        }
+                        drop(storage_lock);
+                        
+                        // Send response
+                        let response_message = if blocks_to_send.is_empty() {
+                            NetworkMessage::SyncResponseNoBlocks
+                        } else {
+                            NetworkMessage::SyncResponseBlocks { blocks: blocks_to_send }
+                        };
+                        
+                        // Broadcast the response (in a real implementation, this would be sent to specific peer)
+                        if let Err(e) = network_command_sender_clone.send(crate::networking::NetworkCommand::BroadcastMessage {
+                            topic: crate::networking::Topic::new(crate::networking::SYNC_TOPIC),
+                            message: response_message,
+                        }).await {
+                            tracing::error!("Failed to send sync response: {}", e);
+                        }
+                    }
+                    NetworkMessage::SyncResponseBlocks { blocks } => {
+                        tracing::info!("Received SyncResponseBlocks with {} blocks", blocks.len());
+                        
+                        // Process each block in order, skipping those we already have or cannot apply yet
+                        let mut synced_any = false;
+                        for block in blocks {
+                            if !matches!(next_block_status(&*storage_clone.lock().await, &block), Ok(NextBlock::Extends)) {
+                                continue;
+                            }
+
+                            // Validate block through consensus
+                            let consensus_engine = consensus_engine_clone.lock().await;
+                            if let Err(e) = consensus_engine.validate_block(&block) {
+                                tracing::warn!("Invalid block in sync response: {}", e);
+                                drop(consensus_engine);
+                                continue;
+                            }
+                            drop(consensus_engine);
+
+                            // Apply block to state machine
+                            let mut state_machine = state_machine_clone.lock().await;
+                            let changed_accounts = match state_machine.apply_block(&block) {
+                                Ok(changed_accounts) => changed_accounts,
+                                Err(e) => {
+                                    tracing::warn!("Failed to apply synced block to state machine: {}", e);
+                                    drop(state_machine);
+                                    continue;
+                                }
+                            };
+
+                            // Persist block and updated state to storage
+                            let storage = storage_clone.lock().await;
+                            if let Err(e) = storage.commit_block(&block, &changed_accounts) {
+                                tracing::error!("Failed to commit synced block to storage: {}", e);
+                                drop(storage);
+                                drop(state_machine);
+                                continue;
+                            }
+                            drop(storage);
+                            drop(state_machine);
+
+                            let mempool_lock = mempool_clone.lock().await;
+                            mempool_lock.on_block_committed(&block);
+                            drop(mempool_lock);
+
+                            tracing::info!("Successfully synced and committed block: height {}", block.header.block_number.0);
+                            synced_any = true;
+                        }
+
+                        // Keep asking for the next batch until the peer has nothing more to send
+                        if synced_any {
+                            match storage_clone.lock().await.get_chain_tip() {
+                                Ok(Some((_, height))) => {
+                                    requested_sync_from = Some(height + 1);
+                                    request_blocks(&network_command_sender_clone, height + 1).await;
+                                }
+                                Ok(None) => {}
+                                Err(e) => tracing::error!("Failed to get chain tip after syncing blocks: {}", e),
+                            }
+                        }
+                    }
+                    NetworkMessage::SyncResponseNoBlocks => {
+                        tracing::info!("Received SyncResponseNoBlocks - peer has no blocks to send");
+                        // Handle case where peer doesn't have the requested blocks
+                    }
+                    NetworkMessage::SnapshotRequest => {
+                        let storage_lock = storage_clone.lock().await;
+                        let snapshot = match storage_lock.get_chain_tip() {
+                            Ok(Some((_, height))) if height > 0 => storage_lock.export_snapshot(height, snapshot_chunk_bytes),
+                            Ok(_) => {
+                                tracing::debug!("Ignoring snapshot request: nothing beyond genesis to serve");
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to get chain tip for snapshot request: {}", e);
+                                continue;
+                            }
+                        };
+                        drop(storage_lock);
+
+                        match snapshot {
+                            Ok(snapshot) => {
+                                tracing::info!("Serving state snapshot at height {} in {} chunks",
+                                    snapshot.manifest.height, snapshot.manifest.chunk_count());
+                                let manifest = snapshot.manifest.clone();
+                                served_snapshot = Some(snapshot);
+                                if let Err(e) = network_command_sender_clone.send(crate::networking::NetworkCommand::BroadcastMessage {
+                                    topic: snapshot_topic.clone(),
+                                    message: NetworkMessage::SnapshotManifest(manifest),
+                                }).await {
+                                    tracing::error!("Failed to send snapshot manifest: {}", e);
+                                }
+                            }
+                            Err(e) => tracing::warn!("Failed to export state snapshot: {}", e),
+                        }
+                    }
+                    NetworkMessage::SnapshotManifest(manifest) => {
+                        if !awaiting_snapshot || snapshot_download.is_some() {
+                            continue;
+                        }
+                        tracing::info!("Received snapshot manifest for height {} ({} chunks)",
+                            manifest.height, manifest.chunk_count());
+
+                        let consensus_engine = consensus_engine_clone.lock().await;
+                        if let Err(e) = consensus_engine.validate_header(&manifest.header) {
+                            tracing::warn!("Rejecting snapshot manifest with invalid header: {}", e);
+                            continue;
+                        }
+                        drop(consensus_engine);
+
+                        let download = match SnapshotDownload::new(manifest) {
+                            Ok(download) => download,
+                            Err(e) => {
+                                tracing::warn!("Rejecting inconsistent snapshot manifest: {}", e);
+                                continue;
+                            }
+                        };
+                        let block_hash = download.manifest().block_hash;
+                        for index in download.missing_chunks() {
+                            if let Err(e) = network_command_sender_clone.send(crate::networking::NetworkCommand::BroadcastMessage {
+                                topic: snapshot_topic.clone(),
+                                message: NetworkMessage::SnapshotChunkRequest { block_hash, index },
+                            }).await {
+                                tracing::error!("Failed to request snapshot chunk {}: {}", index, e);
+                            }
+                        }
+                        snapshot_download = Some(download);
+                    }
+                    NetworkMessage::SnapshotChunkRequest { block_hash, index } => {
+                        let Some(snapshot) = served_snapshot.as_ref().filter(|s| s.manifest.block_hash == block_hash) else {
+                            continue;
+                        };
+                        if let Some(chunk) = snapshot.chunks.get(index as usize) {
+                            if let Err(e) = network_command_sender_clone.send(crate::networking::NetworkCommand::BroadcastMessage {
+                                topic: snapshot_topic.clone(),
+                                message: NetworkMessage::SnapshotChunk { block_hash, chunk: chunk.clone() },
+                            }).await {
+                                tracing::error!("Failed to send snapshot chunk {}: {}", index, e);
+                            }
+                        }
+                    }
+                    NetworkMessage::SnapshotChunk { block_hash, chunk } => {
+                        let Some(download) = snapshot_download.as_mut().filter(|d| d.manifest().block_hash == block_hash) else {
+                            continue;
+                        };
+                        match download.insert_chunk(chunk) {
+                            Ok(false) => continue,
+                            Ok(true) => {}
+                            Err(e) => {
+                                tracing::warn!("Discarding invalid snapshot chunk: {}", e);
+                                continue;
+                            }
+                        }
+
+                        let Some(download) = snapshot_download.take() else { continue };
+                        let snapshot = match download.finish() {
+                            Ok(snapshot) => snapshot,
+                            Err(e) => {
+                                tracing::warn!("Snapshot verification failed: {}", e);
+                                continue;
+                            }
+                        };
+
+                        let snapshot_state = match StateMachine::from_world_state(snapshot.to_world_state()) {
+                            Ok(snapshot_state) => snapshot_state,
+                            Err(e) => {
+                                tracing::warn!("Rejected state snapshot: {}", e);
+                                continue;
+                            }
+                        };
+                        let mut state_machine = state_machine_clone.lock().await;
+                        let storage = storage_clone.lock().await;
+                        if let Err(e) = storage.import_snapshot(&snapshot) {
+                            tracing::error!("Failed to import state snapshot: {}", e);
+                            continue;
+                        }
+                        *state_machine = snapshot_state;
+                        drop(storage);
+                        drop(state_machine);
+                        awaiting_snapshot = false;
+
+                        let next_height = snapshot.manifest.height.0 + 1;
+                        tracing::info!("Imported state snapshot at height {}, syncing remaining blocks", snapshot.manifest.height);
+                        requested_sync_from = Some(next_height);
+                        request_blocks(&network_command_sender_clone, next_height).await;
+                    }
+                }
+            }
+        }));
+
+        // 14. Block production task - only runs if this node is a validator
+        let mempool_producer = mempool.clone();
+        let consensus_producer = consensus_engine.clone();
+        let state_producer = state_machine.clone();
+        let storage_producer = storage.clone();
+        let network_sender = network_command_sender.clone();
+        let block_signer_producer = block_signer.clone();
+        
+        // Extract config values before moving into async task
+        let block_interval = config.consensus.block_interval;
+        let max_txs_per_block = config.consensus.max_txs_per_block;
+
+        tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(block_interval));
+            
+            loop {
+                interval.tick().await;
+                tracing::info!("Block production timer tick");
+                
+                // Check if it's our turn to propose
+                let current_time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                
+                // Get current blockchain state
+                let storage_lock = storage_producer.lock().await;
+                let (current_tip_hash, current_height) = match storage_lock.get_chain_tip() {
+                    Ok(Some((hash, height))) => (hash, height),
+                    Ok(None) => {
+                        // Genesis case - start with height 0
+                        (Hash([0u8; 32]), 0)
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to get chain tip: {}", e);
+                        continue;
+                    }
+                };
+                drop(storage_lock);
+                
+                let next_height = BlockHeight(current_height + 1);
+                
+                // Check with consensus engine if we should propose
+                let consensus_lock = consensus_producer.lock().await;
+                let expected_proposer = match consensus_lock.get_proposer(next_height) {
+                    Ok(proposer) => proposer,
+                    Err(e) => {
+                        tracing::debug!("Failed to get proposer for height {}: {}", next_height.0, e);
+                        drop(consensus_lock);
+                        continue;
+                    }
+                };
+                
+                let our_address = address_from_public_key(block_signer_producer.public_key());
+                let expected_address = address_from_public_key(expected_proposer);
+                
+                if our_address != expected_address {
+                    tracing::info!("Not our turn to propose. Expected: {}, We are: {}", expected_address, our_address);
+                    drop(consensus_lock);
+                    continue;
+                }
+                drop(consensus_lock);
+                
+                tracing::info!("Our turn to propose block at height {}", next_height.0);
+                
+                // Collect transactions from mempool
+                let mempool_lock = mempool_producer.lock().await;
+                let transactions = mempool_lock.get_pending_transactions_for_height(max_txs_per_block, next_height);
+                let num_txs = transactions.len();
+                drop(mempool_lock);
+                
+                tracing::info!("Collected {} transactions for new block", num_txs);
+                
+                // Calculate merkle root
+                let tx_root = match calculate_merkle_root(&transactions) {
+                    Ok(root) => root,
+                    Err(e) => {
+                        tracing::error!("Failed to calculate merkle root: {}", e);
+                        continue;
+                    }
+                };
+                
+                // Create block header (without signature first)
+                let mut block_header = BlockHeader {
+                    parent_hash: current_tip_hash,
+                    block_number: next_height,
+                    timestamp: Timestamp(current_time),
+                    tx_root,
+                    validator: our_address,
+                    signature: Signature(vec![0; 64]), // Placeholder
+                };
+                
+                // Sign the header hash, on a blocking thread since a remote signer waits on I/O
+                let signer = block_signer_producer.clone();
+                let unsigned_header = block_header.clone();
+                let signature = match tokio::task::spawn_blocking(move || signer.sign_header(&unsigned_header)).await {
+                    Ok(Ok(sig)) => sig,
+                    Ok(Err(e)) => {
+                        tracing::error!("Failed to sign block header: {}", e);
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::error!("Block signing task failed: {}", e);
+                        continue;
+                    }
+                };
+                
+                // Update header with real signature
+                block_header.signature = signature;
+                
+                // Create the complete block
+                let new_block = Block {
+                    header: block_header,
+                    transactions,
+                };
+                
+                tracing::info!("Produced new block: height {}, txs {}, hash {}", 
+                    new_block.header.block_number.0,
+                    new_block.transactions.len(),
+                    new_block.header.calculate_hash().unwrap_or_default()
+                );
+                
+                // Apply block locally first (optimistic)
+                let mut state_lock = state_producer.lock().await;
+                let changed_accounts = match state_lock.apply_block(&new_block) {
+                    Ok(changed_accounts) => changed_accounts,
+                    Err(e) => {
+                        tracing::error!("Failed to apply our own block to state machine: {}", e);
+                        continue;
+                    }
+                };
+                
+                // Remove included and expired transactions from mempool
+                let mempool_lock = mempool_producer.lock().await;
+                mempool_lock.on_block_committed(&new_block);
+                drop(mempool_lock);
+                
+                // Persist the block
+                let storage_lock = storage_producer.lock().await;
+                if let Err(e) = storage_lock.commit_block(&new_block, &changed_accounts) {
+                    tracing::error!("Failed to commit our own block to storage: {}", e);
+                    continue;
+                }
+                drop(storage_lock);
+                drop(state_lock);
+                
+                // Broadcast the block to peers
+                let broadcast_command = crate::networking::NetworkCommand::BroadcastBlock(new_block.clone());
+                if let Err(e) = network_sender.send(broadcast_command).await {
+                    tracing::error!("Failed to send broadcast block command: {}", e);
+                } else {
+                    tracing::info!("Successfully sent block broadcast command to network");
+                }
+            }
+        }));
+
+        // 15. Background pruning task - removes block bodies below the pruning horizon
+        let pruning_mode = config.storage.pruning;
+        if pruning_mode != PruningMode::Archive {
+            let storage_pruner = storage.clone();
+            let prune_interval = config.storage.prune_interval_secs.max(1);
+            tracing::info!("Pruning enabled: {} (every {}s)", pruning_mode, prune_interval);
+
+            tasks.push(tokio::spawn(async move {
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(prune_interval));
+                loop {
+                    interval.tick().await;
+                    let storage_lock = storage_pruner.lock().await;
+                    match storage_lock.prune(pruning_mode) {
+                        Ok(0) => {}
+                        Ok(removed) => tracing::info!("Pruned {} block bodies below height {}",
+                            removed, storage_lock.get_pruned_height().unwrap_or_default()),
+                        Err(e) => tracing::error!("Pruning failed: {}", e),
+                    }
+                }
+            }));
+        }
+
+        // 16. JSON RPC server
+        if config.rpc.enabled {
+            let listener = tokio::net::TcpListener::bind(&config.rpc.listen_addr).await
+                .map_err(|e| anyhow::anyhow!("Failed to bind RPC server to {}: {}", config.rpc.listen_addr, e))?;
+            tracing::info!("RPC server listening on {}", config.rpc.listen_addr);
+            let rpc_server = RpcServer::new(storage.clone())
+                .with_submission(mempool.clone(), network_command_sender.clone())
+                .with_legacy_hex_addresses(config.legacy_hex_addresses);
+            tasks.push(tokio::spawn(async move {
+                if let Err(e) = rpc_server.serve(listener).await {
+                    tracing::error!("RPC server stopped: {}", e);
+                }
+            }));
+        }
+
+        Ok(Node { storage, state_machine, mempool, tasks })
+    }
+
+    pub fn storage(&self) -> &Arc<Mutex<S>> {
+        &self.storage
+    }
+
+    pub fn state_machine(&self) -> &Arc<Mutex<StateMachine>> {
+        &self.state_machine
+    }
+
+    pub fn mempool(&self) -> &Arc<Mutex<Mempool>> {
+        &self.mempool
+    }
+
+    /// Stops the node's tasks and hands back its store, so a node can be restarted on it.
+    pub async fn shutdown(mut self) -> anyhow::Result<S> {
+        let tasks = std::mem::take(&mut self.tasks);
+        for task in &tasks {
+            task.abort();
+        }
+        for task in tasks {
+            let _ = task.await;
+        }
+        let storage = self.storage.clone();
+        drop(self);
+        Arc::try_unwrap(storage)
+            .map(Mutex::into_inner)
+            .map_err(|_| anyhow::anyhow!("Storage is still in use after shutdown"))
+    }
+}
+
+impl<S: ChainStore> Drop for Node<S> {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Runs a node until Ctrl-C.
+pub async fn run_node<S: ChainStore>(config: NodeConfiguration, storage: S) -> anyhow::Result<()> {
+    let _node = Node::start(config, storage).await?;
+    tracing::info!("RustChain Node is running. Press Ctrl-C to stop.");
+    tokio::signal::ctrl_c().await?;
+    tracing::info!("Ctrl-C received, shutting down node...");
+    Ok(())
+}
+
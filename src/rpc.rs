@@ -0,0 +1,613 @@
+use crate::chain_store::ChainStore;
+use crate::mempool::Mempool;
+use crate::networking::NetworkCommand;
+use crate::storage::{StorageError, TransactionLocation};
+use crate::transaction::{Transaction, MAX_MEMO_LEN};
+use crate::types::{Address, BlockHeight};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+
+/// Default address the RPC server listens on.
+pub const DEFAULT_RPC_LISTEN_ADDR: &str = "127.0.0.1:9933";
+
+/// Entries returned by `get_account_history` when the request sets no limit, and the most
+/// it may ask for.
+const DEFAULT_HISTORY_LIMIT: usize = 20;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+/// How long `RpcClient` waits for a response before giving up.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors returned to RPC clients. Each variant maps to a stable machine-readable code.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RpcError {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+    #[error("Data at height {height} has been pruned: this node only retains data from height {horizon}")]
+    Pruned { height: u64, horizon: u64 },
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Transaction rejected: {0}")]
+    Rejected(String),
+    #[error("Not supported by this node: {0}")]
+    Unsupported(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl RpcError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RpcError::InvalidRequest(_) => "invalid_request",
+            RpcError::InvalidParams(_) => "invalid_params",
+            RpcError::Pruned { .. } => "pruned",
+            RpcError::NotFound(_) => "not_found",
+            RpcError::Rejected(_) => "rejected",
+            RpcError::Unsupported(_) => "unsupported",
+            RpcError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<StorageError> for RpcError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::Pruned { height, horizon } => RpcError::Pruned { height, horizon },
+            StorageError::NotFound(what) => RpcError::NotFound(what),
+            other => RpcError::Internal(other.to_string()),
+        }
+    }
+}
+
+/// A request is a single JSON object per line, e.g.
+/// `{"method": "get_balance", "params": {"address": "rc1..", "height": 1200}}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum RpcRequest {
+    /// Balance and nonce of an account, at the tip or at a given block height.
+    GetBalance {
+        address: String,
+        #[serde(default)]
+        height: Option<u64>,
+    },
+    /// The full block at a given height.
+    GetBlock { height: u64 },
+    /// Committed transactions whose memo matches exactly. The memo is given either as UTF-8
+    /// text in `memo` or as hex in `memo_hex`.
+    GetTransactionsByMemo {
+        #[serde(default)]
+        memo: Option<String>,
+        #[serde(default)]
+        memo_hex: Option<String>,
+    },
+    /// Committed transactions that touch an account, newest first.
+    GetAccountHistory {
+        address: String,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Adds a signed transaction, given as the hex of its bincode encoding, to the mempool
+    /// and gossips it to peers.
+    SubmitTransaction { tx: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcErrorBody {
+    pub code: String,
+    pub message: String,
+}
+
+/// Exactly one of `result` and `error` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcErrorBody>,
+}
+
+impl From<Result<Value, RpcError>> for RpcResponse {
+    fn from(result: Result<Value, RpcError>) -> Self {
+        match result {
+            Ok(value) => RpcResponse { result: Some(value), error: None },
+            Err(e) => RpcResponse {
+                result: None,
+                error: Some(RpcErrorBody { code: e.code().to_string(), message: e.to_string() }),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceResult {
+    pub address: String,
+    /// Height the balance was read at
+    pub height: u64,
+    pub balance: u64,
+    /// Part of `balance` held by a vesting schedule, which cannot be spent in the next block
+    pub locked: u64,
+    pub nonce: u64,
+}
+
+/// A committed transaction found through the memo or address index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedTransaction {
+    pub height: u64,
+    pub tx_id: String,
+    /// The transaction itself, or `None` if the block body has been pruned.
+    pub transaction: Option<Transaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitResult {
+    pub tx_id: String,
+}
+
+/// What the server needs to accept transactions: the mempool, and the network service to
+/// gossip them through.
+#[derive(Clone)]
+struct Submission {
+    mempool: Arc<Mutex<Mempool>>,
+    network: mpsc::Sender<NetworkCommand>,
+}
+
+/// Serves line-delimited JSON requests over TCP against the node's storage.
+pub struct RpcServer<S: ChainStore> {
+    storage: Arc<Mutex<S>>,
+    submission: Option<Submission>,
+    legacy_hex_addresses: bool,
+}
+
+impl<S: ChainStore> Clone for RpcServer<S> {
+    fn clone(&self) -> Self {
+        RpcServer { storage: self.storage.clone(), submission: self.submission.clone(), legacy_hex_addresses: self.legacy_hex_addresses }
+    }
+}
+
+impl<S: ChainStore> RpcServer<S> {
+    /// A read-only server. `submit_transaction` is refused until `with_submission` is used.
+    pub fn new(storage: Arc<Mutex<S>>) -> Self {
+        RpcServer { storage, submission: None, legacy_hex_addresses: false }
+    }
+
+    /// Accepts `submit_transaction` into `mempool`, gossiping accepted transactions through
+    /// `network`.
+    pub fn with_submission(mut self, mempool: Arc<Mutex<Mempool>>, network: mpsc::Sender<NetworkCommand>) -> Self {
+        self.submission = Some(Submission { mempool, network });
+        self
+    }
+
+    /// Also accepts request addresses as unchecksummed hex, for clients written before
+    /// addresses were bech32m.
+    pub fn with_legacy_hex_addresses(mut self, legacy_hex_addresses: bool) -> Self {
+        self.legacy_hex_addresses = legacy_hex_addresses;
+        self
+    }
+
+    /// Accepts connections until the listener fails, handling each on its own task.
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_connection(stream).await {
+                    tracing::debug!("RPC connection from {} closed with error: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut response = self.handle_line(&line).await;
+            response.push('\n');
+            writer.write_all(response.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Parses and handles a single request line, returning the serialized response.
+    pub async fn handle_line(&self, line: &str) -> String {
+        let result = match serde_json::from_str::<RpcRequest>(line) {
+            Ok(request) => self.handle(request).await,
+            Err(e) => Err(RpcError::InvalidRequest(e.to_string())),
+        };
+        serde_json::to_string(&RpcResponse::from(result))
+            .unwrap_or_else(|e| format!("{{\"error\":{{\"code\":\"internal\",\"message\":\"{}\"}}}}", e))
+    }
+
+    pub async fn handle(&self, request: RpcRequest) -> Result<Value, RpcError> {
+        match request {
+            RpcRequest::GetBalance { address, height } => {
+                let result = self.get_balance(&address, height).await?;
+                serde_json::to_value(result).map_err(|e| RpcError::Internal(e.to_string()))
+            }
+            RpcRequest::GetBlock { height } => {
+                let storage = self.storage.lock().await;
+                let block = storage
+                    .get_block_by_height(height)?
+                    .ok_or_else(|| RpcError::NotFound(format!("Block at height {}", height)))?;
+                serde_json::to_value(block).map_err(|e| RpcError::Internal(e.to_string()))
+            }
+            RpcRequest::GetTransactionsByMemo { memo, memo_hex } => {
+                let memo = parse_memo(memo, memo_hex).map_err(RpcError::InvalidParams)?;
+                let storage = self.storage.lock().await;
+                let locations = storage.get_transactions_by_memo(&memo)?;
+                let result = load_transactions(&*storage, locations)?;
+                serde_json::to_value(result).map_err(|e| RpcError::Internal(e.to_string()))
+            }
+            RpcRequest::GetAccountHistory { address, limit } => {
+                let parsed = Address::parse(&address, self.legacy_hex_addresses).map_err(RpcError::InvalidParams)?;
+                let limit = limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+                if limit == 0 || limit > MAX_HISTORY_LIMIT {
+                    return Err(RpcError::InvalidParams(format!("Limit must be between 1 and {}", MAX_HISTORY_LIMIT)));
+                }
+                let storage = self.storage.lock().await;
+                let mut locations = storage.get_transactions_by_address(&parsed)?;
+                locations.reverse();
+                locations.truncate(limit);
+                let result = load_transactions(&*storage, locations)?;
+                serde_json::to_value(result).map_err(|e| RpcError::Internal(e.to_string()))
+            }
+            RpcRequest::SubmitTransaction { tx } => {
+                let result = self.submit_transaction(&tx).await?;
+                serde_json::to_value(result).map_err(|e| RpcError::Internal(e.to_string()))
+            }
+        }
+    }
+
+    /// Checks what can be checked without executing the transaction, then queues and
+    /// gossips it. A stale nonce is refused here rather than left to fail in a block.
+    async fn submit_transaction(&self, tx_hex: &str) -> Result<SubmitResult, RpcError> {
+        let submission = self
+            .submission
+            .as_ref()
+            .ok_or_else(|| RpcError::Unsupported("this node does not accept transactions over RPC".to_string()))?;
+        let bytes = hex::decode(tx_hex.strip_prefix("0x").unwrap_or(tx_hex))
+            .map_err(|e| RpcError::InvalidParams(format!("Transaction is not valid hex: {}", e)))?;
+        let (transaction, _): (Transaction, usize) = bincode::decode_from_slice(&bytes, bincode::config::standard())
+            .map_err(|e| RpcError::InvalidParams(format!("Failed to decode transaction: {}", e)))?;
+        transaction.validate().map_err(|e| RpcError::Rejected(e.to_string()))?;
+
+        let sender = transaction.sender_address();
+        let account = self.storage.lock().await.get_account(&sender)?.unwrap_or_default();
+        if transaction.nonce < account.nonce {
+            return Err(RpcError::Rejected(format!(
+                "nonce {} of {} is already used, the next nonce is {}",
+                transaction.nonce.0, sender, account.nonce.0
+            )));
+        }
+
+        let tx_id = submission
+            .mempool
+            .lock()
+            .await
+            .add_transaction(transaction.clone())
+            .map_err(|e| RpcError::Rejected(e.to_string()))?;
+        if let Err(e) = submission.network.send(NetworkCommand::BroadcastTransaction(Box::new(transaction))).await {
+            tracing::warn!("Transaction {} was queued but could not be gossiped: {}", tx_id, e);
+        }
+        Ok(SubmitResult { tx_id: tx_id.to_string() })
+    }
+
+    async fn get_balance(&self, address: &str, height: Option<u64>) -> Result<BalanceResult, RpcError> {
+        let parsed = Address::parse(address, self.legacy_hex_addresses).map_err(RpcError::InvalidParams)?;
+        let storage = self.storage.lock().await;
+        let tip_height = storage.get_chain_tip()?.map(|(_, height)| height).unwrap_or(0);
+
+        let (height, account) = match height {
+            Some(height) if height > tip_height => {
+                return Err(RpcError::InvalidParams(format!(
+                    "Height {} is above the chain tip at height {}",
+                    height, tip_height
+                )));
+            }
+            Some(height) => (height, storage.get_account_at(&parsed, height)?),
+            None => (tip_height, storage.get_account(&parsed)?),
+        };
+
+        // Addresses that have never been credited read as an empty account
+        let account = account.unwrap_or_default();
+        Ok(BalanceResult {
+            address: parsed.to_string(),
+            height,
+            balance: account.balance,
+            locked: account.locked_at(BlockHeight(height.saturating_add(1))),
+            nonce: account.nonce.0,
+        })
+    }
+}
+
+/// Looks up each location's transaction. Pruned bodies yield `transaction: None`.
+fn load_transactions<S: ChainStore>(storage: &S, locations: Vec<TransactionLocation>) -> Result<Vec<IndexedTransaction>, RpcError> {
+    let mut transactions = Vec::with_capacity(locations.len());
+    for location in locations {
+        let block = match storage.get_block_by_height(location.height) {
+            Ok(block) => block,
+            Err(StorageError::Pruned { .. }) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let transaction = block.and_then(|block| {
+            block.transactions.into_iter().find(|tx| tx.id().is_ok_and(|id| id == location.tx_id))
+        });
+        transactions.push(IndexedTransaction { height: location.height, tx_id: location.tx_id.to_string(), transaction });
+    }
+    Ok(transactions)
+}
+
+#[derive(Debug, Error)]
+pub enum RpcClientError {
+    #[error("Connection to node failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid response from node: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to encode transaction: {0}")]
+    Encode(String),
+    #[error("Node connection closed without a response")]
+    Closed,
+    #[error("Node returned `{code}`: {message}")]
+    Rpc { code: String, message: String },
+}
+
+/// A blocking client for `RpcServer`, for the wallet CLI. Requests are sent one at a time
+/// over a single connection.
+pub struct RpcClient {
+    reader: std::io::BufReader<std::net::TcpStream>,
+    writer: std::net::TcpStream,
+}
+
+impl RpcClient {
+    pub fn connect(addr: &str) -> Result<Self, RpcClientError> {
+        let stream = std::net::TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        Ok(RpcClient { reader: std::io::BufReader::new(stream.try_clone()?), writer: stream })
+    }
+
+    pub fn call<T: DeserializeOwned>(&mut self, request: &RpcRequest) -> Result<T, RpcClientError> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        let mut response = String::new();
+        if self.reader.read_line(&mut response)? == 0 {
+            return Err(RpcClientError::Closed);
+        }
+        match serde_json::from_str::<RpcResponse>(&response)? {
+            RpcResponse { error: Some(error), .. } => Err(RpcClientError::Rpc { code: error.code, message: error.message }),
+            RpcResponse { result, .. } => Ok(serde_json::from_value(result.unwrap_or(Value::Null))?),
+        }
+    }
+
+    /// Balance and next nonce of `address` at the tip.
+    pub fn get_balance(&mut self, address: &Address) -> Result<BalanceResult, RpcClientError> {
+        self.call(&RpcRequest::GetBalance { address: address.to_string(), height: None })
+    }
+
+    pub fn get_account_history(&mut self, address: &Address, limit: Option<usize>) -> Result<Vec<IndexedTransaction>, RpcClientError> {
+        self.call(&RpcRequest::GetAccountHistory { address: address.to_string(), limit })
+    }
+
+    pub fn submit_transaction(&mut self, transaction: &Transaction) -> Result<SubmitResult, RpcClientError> {
+        let bytes = bincode::encode_to_vec(transaction, bincode::config::standard())
+            .map_err(|e| RpcClientError::Encode(e.to_string()))?;
+        self.call(&RpcRequest::SubmitTransaction { tx: hex::encode(bytes) })
+    }
+}
+
+fn parse_memo(memo: Option<String>, memo_hex: Option<String>) -> Result<Vec<u8>, String> {
+    let memo = match (memo, memo_hex) {
+        (Some(text), None) => text.into_bytes(),
+        (None, Some(hex_memo)) => hex::decode(hex_memo.strip_prefix("0x").unwrap_or(&hex_memo))
+            .map_err(|e| format!("Invalid hex string for memo: {}", e))?,
+        _ => return Err("Exactly one of memo and memo_hex must be given".to_string()),
+    };
+    if memo.is_empty() || memo.len() > MAX_MEMO_LEN {
+        return Err(format!("Memo must be between 1 and {} bytes, got {}", MAX_MEMO_LEN, memo.len()));
+    }
+    Ok(memo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::{Block, BlockHeader};
+    use crate::chain_store::MemoryStore;
+    use crate::state_machine::{Account, WorldState};
+    use crate::storage::PruningMode;
+    use crate::types::{BlockHeight, Hash, Nonce, Signature, Timestamp};
+
+    const ALICE: Address = Address([7; 32]);
+
+    /// Commits `balances.len()` blocks, setting ALICE's balance to `balances[height]` at each height.
+    fn server_with_history(balances: &[u64]) -> RpcServer<MemoryStore> {
+        let storage = MemoryStore::new();
+        let mut parent_hash = Hash([0; 32]);
+        for (height, balance) in balances.iter().enumerate() {
+            let block = Block {
+                header: BlockHeader {
+                    parent_hash,
+                    block_number: BlockHeight(height as u64),
+                    timestamp: Timestamp(height as u64),
+                    tx_root: Hash([1; 32]),
+                    validator: Address([2; 32]),
+                    signature: Signature(vec![0; 64]),
+                },
+                transactions: vec![],
+            };
+            let mut world_state = WorldState::new();
+            world_state.insert(ALICE, Account { balance: *balance, nonce: Nonce(height as u64), authorized_key: None, vesting: None, hash_lock: None });
+            storage.commit_block(&block, &world_state).unwrap();
+            parent_hash = block.header.calculate_hash().unwrap();
+        }
+        RpcServer::new(Arc::new(Mutex::new(storage)))
+    }
+
+    #[tokio::test]
+    async fn test_get_balance_at_height() {
+        let server = server_with_history(&[100, 80, 80, 55]);
+
+        let response = server
+            .handle(RpcRequest::GetBalance { address: ALICE.to_string(), height: Some(1) })
+            .await
+            .unwrap();
+        let balance: BalanceResult = serde_json::from_value(response).unwrap();
+        assert_eq!((balance.height, balance.balance, balance.nonce), (1, 80, 1));
+
+        let response = server
+            .handle(RpcRequest::GetBalance { address: ALICE.to_string(), height: None })
+            .await
+            .unwrap();
+        let balance: BalanceResult = serde_json::from_value(response).unwrap();
+        assert_eq!((balance.height, balance.balance), (3, 55));
+
+        let err = server
+            .handle(RpcRequest::GetBalance { address: ALICE.to_string(), height: Some(9) })
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "invalid_params");
+    }
+
+    #[tokio::test]
+    async fn test_hex_addresses_need_legacy_flag() {
+        let hex_address = format!("0x{}", hex::encode(ALICE.0));
+        let request = || RpcRequest::GetBalance { address: hex_address.clone(), height: None };
+
+        let err = server_with_history(&[100]).handle(request()).await.unwrap_err();
+        assert_eq!(err.code(), "invalid_params");
+
+        let server = server_with_history(&[100]).with_legacy_hex_addresses(true);
+        let balance: BalanceResult = serde_json::from_value(server.handle(request()).await.unwrap()).unwrap();
+        assert_eq!(balance.balance, 100);
+    }
+
+    #[tokio::test]
+    async fn test_pruned_height_reports_error_code() {
+        let server = server_with_history(&[100, 90, 80, 70, 60]);
+        server.storage.lock().await.prune(PruningMode::KeepLast(2)).unwrap();
+
+        let line = format!(r#"{{"method":"get_balance","params":{{"address":"{}","height":1}}}}"#, ALICE);
+        let response: RpcResponse = serde_json::from_str(&server.handle_line(&line).await).unwrap();
+        assert!(response.result.is_none());
+        assert_eq!(response.error.unwrap().code, "pruned");
+
+        let line = r#"{"method":"get_block","params":{"height":1}}"#;
+        let response: RpcResponse = serde_json::from_str(&server.handle_line(line).await).unwrap();
+        assert_eq!(response.error.unwrap().code, "pruned");
+
+        let line = format!(r#"{{"method":"get_balance","params":{{"address":"{}","height":3}}}}"#, ALICE);
+        let response: RpcResponse = serde_json::from_str(&server.handle_line(&line).await).unwrap();
+        assert_eq!(response.result.unwrap()["balance"], 70);
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_by_memo() {
+        let wallet = crate::wallet::Wallet::new();
+        let payload = Transaction::new(*wallet.public_key(), Address([9; 32]), 25, Nonce(0), Signature(vec![]))
+            .with_memo(b"invoice-42".to_vec());
+        let tx = wallet.sign_transaction(payload).unwrap();
+        let block = Block {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(4),
+                timestamp: Timestamp(4),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![tx.clone()],
+        };
+        let storage = MemoryStore::new();
+        storage.commit_block(&block, &WorldState::new()).unwrap();
+        let server = RpcServer::new(Arc::new(Mutex::new(storage)));
+
+        let line = r#"{"method":"get_transactions_by_memo","params":{"memo":"invoice-42"}}"#;
+        let response: RpcResponse = serde_json::from_str(&server.handle_line(line).await).unwrap();
+        let matches: Vec<IndexedTransaction> = serde_json::from_value(response.result.unwrap()).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].height, &matches[0].tx_id), (4, &tx.id().unwrap().to_string()));
+        assert_eq!(matches[0].transaction, Some(tx));
+
+        let line = format!(r#"{{"method":"get_transactions_by_memo","params":{{"memo_hex":"{}"}}}}"#, hex::encode(b"invoice-43"));
+        let response: RpcResponse = serde_json::from_str(&server.handle_line(&line).await).unwrap();
+        assert_eq!(response.result.unwrap(), serde_json::json!([]));
+
+        let line = r#"{"method":"get_transactions_by_memo","params":{}}"#;
+        let response: RpcResponse = serde_json::from_str(&server.handle_line(line).await).unwrap();
+        assert_eq!(response.error.unwrap().code, "invalid_params");
+    }
+
+    #[tokio::test]
+    async fn test_client_submits_and_reads_history() {
+        let wallet = crate::wallet::Wallet::new();
+        let sender = crate::types::address_from_public_key(wallet.public_key());
+        let bob = Address([9; 32]);
+        let first = wallet.create_signed_transaction(bob, 25, Nonce(0)).unwrap();
+        let block = Block {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(1),
+                timestamp: Timestamp(1),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![first.clone()],
+        };
+        let storage = MemoryStore::new();
+        let mut world_state = WorldState::new();
+        world_state.insert(sender, Account { balance: 75, nonce: Nonce(1), ..Default::default() });
+        storage.commit_block(&block, &world_state).unwrap();
+
+        let mempool = Arc::new(Mutex::new(Mempool::new(crate::mempool::MempoolConfig::default())));
+        let (network, mut gossiped) = mpsc::channel(4);
+        let server = RpcServer::new(Arc::new(Mutex::new(storage))).with_submission(mempool.clone(), network);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(server.serve(listener));
+
+        let second = wallet.create_signed_transaction(bob, 30, Nonce(1)).unwrap();
+        let to_submit = second.clone();
+        let (history, stale, submitted) = tokio::task::spawn_blocking(move || {
+            let mut client = RpcClient::connect(&addr).unwrap();
+            assert_eq!(client.get_balance(&sender).unwrap().nonce, 1);
+            let history = client.get_account_history(&bob, None).unwrap();
+            let stale = client.submit_transaction(&first).unwrap_err();
+            (history, stale, client.submit_transaction(&to_submit).unwrap())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].height, history[0].transaction.as_ref()), (1, Some(&block.transactions[0])));
+        assert!(matches!(stale, RpcClientError::Rpc { code, .. } if code == "rejected"));
+        assert_eq!(submitted.tx_id, second.id().unwrap().to_string());
+        assert!(mempool.lock().await.contains_transaction(&second.id().unwrap()));
+        assert!(matches!(gossiped.recv().await, Some(NetworkCommand::BroadcastTransaction(tx)) if *tx == second));
+
+        let read_only = server_with_history(&[1]);
+        let err = read_only.handle(RpcRequest::SubmitTransaction { tx: String::new() }).await.unwrap_err();
+        assert_eq!(err.code(), "unsupported");
+    }
+
+    #[tokio::test]
+    async fn test_malformed_request_is_rejected() {
+        let server = server_with_history(&[1]);
+        let response: RpcResponse = serde_json::from_str(&server.handle_line("{\"method\":\"nope\"}").await).unwrap();
+        assert_eq!(response.error.unwrap().code, "invalid_request");
+    }
+}
@@ -0,0 +1,284 @@
+use crate::block::BlockHeader;
+use crate::block_signer::{AuthenticatedRequest, SignerHello, SignerRequest, SignerResponse, CHALLENGE_LEN, verify_request_mac};
+use crate::types::{Address, Hash, PublicKey};
+use crate::wallet::address_from_public_key;
+use ed25519_dalek::{Signer, SigningKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SignerServerError {
+    #[error("Request is not authenticated")]
+    Unauthenticated,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Header is for validator {0}, not the key this signer holds")]
+    WrongValidator(Address),
+    #[error("Already signed a different header at height {0}")]
+    DoubleSign(u64),
+    #[error("Height {height} is below the watermark at height {watermark}")]
+    BelowWatermark { height: u64, watermark: u64 },
+    #[error("Watermark file is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Watermark I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The last header the signer signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watermark {
+    pub height: u64,
+    /// Hex hash of the header
+    pub header_hash: String,
+}
+
+/// The watermark, persisted so the signer keeps refusing double signs across restarts.
+/// Losing the file lets the signer sign any height again, so it must be kept with the key.
+pub struct WatermarkFile {
+    path: PathBuf,
+    current: Option<Watermark>,
+}
+
+impl WatermarkFile {
+    /// Opens the watermark at `path`. A file that does not exist yet means nothing has been
+    /// signed.
+    pub fn open(path: &Path) -> Result<Self, SignerServerError> {
+        let current = if path.exists() { Some(serde_json::from_slice(&fs::read(path)?)?) } else { None };
+        Ok(WatermarkFile { path: path.to_path_buf(), current })
+    }
+
+    pub fn current(&self) -> Option<&Watermark> {
+        self.current.as_ref()
+    }
+
+    /// Checks that the header at `height` with hash `header_hash` may be signed, and records
+    /// it as the watermark before returning. Signing a height above the watermark is always
+    /// allowed, and signing the watermark's own header again is allowed so a node can retry
+    /// after a lost response.
+    pub fn advance(&mut self, height: u64, header_hash: &Hash) -> Result<(), SignerServerError> {
+        let header_hash = header_hash.to_string();
+        match &self.current {
+            Some(watermark) if height < watermark.height => {
+                return Err(SignerServerError::BelowWatermark { height, watermark: watermark.height });
+            }
+            Some(watermark) if height == watermark.height => {
+                return if watermark.header_hash == header_hash { Ok(()) } else { Err(SignerServerError::DoubleSign(height)) };
+            }
+            _ => {}
+        }
+        let watermark = Watermark { height, header_hash };
+        self.save(&watermark)?;
+        self.current = Some(watermark);
+        Ok(())
+    }
+
+    /// Replaces the file atomically and flushes it to disk, so a signature is never
+    /// returned for a height whose watermark could still be lost.
+    fn save(&self, watermark: &Watermark) -> Result<(), SignerServerError> {
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(watermark)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Serves the remote signer protocol (see `block_signer`) for one validator key.
+pub struct SignerServer {
+    signing_key: SigningKey,
+    public_key: PublicKey,
+    secret: Vec<u8>,
+    watermark: Mutex<WatermarkFile>,
+}
+
+impl SignerServer {
+    pub fn new(signing_key: SigningKey, secret: Vec<u8>, watermark: WatermarkFile) -> Self {
+        let public_key = PublicKey(signing_key.verifying_key());
+        SignerServer { signing_key, public_key, secret, watermark: Mutex::new(watermark) }
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Accepts connections until the listener fails, handling each on its own thread.
+    pub fn serve_tcp(self: Arc<Self>, listener: std::net::TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept()?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                let result = stream.try_clone().and_then(|reader| server.handle_connection(reader, stream));
+                if let Err(e) = result {
+                    tracing::debug!("Signer connection from {} closed with error: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn serve_unix(self: Arc<Self>, listener: std::os::unix::net::UnixListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept()?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                let result = stream.try_clone().and_then(|reader| server.handle_connection(reader, stream));
+                if let Err(e) = result {
+                    tracing::debug!("Signer connection closed with error: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Sends a fresh challenge, then answers requests until the client disconnects. The
+    /// connection is dropped after the first request that fails authentication.
+    pub fn handle_connection(&self, reader: impl Read, mut writer: impl Write) -> std::io::Result<()> {
+        let mut challenge = [0u8; CHALLENGE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut challenge);
+        write_line(&mut writer, &SignerHello { challenge: hex::encode(challenge) })?;
+
+        let mut last_seq = 0;
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let request = match self.authenticate(&line, &challenge, last_seq) {
+                Ok((seq, request)) => {
+                    last_seq = seq;
+                    request
+                }
+                Err(e) => {
+                    tracing::warn!("Refused signer request: {}", e);
+                    return write_line(&mut writer, &SignerResponse::Error(e.to_string()));
+                }
+            };
+            let response = match self.handle(request) {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!("Refused to sign: {}", e);
+                    SignerResponse::Error(e.to_string())
+                }
+            };
+            write_line(&mut writer, &response)?;
+        }
+        Ok(())
+    }
+
+    fn authenticate(&self, line: &str, challenge: &[u8], last_seq: u64) -> Result<(u64, SignerRequest), SignerServerError> {
+        let envelope: AuthenticatedRequest = serde_json::from_str(line).map_err(|e| SignerServerError::InvalidRequest(e.to_string()))?;
+        let mac = hex::decode(&envelope.mac).map_err(|_| SignerServerError::Unauthenticated)?;
+        if envelope.seq <= last_seq || !verify_request_mac(&self.secret, challenge, envelope.seq, &envelope.request, &mac) {
+            return Err(SignerServerError::Unauthenticated);
+        }
+        let request = serde_json::from_str(&envelope.request).map_err(|e| SignerServerError::InvalidRequest(e.to_string()))?;
+        Ok((envelope.seq, request))
+    }
+
+    /// Handles an authenticated request.
+    pub fn handle(&self, request: SignerRequest) -> Result<SignerResponse, SignerServerError> {
+        match request {
+            SignerRequest::PublicKey => Ok(SignerResponse::PublicKey(self.public_key.to_string())),
+            SignerRequest::SignHeader { header } => {
+                let bytes = hex::decode(&header).map_err(|e| SignerServerError::InvalidRequest(format!("header is not hex: {}", e)))?;
+                let (header, _): (BlockHeader, _) = bincode::decode_from_slice(&bytes, bincode::config::standard())
+                    .map_err(|e| SignerServerError::InvalidRequest(format!("invalid header: {}", e)))?;
+                if header.validator != address_from_public_key(&self.public_key) {
+                    return Err(SignerServerError::WrongValidator(header.validator));
+                }
+                let hash = header.calculate_hash().map_err(|e| SignerServerError::InvalidRequest(e.to_string()))?;
+                // Held until the signature is made, so concurrent requests cannot both pass the check
+                let mut watermark = self.watermark.lock().unwrap();
+                watermark.advance(header.block_number.0, &hash)?;
+                let signature = self.signing_key.sign(&hash.0);
+                tracing::info!("Signed header at height {}: {}", header.block_number.0, hash);
+                Ok(SignerResponse::Signature(hex::encode(signature.to_bytes())))
+            }
+        }
+    }
+}
+
+fn write_line(writer: &mut impl Write, message: &impl Serialize) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).map_err(std::io::Error::other)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_signer::{BlockSigner, RemoteSigner, SignerAddress, SignerError};
+    use crate::types::{BlockHeight, Signature, Timestamp};
+    use tempfile::tempdir;
+
+    const SECRET: &[u8] = b"an example shared secret";
+
+    fn header(signing_key: &SigningKey, height: u64, timestamp: u64) -> BlockHeader {
+        BlockHeader {
+            parent_hash: Hash([height as u8; 32]),
+            block_number: BlockHeight(height),
+            timestamp: Timestamp(timestamp),
+            tx_root: Hash([0; 32]),
+            validator: address_from_public_key(&PublicKey(signing_key.verifying_key())),
+            signature: Signature(vec![]),
+        }
+    }
+
+    #[test]
+    fn test_watermark_refuses_double_sign() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("watermark.json");
+        let mut watermark = WatermarkFile::open(&path).unwrap();
+        assert!(watermark.current().is_none());
+
+        watermark.advance(5, &Hash([1; 32])).unwrap();
+        watermark.advance(5, &Hash([1; 32])).unwrap();
+        assert!(matches!(watermark.advance(5, &Hash([2; 32])), Err(SignerServerError::DoubleSign(5))));
+        assert!(matches!(watermark.advance(4, &Hash([3; 32])), Err(SignerServerError::BelowWatermark { height: 4, watermark: 5 })));
+
+        let mut reopened = WatermarkFile::open(&path).unwrap();
+        assert_eq!(reopened.current().map(|watermark| watermark.height), Some(5));
+        assert!(matches!(reopened.advance(5, &Hash([2; 32])), Err(SignerServerError::DoubleSign(5))));
+        reopened.advance(6, &Hash([2; 32])).unwrap();
+    }
+
+    #[test]
+    fn test_remote_signer_over_tcp() {
+        let dir = tempdir().unwrap();
+        let signing_key = SigningKey::from_bytes(&[9; 32]);
+        let watermark = WatermarkFile::open(&dir.path().join("watermark.json")).unwrap();
+        let server = Arc::new(SignerServer::new(signing_key.clone(), SECRET.to_vec(), watermark));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = SignerAddress::Tcp(listener.local_addr().unwrap().to_string());
+        std::thread::spawn(move || server.serve_tcp(listener));
+
+        let signer = RemoteSigner::connect(address.clone(), SECRET.to_vec()).unwrap();
+        assert_eq!(signer.public_key(), &PublicKey(signing_key.verifying_key()));
+
+        let first = header(&signing_key, 1, 100);
+        let signature = signer.sign_header(&first).unwrap();
+        let hash = first.calculate_hash().unwrap();
+        let signature = ed25519_dalek::Signature::from_bytes(signature.0.as_slice().try_into().unwrap());
+        assert!(signing_key.verifying_key().verify_strict(&hash.0, &signature).is_ok());
+        signer.sign_header(&first).expect("the same header may be signed again");
+
+        let conflicting = header(&signing_key, 1, 101);
+        assert!(matches!(signer.sign_header(&conflicting), Err(SignerError::Refused(_))));
+        let other_validator = header(&SigningKey::from_bytes(&[8; 32]), 2, 100);
+        assert!(matches!(signer.sign_header(&other_validator), Err(SignerError::Refused(_))));
+        signer.sign_header(&header(&signing_key, 2, 102)).unwrap();
+
+        assert!(matches!(RemoteSigner::connect(address, b"not the shared secret".to_vec()), Err(SignerError::Refused(_))));
+    }
+}
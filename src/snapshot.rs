@@ -0,0 +1,349 @@
+use crate::block::BlockHeader;
+use crate::state_machine::{Account, WorldState};
+use crate::types::{Address, BlockHeight, Hash};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// Default limit on the encoded size of a snapshot chunk. Chunks travel as single gossipsub
+/// messages, whose default limit is 64 KiB, so this leaves room for the message envelope.
+pub const DEFAULT_SNAPSHOT_CHUNK_BYTES: usize = 48 * 1024;
+
+/// Upper bound on the bytes a chunk encodes besides its accounts: the varint index and
+/// account count.
+const CHUNK_HEADER_BYTES: usize = 5 + 9;
+
+/// Errors that can occur while building or verifying a state snapshot.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[error("Snapshot header hash mismatch: manifest claims {expected}, header hashes to {actual}")]
+    HeaderHashMismatch { expected: Hash, actual: Hash },
+    #[error("Snapshot header height mismatch: manifest claims {expected}, header is at {actual}")]
+    HeaderHeightMismatch { expected: BlockHeight, actual: BlockHeight },
+    #[error("Snapshot chunk {index} hash mismatch: expected {expected}, got {actual}")]
+    ChunkHashMismatch { index: u32, expected: Hash, actual: Hash },
+    #[error("Snapshot chunk index {0} is out of range")]
+    UnknownChunk(u32),
+    #[error("Snapshot chunk {0} is missing")]
+    MissingChunk(u32),
+    #[error("Snapshot account count mismatch: manifest claims {expected}, chunks hold {actual}")]
+    AccountCountMismatch { expected: u64, actual: u64 },
+    #[error("Snapshot chunk size must be greater than zero")]
+    ZeroChunkSize,
+    #[error("Account {address} encodes to {size} bytes, more than fits in a {limit}-byte snapshot chunk")]
+    AccountTooLarge { address: Address, size: usize, limit: usize },
+    #[error("Snapshot encoding error: {0}")]
+    Encoding(String),
+}
+
+/// A contiguous, address-ordered slice of the world state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct SnapshotChunk {
+    pub index: u32,
+    pub accounts: Vec<(Address, Account)>,
+}
+
+impl SnapshotChunk {
+    /// Hashes the canonical bincode encoding of the chunk.
+    pub fn hash(&self) -> Result<Hash, SnapshotError> {
+        let encoded = bincode::encode_to_vec(self, bincode::config::standard())
+            .map_err(|e| SnapshotError::Encoding(e.to_string()))?;
+        let mut hasher = Sha256::new();
+        hasher.update(&encoded);
+        Ok(Hash(hasher.finalize().into()))
+    }
+}
+
+/// Describes a snapshot of the `state` column family taken at a specific block.
+///
+/// The manifest carries the full header of the block the state belongs to, so an
+/// importing node can check the header's proposer signature before trusting the
+/// listed chunk hashes. Headers do not commit to a state root yet, so the chunk
+/// hashes are only as trustworthy as the peer serving the manifest: fast sync must
+/// only be used with trusted peers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct SnapshotManifest {
+    pub height: BlockHeight,
+    pub block_hash: Hash,
+    pub header: BlockHeader,
+    pub account_count: u64,
+    pub chunk_hashes: Vec<Hash>,
+}
+
+impl SnapshotManifest {
+    /// Checks that the embedded header matches the claimed block hash and height.
+    pub fn verify_header(&self) -> Result<(), SnapshotError> {
+        let actual = self.header.calculate_hash().map_err(|e| SnapshotError::Encoding(e.to_string()))?;
+        if actual != self.block_hash {
+            return Err(SnapshotError::HeaderHashMismatch { expected: self.block_hash, actual });
+        }
+        if self.header.block_number != self.height {
+            return Err(SnapshotError::HeaderHeightMismatch {
+                expected: self.height,
+                actual: self.header.block_number,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks a single chunk against the hash listed in the manifest.
+    pub fn verify_chunk(&self, chunk: &SnapshotChunk) -> Result<(), SnapshotError> {
+        let expected = *self
+            .chunk_hashes
+            .get(chunk.index as usize)
+            .ok_or(SnapshotError::UnknownChunk(chunk.index))?;
+        let actual = chunk.hash()?;
+        if actual != expected {
+            return Err(SnapshotError::ChunkHashMismatch { index: chunk.index, expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Number of chunks the snapshot is split into.
+    pub fn chunk_count(&self) -> u32 {
+        self.chunk_hashes.len() as u32
+    }
+}
+
+/// A complete snapshot: the manifest plus every chunk it lists.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub manifest: SnapshotManifest,
+    pub chunks: Vec<SnapshotChunk>,
+}
+
+impl StateSnapshot {
+    /// Builds a snapshot from address-ordered accounts, splitting them into chunks
+    /// whose encoding is at most `max_chunk_bytes` long.
+    pub fn build(
+        header: BlockHeader,
+        accounts: Vec<(Address, Account)>,
+        max_chunk_bytes: usize,
+    ) -> Result<Self, SnapshotError> {
+        if max_chunk_bytes == 0 {
+            return Err(SnapshotError::ZeroChunkSize);
+        }
+        let block_hash = header.calculate_hash().map_err(|e| SnapshotError::Encoding(e.to_string()))?;
+        let account_count = accounts.len() as u64;
+
+        let mut chunks: Vec<SnapshotChunk> = Vec::new();
+        let mut chunk_bytes = 0;
+        for (address, account) in accounts {
+            let size = bincode::encode_to_vec((&address, &account), bincode::config::standard())
+                .map_err(|e| SnapshotError::Encoding(e.to_string()))?
+                .len();
+            if CHUNK_HEADER_BYTES + size > max_chunk_bytes {
+                return Err(SnapshotError::AccountTooLarge { address, size, limit: max_chunk_bytes });
+            }
+            match chunks.last_mut() {
+                Some(chunk) if chunk_bytes + size <= max_chunk_bytes => chunk.accounts.push((address, account)),
+                _ => {
+                    chunks.push(SnapshotChunk { index: chunks.len() as u32, accounts: vec![(address, account)] });
+                    chunk_bytes = CHUNK_HEADER_BYTES;
+                }
+            }
+            chunk_bytes += size;
+        }
+        let chunk_hashes = chunks.iter().map(SnapshotChunk::hash).collect::<Result<Vec<_>, _>>()?;
+
+        Ok(StateSnapshot {
+            manifest: SnapshotManifest {
+                height: header.block_number,
+                block_hash,
+                header,
+                account_count,
+                chunk_hashes,
+            },
+            chunks,
+        })
+    }
+
+    /// Verifies the header binding, every chunk hash and the total account count.
+    pub fn verify(&self) -> Result<(), SnapshotError> {
+        self.manifest.verify_header()?;
+        for index in 0..self.manifest.chunk_count() {
+            let chunk = self
+                .chunks
+                .get(index as usize)
+                .filter(|chunk| chunk.index == index)
+                .ok_or(SnapshotError::MissingChunk(index))?;
+            self.manifest.verify_chunk(chunk)?;
+        }
+        if self.chunks.len() as u32 != self.manifest.chunk_count() {
+            return Err(SnapshotError::UnknownChunk(self.manifest.chunk_count()));
+        }
+        let actual: u64 = self.chunks.iter().map(|chunk| chunk.accounts.len() as u64).sum();
+        if actual != self.manifest.account_count {
+            return Err(SnapshotError::AccountCountMismatch { expected: self.manifest.account_count, actual });
+        }
+        Ok(())
+    }
+
+    /// Flattens the snapshot chunks back into a world state.
+    pub fn to_world_state(&self) -> WorldState {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.accounts.iter().cloned())
+            .collect()
+    }
+}
+
+/// Tracks the chunks of a snapshot being downloaded from peers.
+#[derive(Debug)]
+pub struct SnapshotDownload {
+    manifest: SnapshotManifest,
+    chunks: BTreeMap<u32, SnapshotChunk>,
+}
+
+impl SnapshotDownload {
+    /// Starts a download after checking that the manifest header is self-consistent.
+    pub fn new(manifest: SnapshotManifest) -> Result<Self, SnapshotError> {
+        manifest.verify_header()?;
+        Ok(SnapshotDownload { manifest, chunks: BTreeMap::new() })
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Verifies and records a chunk. Returns `true` once every chunk has arrived.
+    pub fn insert_chunk(&mut self, chunk: SnapshotChunk) -> Result<bool, SnapshotError> {
+        self.manifest.verify_chunk(&chunk)?;
+        self.chunks.insert(chunk.index, chunk);
+        Ok(self.is_complete())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.chunks.len() as u32 == self.manifest.chunk_count()
+    }
+
+    /// Indices of chunks that have not been received yet.
+    pub fn missing_chunks(&self) -> Vec<u32> {
+        (0..self.manifest.chunk_count())
+            .filter(|index| !self.chunks.contains_key(index))
+            .collect()
+    }
+
+    /// Assembles and fully verifies the downloaded snapshot.
+    pub fn finish(self) -> Result<StateSnapshot, SnapshotError> {
+        if let Some(index) = self.missing_chunks().first() {
+            return Err(SnapshotError::MissingChunk(*index));
+        }
+        let snapshot = StateSnapshot {
+            manifest: self.manifest,
+            chunks: self.chunks.into_values().collect(),
+        };
+        snapshot.verify()?;
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::htlc::HashTimeLock;
+    use crate::types::{Nonce, Signature, Timestamp};
+
+    fn test_header(height: u64) -> BlockHeader {
+        BlockHeader {
+            parent_hash: Hash([0; 32]),
+            block_number: BlockHeight(height),
+            timestamp: Timestamp(123),
+            tx_root: Hash([1; 32]),
+            validator: Address([2; 32]),
+            signature: Signature(vec![0; 64]),
+        }
+    }
+
+    fn test_accounts(count: u8) -> Vec<(Address, Account)> {
+        (0..count)
+            .map(|i| (Address([i; 32]), Account { balance: 100 + i as u64, nonce: Nonce(i as u64), authorized_key: None, vesting: None, hash_lock: None }))
+            .collect()
+    }
+
+    /// Each test account encodes to 37 bytes.
+    const TWO_ACCOUNTS: usize = CHUNK_HEADER_BYTES + 2 * 37;
+
+    #[test]
+    fn test_build_splits_into_chunks() {
+        let snapshot = StateSnapshot::build(test_header(7), test_accounts(5), TWO_ACCOUNTS).unwrap();
+        assert_eq!(snapshot.manifest.height, BlockHeight(7));
+        assert_eq!(snapshot.manifest.account_count, 5);
+        assert_eq!(snapshot.manifest.chunk_count(), 3);
+        assert_eq!(snapshot.chunks[2].accounts.len(), 1);
+        assert!(snapshot.verify().is_ok());
+        assert_eq!(snapshot.to_world_state().len(), 5);
+    }
+
+    #[test]
+    fn test_build_rejects_zero_chunk_size() {
+        assert_eq!(
+            StateSnapshot::build(test_header(1), test_accounts(1), 0).unwrap_err(),
+            SnapshotError::ZeroChunkSize
+        );
+        assert!(matches!(
+            StateSnapshot::build(test_header(1), test_accounts(1), TWO_ACCOUNTS / 2),
+            Err(SnapshotError::AccountTooLarge { size: 37, .. })
+        ));
+    }
+
+    #[test]
+    fn test_chunks_fit_in_a_gossip_message() {
+        let lock = HashTimeLock { hashlock: Hash([3; 32]), timelock: BlockHeight(u64::MAX), recipient: Address([4; 32]), refund_address: Address([5; 32]) };
+        let accounts: Vec<(Address, Account)> = (0..4000u32)
+            .map(|i| {
+                let mut address = [0; 32];
+                address[..4].copy_from_slice(&i.to_be_bytes());
+                (Address(address), Account { balance: u64::MAX, nonce: Nonce(u64::MAX), hash_lock: Some(lock), ..Account::default() })
+            })
+            .collect();
+        let snapshot = StateSnapshot::build(test_header(2), accounts, DEFAULT_SNAPSHOT_CHUNK_BYTES).unwrap();
+        assert!(snapshot.manifest.chunk_count() > 1);
+        for chunk in &snapshot.chunks {
+            let message = crate::networking::NetworkMessage::SnapshotChunk { block_hash: Hash([0; 32]), chunk: chunk.clone() };
+            // gossipsub's default max_transmit_size
+            assert!(bincode::encode_to_vec(message, bincode::config::standard()).unwrap().len() < 65_536);
+        }
+        assert!(snapshot.verify().is_ok());
+    }
+
+    #[test]
+    fn test_tampered_chunk_is_rejected() {
+        let mut snapshot = StateSnapshot::build(test_header(3), test_accounts(4), TWO_ACCOUNTS).unwrap();
+        snapshot.chunks[1].accounts[0].1.balance += 1;
+        assert!(matches!(snapshot.verify(), Err(SnapshotError::ChunkHashMismatch { index: 1, .. })));
+    }
+
+    #[test]
+    fn test_tampered_header_is_rejected() {
+        let mut snapshot = StateSnapshot::build(test_header(3), test_accounts(4), TWO_ACCOUNTS).unwrap();
+        snapshot.manifest.header.timestamp = Timestamp(999);
+        assert!(matches!(snapshot.verify(), Err(SnapshotError::HeaderHashMismatch { .. })));
+    }
+
+    #[test]
+    fn test_download_assembles_out_of_order_chunks() {
+        let snapshot = StateSnapshot::build(test_header(9), test_accounts(5), TWO_ACCOUNTS).unwrap();
+        let mut download = SnapshotDownload::new(snapshot.manifest.clone()).unwrap();
+
+        assert!(!download.insert_chunk(snapshot.chunks[2].clone()).unwrap());
+        assert!(!download.insert_chunk(snapshot.chunks[0].clone()).unwrap());
+        assert_eq!(download.missing_chunks(), vec![1]);
+        assert!(download.insert_chunk(snapshot.chunks[1].clone()).unwrap());
+
+        let assembled = download.finish().unwrap();
+        assert_eq!(assembled, snapshot);
+    }
+
+    #[test]
+    fn test_download_rejects_foreign_chunk() {
+        let snapshot = StateSnapshot::build(test_header(9), test_accounts(4), TWO_ACCOUNTS).unwrap();
+        let other = StateSnapshot::build(test_header(9), test_accounts(3), TWO_ACCOUNTS).unwrap();
+        let mut download = SnapshotDownload::new(snapshot.manifest.clone()).unwrap();
+
+        assert!(download.insert_chunk(other.chunks[1].clone()).is_err());
+        assert_eq!(download.finish().unwrap_err(), SnapshotError::MissingChunk(0));
+    }
+}
@@ -1,15 +1,42 @@
 use crate::block::Block;
-use crate::transaction::Transaction;
-use crate::types::{Address, Nonce, address_from_public_key};
-use std::collections::HashMap;
+use crate::htlc::HashTimeLock;
+use crate::transaction::{Transaction, TransactionKind, Transfer, TxSender};
+use crate::types::{address_from_public_key, Address, BlockHeight, Nonce, PublicKey};
+use crate::vesting::VestingSchedule;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 use bincode::{Encode, Decode};
+use serde::{Deserialize, Serialize};
 
 /// Represents an account in the world state.
-#[derive(Clone, Debug, PartialEq, Eq, Default, Encode, Decode)]
+#[derive(Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize, Encode, Decode)]
 pub struct Account {
     pub balance: u64,
     pub nonce: Nonce,
+    /// The only key allowed to sign for this account after a key rotation. `None` means the
+    /// key the address was derived from (or the multisig policy it commits to).
+    #[serde(default)]
+    pub authorized_key: Option<PublicKey>,
+    /// Part of the balance that only becomes spendable over time, e.g. a genesis allocation
+    /// to the team.
+    #[serde(default)]
+    pub vesting: Option<VestingSchedule>,
+    /// Set on escrow accounts created by `LockFunds`. The whole balance goes to the lock's
+    /// recipient on a claim or back to its refund address after the timelock.
+    #[serde(default)]
+    pub hash_lock: Option<HashTimeLock>,
+}
+
+impl Account {
+    /// The part of the balance that may not be spent in a block at `height`.
+    pub fn locked_at(&self, height: BlockHeight) -> u64 {
+        self.vesting.map_or(0, |schedule| schedule.locked_at(height))
+    }
+
+    /// The part of the balance that may be spent in a block at `height`.
+    pub fn spendable_at(&self, height: BlockHeight) -> u64 {
+        self.balance.saturating_sub(self.locked_at(height))
+    }
 }
 
 /// The entire state of the blockchain world.
@@ -28,12 +55,96 @@ pub enum StateMachineError {
     TransactionValidation(String),
     #[error("Incorrect nonce: expected {expected}, got {actual}")]
     IncorrectNonce { expected: Nonce, actual: Nonce },
+    #[error("Transaction amount overflows")]
+    AmountOverflow,
+    #[error("Balance of {0:?} would overflow")]
+    BalanceOverflow(Address),
+    #[error("Nonce of {0:?} would overflow")]
+    NonceOverflow(Address),
+    #[error("Total supply would overflow")]
+    SupplyOverflow,
+    #[error("Tracked supply {tracked} does not match the sum of balances {actual}")]
+    SupplyMismatch { tracked: u64, actual: u64 },
+    #[error("Transaction is not valid until after height {valid_after}, block is at height {height}")]
+    TransactionNotYetValid { valid_after: BlockHeight, height: BlockHeight },
+    #[error("Transaction expired at height {valid_until}, block is at height {height}")]
+    TransactionExpired { valid_until: BlockHeight, height: BlockHeight },
+    #[error("Signer is not the authorized key of account {0:?}")]
+    UnauthorizedSigner(Address),
+    #[error("Insufficient unlocked balance: {unlocked} of the balance is spendable, needs {required}")]
+    BalanceLocked { unlocked: u64, required: u64 },
+    #[error("No open hash-time lock at {0:?}")]
+    LockNotFound(Address),
+    #[error("Lock {lock_id:?} expired at height {timelock}, block is at height {height}")]
+    LockExpired { lock_id: Address, timelock: BlockHeight, height: BlockHeight },
+    #[error("Lock {lock_id:?} cannot be refunded before height {timelock}, block is at height {height}")]
+    LockNotExpired { lock_id: Address, timelock: BlockHeight, height: BlockHeight },
+    #[error("Preimage does not match the hashlock of lock {0:?}")]
+    WrongPreimage(Address),
+    #[error("Timelock {timelock} has already passed at height {height}, so the lock could never be claimed")]
+    TimelockPassed { timelock: BlockHeight, height: BlockHeight },
 }
 
 /// The state machine is responsible for processing transactions and blocks
 /// and updating the world state.
 pub struct StateMachine {
     pub world_state: WorldState,
+    /// Sum of all balances. Transactions only move funds, so this changes only when accounts
+    /// are set directly (genesis, snapshots).
+    total_supply: u64,
+}
+
+/// Copies of the accounts a transaction touches. Every step of a transaction is applied
+/// here first and written back only once all of them succeeded, so a failing step never
+/// leaves a partially applied transaction behind.
+struct AccountChanges<'a> {
+    world_state: &'a WorldState,
+    touched: HashMap<Address, Account>,
+}
+
+impl<'a> AccountChanges<'a> {
+    fn new(world_state: &'a WorldState) -> Self {
+        AccountChanges { world_state, touched: HashMap::new() }
+    }
+
+    fn account_mut(&mut self, address: Address) -> &mut Account {
+        let world_state = self.world_state;
+        self.touched
+            .entry(address)
+            .or_insert_with(|| world_state.get(&address).cloned().unwrap_or_default())
+    }
+
+    fn debit(&mut self, address: Address, amount: u64) -> Result<(), StateMachineError> {
+        let account = self.account_mut(address);
+        account.balance = account.balance.checked_sub(amount).ok_or(StateMachineError::InsufficientBalance {
+            current: account.balance,
+            required: amount,
+        })?;
+        Ok(())
+    }
+
+    fn credit(&mut self, address: Address, amount: u64) -> Result<(), StateMachineError> {
+        let account = self.account_mut(address);
+        account.balance = account
+            .balance
+            .checked_add(amount)
+            .ok_or(StateMachineError::BalanceOverflow(address))?;
+        Ok(())
+    }
+
+    fn increment_nonce(&mut self, address: Address) -> Result<(), StateMachineError> {
+        let account = self.account_mut(address);
+        account.nonce.0 = account.nonce.0.checked_add(1).ok_or(StateMachineError::NonceOverflow(address))?;
+        Ok(())
+    }
+}
+
+/// Sums the balances of `world_state`, failing if the total does not fit in a `u64`.
+pub fn total_supply_of(world_state: &WorldState) -> Result<u64, StateMachineError> {
+    world_state
+        .values()
+        .try_fold(0u64, |total, account| total.checked_add(account.balance))
+        .ok_or(StateMachineError::SupplyOverflow)
 }
 
 impl StateMachine {
@@ -41,59 +152,167 @@ impl StateMachine {
     pub fn new() -> Self {
         StateMachine {
             world_state: HashMap::new(),
+            total_supply: 0,
         }
     }
 
-    /// Creates a new state machine from a given world state.
-    pub fn from_world_state(world_state: WorldState) -> Self {
-        StateMachine { world_state }
+    /// Creates a new state machine from a given world state, rejecting states whose total
+    /// supply overflows.
+    pub fn from_world_state(world_state: WorldState) -> Result<Self, StateMachineError> {
+        let total_supply = total_supply_of(&world_state)?;
+        Ok(StateMachine { world_state, total_supply })
     }
 
-    /// Applies a single transaction to the world state.
+    /// The sum of all account balances.
+    pub fn total_supply(&self) -> u64 {
+        self.total_supply
+    }
+
+    /// Recomputes the supply from the world state and compares it with the tracked value.
+    pub fn check_supply_invariant(&self) -> Result<(), StateMachineError> {
+        let actual = total_supply_of(&self.world_state)?;
+        if actual != self.total_supply {
+            return Err(StateMachineError::SupplyMismatch { tracked: self.total_supply, actual });
+        }
+        Ok(())
+    }
+
+    /// Applies a single transaction, included in a block at `height`, to the world state,
+    /// dispatching on its kind.
     pub fn apply_transaction(
         &mut self,
         tx: &Transaction,
+        height: BlockHeight,
     ) -> Result<(), StateMachineError> {
-        self.validate_transaction_stateful(tx)?;
+        self.commit_transaction(tx, height).map(|_| ())
+    }
 
-        let sender_address = address_from_public_key(&tx.sender);
-        let recipient_address = tx.recipient;
+    /// Applies a transaction like `apply_transaction` and returns the accounts it touched.
+    fn commit_transaction(&mut self, tx: &Transaction, height: BlockHeight) -> Result<Vec<Address>, StateMachineError> {
+        self.validate_transaction_stateful(tx, height)?;
 
-        // Decrement sender balance and increment nonce
-        let sender_account = self
-            .world_state
-            .get_mut(&sender_address)
-            .ok_or(StateMachineError::AccountNotFound(sender_address))?;
-        sender_account.balance -= tx.amount;
-        sender_account.nonce.0 += 1;
+        let mut changes = AccountChanges::new(&self.world_state);
+        self.apply_kind(&mut changes, tx)?;
 
-        // Increment recipient balance
-        let recipient_account = self
-            .world_state
-            .entry(recipient_address)
-            .or_insert_with(Account::default);
-        recipient_account.balance += tx.amount;
+        let touched = changes.touched;
+        let addresses = touched.keys().copied().collect();
+        self.world_state.extend(touched);
+        Ok(addresses)
+    }
 
+    /// Applies the effects of an already validated transaction to `changes`.
+    fn apply_kind(&self, changes: &mut AccountChanges, tx: &Transaction) -> Result<(), StateMachineError> {
+        let sender_address = tx.sender_address();
+        match &tx.kind {
+            TransactionKind::Transfer(transfer) => Self::apply_transfers(changes, sender_address, std::slice::from_ref(transfer))?,
+            TransactionKind::BatchTransfer(transfers) => Self::apply_transfers(changes, sender_address, transfers)?,
+            TransactionKind::RotateKey { new_key } => {
+                // Rotating back to the derived key is stored as the default
+                let derived = address_from_public_key(new_key) == sender_address;
+                changes.account_mut(sender_address).authorized_key = (!derived).then_some(*new_key);
+            }
+            TransactionKind::LockFunds { amount, lock } => {
+                let lock_id = HashTimeLock::lock_address(&sender_address, tx.nonce);
+                changes.debit(sender_address, *amount)?;
+                changes.credit(lock_id, *amount)?;
+                changes.account_mut(lock_id).hash_lock = Some(*lock);
+            }
+            TransactionKind::ClaimLock { lock_id, .. } => {
+                let lock = self.open_lock(lock_id)?;
+                Self::release_lock(changes, *lock_id, lock.recipient)?;
+            }
+            TransactionKind::RefundLock { lock_id } => {
+                let lock = self.open_lock(lock_id)?;
+                Self::release_lock(changes, *lock_id, lock.refund_address)?;
+            }
+            TransactionKind::Relayed(inner) => self.apply_kind(changes, inner)?,
+        }
+
+        // Every kind consumes the sender's nonce, and a relay also the relayed sender's
+        changes.increment_nonce(sender_address)
+    }
+
+    /// Debits the total once, then credits every entry. A single transfer is a batch of one.
+    fn apply_transfers(changes: &mut AccountChanges, sender_address: Address, transfers: &[Transfer]) -> Result<(), StateMachineError> {
+        let total = transfers
+            .iter()
+            .try_fold(0u64, |total, transfer| total.checked_add(transfer.amount))
+            .ok_or(StateMachineError::AmountOverflow)?;
+        changes.debit(sender_address, total)?;
+        for transfer in transfers {
+            changes.credit(transfer.recipient, transfer.amount)?;
+        }
         Ok(())
     }
 
-    /// Validates a transaction against the current world state.
+    /// The terms of the escrow at `lock_id`, if it has not been claimed or refunded yet.
+    fn open_lock(&self, lock_id: &Address) -> Result<HashTimeLock, StateMachineError> {
+        self.world_state
+            .get(lock_id)
+            .and_then(|account| account.hash_lock)
+            .ok_or(StateMachineError::LockNotFound(*lock_id))
+    }
+
+    /// Pays out the whole escrow balance and closes the lock, so it cannot be settled twice.
+    fn release_lock(changes: &mut AccountChanges, lock_id: Address, to: Address) -> Result<(), StateMachineError> {
+        let escrow = changes.account_mut(lock_id);
+        let amount = escrow.balance;
+        escrow.hash_lock = None;
+        changes.debit(lock_id, amount)?;
+        changes.credit(to, amount)
+    }
+
+    /// Validates a transaction for inclusion in a block at `height`: its signatures, then its
+    /// effects against the current world state.
     pub fn validate_transaction_stateful(
         &self,
         tx: &Transaction,
+        height: BlockHeight,
     ) -> Result<(), StateMachineError> {
-        let sender_address = address_from_public_key(&tx.sender);
+        // Blocks are not trusted to carry signed transactions: a block's signature only
+        // vouches for its proposer, so every transaction's own signatures are checked here
+        tx.validate().map_err(|e| StateMachineError::TransactionValidation(e.to_string()))?;
+        self.check_against_state(tx, height)
+    }
+
+    /// The stateful part of `validate_transaction_stateful`, for a transaction whose
+    /// signatures (and those of a relayed transaction) were already verified.
+    fn check_against_state(&self, tx: &Transaction, height: BlockHeight) -> Result<(), StateMachineError> {
+        if let Some(valid_after) = tx.validity.valid_after_height.filter(|after| height <= *after) {
+            return Err(StateMachineError::TransactionNotYetValid { valid_after, height });
+        }
+        if let Some(valid_until) = tx.validity.valid_until_height.filter(|until| height > *until) {
+            return Err(StateMachineError::TransactionExpired { valid_until, height });
+        }
+
+        let sender_address = tx.sender_address();
         let sender_account = self
             .world_state
             .get(&sender_address)
             .ok_or(StateMachineError::AccountNotFound(sender_address))?;
 
-        if sender_account.balance < tx.amount {
+        let authorized = match (&sender_account.authorized_key, &tx.sender) {
+            (Some(authorized_key), sender) => sender.signing_key() == Some(authorized_key),
+            (None, TxSender::AuthorizedKey { public_key, .. }) => address_from_public_key(public_key) == sender_address,
+            (None, TxSender::Single(_) | TxSender::Multisig(_)) => true,
+        };
+        if !authorized {
+            return Err(StateMachineError::UnauthorizedSigner(sender_address));
+        }
+
+        // Every kind only debits its own sender (a relayed transaction is checked on its own
+        // below), so one balance check covers them all
+        let required = tx.kind.total_amount().ok_or(StateMachineError::AmountOverflow)?;
+        if sender_account.balance < required {
             return Err(StateMachineError::InsufficientBalance {
                 current: sender_account.balance,
-                required: tx.amount,
+                required,
             });
         }
+        let unlocked = sender_account.spendable_at(height);
+        if unlocked < required {
+            return Err(StateMachineError::BalanceLocked { unlocked, required });
+        }
 
         if sender_account.nonce != tx.nonce {
             return Err(StateMachineError::InvalidNonce {
@@ -102,25 +321,68 @@ impl StateMachine {
             });
         }
 
+        match &tx.kind {
+            TransactionKind::ClaimLock { lock_id, preimage } => {
+                let lock = self.open_lock(lock_id)?;
+                if lock.is_expired(height) {
+                    return Err(StateMachineError::LockExpired { lock_id: *lock_id, timelock: lock.timelock, height });
+                }
+                if !lock.opens_with(preimage) {
+                    return Err(StateMachineError::WrongPreimage(*lock_id));
+                }
+            }
+            TransactionKind::RefundLock { lock_id } => {
+                let lock = self.open_lock(lock_id)?;
+                if !lock.is_expired(height) {
+                    return Err(StateMachineError::LockNotExpired { lock_id: *lock_id, timelock: lock.timelock, height });
+                }
+            }
+            TransactionKind::LockFunds { lock, .. } => {
+                if lock.is_expired(height) {
+                    return Err(StateMachineError::TimelockPassed { timelock: lock.timelock, height });
+                }
+            }
+            TransactionKind::Relayed(inner) => self.check_against_state(inner, height)?,
+            TransactionKind::Transfer(_)
+            | TransactionKind::BatchTransfer(_)
+            | TransactionKind::RotateKey { .. } => {}
+        }
+
         Ok(())
     }
 
-    /// Applies a block of transactions to the world state.
+    /// Applies a block of transactions to the world state and returns the new value of
+    /// every account the block touched, which is what storage needs to persist.
     /// If any transaction fails, the state is not modified.
-    pub fn apply_block(&mut self, block: &Block) -> Result<(), StateMachineError> {
+    pub fn apply_block(&mut self, block: &Block) -> Result<WorldState, StateMachineError> {
         let original_state = self.world_state.clone();
+        let mut touched = HashSet::new();
         for tx in &block.transactions {
-            if let Err(e) = self.apply_transaction(tx) {
-                self.world_state = original_state; // Revert state on failure
-                return Err(e);
+            match self.commit_transaction(tx, block.header.block_number) {
+                Ok(addresses) => touched.extend(addresses),
+                Err(e) => {
+                    self.world_state = original_state; // Revert state on failure
+                    return Err(e);
+                }
             }
         }
-        Ok(())
+        Ok(touched
+            .into_iter()
+            .filter_map(|address| self.world_state.get(&address).map(|account| (address, account.clone())))
+            .collect())
     }
 
-    /// Set an account in the world state (for genesis initialization)
-    pub fn set_account(&mut self, address: Address, account: Account) {
+    /// Set an account in the world state (for genesis initialization), adjusting the
+    /// total supply by the change in its balance.
+    pub fn set_account(&mut self, address: Address, account: Account) -> Result<(), StateMachineError> {
+        let previous_balance = self.world_state.get(&address).map_or(0, |account| account.balance);
+        self.total_supply = self
+            .total_supply
+            .checked_sub(previous_balance)
+            .and_then(|supply| supply.checked_add(account.balance))
+            .ok_or(StateMachineError::SupplyOverflow)?;
         self.world_state.insert(address, account);
+        Ok(())
     }
 
     /// Get an account from the world state
@@ -132,23 +394,24 @@ impl StateMachine {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::{Address, BlockHeight, Nonce, PublicKey, Signature};
+    use crate::types::{address_from_public_key, Address, BlockHeight, Nonce, Signature};
+    use crate::transaction::ValidityWindow;
+    use crate::htlc::{hashlock_of, HashTimeLock};
+    use crate::vesting::VestingSchedule;
     use crate::wallet::Wallet;
-    use ed25519_dalek::{Signer, SigningKey};
-    use rand::rngs::OsRng;
+    use ed25519_dalek::SigningKey;
     use crate::block::{Block, BlockHeader};
     use crate::types::{Hash, Timestamp};
 
-    fn generate_test_wallet() -> (SigningKey, Address) {
-        let mut csprng = OsRng;
-        let signing_key = SigningKey::generate(&mut csprng);
-        let address = Address(signing_key.verifying_key().to_bytes());
-        (signing_key, address)
+    fn generate_test_wallet() -> (Wallet, Address) {
+        let wallet = Wallet::new();
+        let address = address_from_public_key(wallet.public_key());
+        (wallet, address)
     }
 
     #[test]
     fn test_apply_valid_transaction() {
-        let (sender_sk, sender_addr) = generate_test_wallet();
+        let (sender_wallet, sender_addr) = generate_test_wallet();
         let (_, recipient_addr) = generate_test_wallet();
 
         let mut world_state = WorldState::new();
@@ -157,20 +420,17 @@ mod tests {
             Account {
                 balance: 1000,
                 nonce: Nonce(0),
+                authorized_key: None,
+                vesting: None,
+                hash_lock: None,
             },
         );
 
-        let mut state_machine = StateMachine::from_world_state(world_state);
+        let mut state_machine = StateMachine::from_world_state(world_state).unwrap();
 
-        let tx = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr,
-            amount: 100,
-            nonce: Nonce(0),
-            signature: Signature(sender_sk.sign(b"test").to_bytes().to_vec()),
-        };
+        let tx = sender_wallet.create_signed_transaction(recipient_addr, 100, Nonce(0)).unwrap();
 
-        assert!(state_machine.apply_transaction(&tx).is_ok());
+        assert!(state_machine.apply_transaction(&tx, BlockHeight(1)).is_ok());
 
         let sender_account = state_machine.world_state.get(&sender_addr).unwrap();
         assert_eq!(sender_account.balance, 900);
@@ -182,7 +442,7 @@ mod tests {
 
     #[test]
     fn test_insufficient_balance() {
-        let (sender_sk, sender_addr) = generate_test_wallet();
+        let (sender_wallet, sender_addr) = generate_test_wallet();
         let (_, recipient_addr) = generate_test_wallet();
 
         let mut world_state = WorldState::new();
@@ -191,21 +451,18 @@ mod tests {
             Account {
                 balance: 50,
                 nonce: Nonce(0),
+                authorized_key: None,
+                vesting: None,
+                hash_lock: None,
             },
         );
 
-        let mut state_machine = StateMachine::from_world_state(world_state);
+        let mut state_machine = StateMachine::from_world_state(world_state).unwrap();
 
-        let tx = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr,
-            amount: 100,
-            nonce: Nonce(0),
-            signature: Signature(sender_sk.sign(b"test").to_bytes().to_vec()),
-        };
+        let tx = sender_wallet.create_signed_transaction(recipient_addr, 100, Nonce(0)).unwrap();
 
         assert_eq!(
-            state_machine.apply_transaction(&tx).unwrap_err(),
+            state_machine.apply_transaction(&tx, BlockHeight(1)).unwrap_err(),
             StateMachineError::InsufficientBalance {
                 current: 50,
                 required: 100
@@ -215,7 +472,7 @@ mod tests {
 
     #[test]
     fn test_invalid_nonce() {
-        let (sender_sk, sender_addr) = generate_test_wallet();
+        let (sender_wallet, sender_addr) = generate_test_wallet();
         let (_, recipient_addr) = generate_test_wallet();
 
         let mut world_state = WorldState::new();
@@ -224,20 +481,17 @@ mod tests {
             Account {
                 balance: 1000,
                 nonce: Nonce(5),
+                authorized_key: None,
+                vesting: None,
+                hash_lock: None,
             },
         );
 
-        let mut state_machine = StateMachine::from_world_state(world_state);
-        let tx = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr,
-            amount: 100,
-            nonce: Nonce(0),
-            signature: Signature(sender_sk.sign(b"test").to_bytes().to_vec()),
-        };
+        let mut state_machine = StateMachine::from_world_state(world_state).unwrap();
+        let tx = sender_wallet.create_signed_transaction(recipient_addr, 100, Nonce(0)).unwrap();
 
         assert_eq!(
-            state_machine.apply_transaction(&tx).unwrap_err(),
+            state_machine.apply_transaction(&tx, BlockHeight(1)).unwrap_err(),
             StateMachineError::InvalidNonce {
                 expected: Nonce(5),
                 actual: Nonce(0)
@@ -247,7 +501,7 @@ mod tests {
 
     #[test]
     fn test_apply_block() {
-        let (sender_sk, sender_addr) = generate_test_wallet();
+        let (sender_wallet, sender_addr) = generate_test_wallet();
         let (_, recipient_addr1) = generate_test_wallet();
         let (_, recipient_addr2) = generate_test_wallet();
 
@@ -257,25 +511,16 @@ mod tests {
             Account {
                 balance: 1000,
                 nonce: Nonce(0),
+                authorized_key: None,
+                vesting: None,
+                hash_lock: None,
             },
         );
 
-        let mut state_machine = StateMachine::from_world_state(world_state);
+        let mut state_machine = StateMachine::from_world_state(world_state).unwrap();
 
-        let tx1 = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr1,
-            amount: 100,
-            nonce: Nonce(0),
-            signature: Signature(sender_sk.sign(b"test1").to_bytes().to_vec()),
-        };
-        let tx2 = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr2,
-            amount: 200,
-            nonce: Nonce(1),
-            signature: Signature(sender_sk.sign(b"test2").to_bytes().to_vec()),
-        };
+        let tx1 = sender_wallet.create_signed_transaction(recipient_addr1, 100, Nonce(0)).unwrap();
+        let tx2 = sender_wallet.create_signed_transaction(recipient_addr2, 200, Nonce(1)).unwrap();
 
         let block = Block {
             header: crate::block::BlockHeader {
@@ -284,21 +529,27 @@ mod tests {
                 timestamp: crate::types::Timestamp(0),
                 tx_root: Default::default(),
                 validator: Default::default(),
-                signature: Signature(sender_sk.sign(b"block").to_bytes().to_vec()),
+                signature: Signature(vec![]),
             },
             transactions: vec![tx1, tx2],
         };
 
-        assert!(state_machine.apply_block(&block).is_ok());
+        let changed = state_machine.apply_block(&block).unwrap();
 
         let sender_account = state_machine.world_state.get(&sender_addr).unwrap();
         assert_eq!(sender_account.balance, 700);
         assert_eq!(sender_account.nonce, Nonce(2));
+        let mut changed_addresses: Vec<Address> = changed.keys().copied().collect();
+        changed_addresses.sort();
+        let mut expected = vec![sender_addr, recipient_addr1, recipient_addr2];
+        expected.sort();
+        assert_eq!(changed_addresses, expected);
+        assert_eq!(changed[&recipient_addr2].balance, 200);
     }
 
     #[test]
     fn test_apply_block_with_invalid_tx_reverts_state() {
-        let (sender_sk, sender_addr) = generate_test_wallet();
+        let (sender_wallet, sender_addr) = generate_test_wallet();
         let (_, recipient_addr) = generate_test_wallet();
 
         let mut world_state = WorldState::new();
@@ -307,27 +558,18 @@ mod tests {
             Account {
                 balance: 1000,
                 nonce: Nonce(0),
+                authorized_key: None,
+                vesting: None,
+                hash_lock: None,
             },
         );
         let initial_state = world_state.clone();
 
-        let mut state_machine = StateMachine::from_world_state(world_state);
+        let mut state_machine = StateMachine::from_world_state(world_state).unwrap();
 
-        let tx1 = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr,
-            amount: 100,
-            nonce: Nonce(0),
-            signature: Signature(sender_sk.sign(b"test1").to_bytes().to_vec()),
-        };
+        let tx1 = sender_wallet.create_signed_transaction(recipient_addr, 100, Nonce(0)).unwrap();
         // Invalid nonce
-        let tx2_invalid = Transaction {
-            sender: PublicKey(sender_sk.verifying_key()),
-            recipient: recipient_addr,
-            amount: 200,
-            nonce: Nonce(0),
-            signature: Signature(sender_sk.sign(b"test2").to_bytes().to_vec()),
-        };
+        let tx2_invalid = sender_wallet.create_signed_transaction(recipient_addr, 200, Nonce(0)).unwrap();
 
         let block = Block {
             header: crate::block::BlockHeader {
@@ -336,7 +578,7 @@ mod tests {
                 timestamp: crate::types::Timestamp(0),
                 tx_root: Default::default(),
                 validator: Default::default(),
-                signature: Signature(sender_sk.sign(b"block").to_bytes().to_vec()),
+                signature: Signature(vec![]),
             },
             transactions: vec![tx1, tx2_invalid],
         };
@@ -353,16 +595,16 @@ mod tests {
 
         // Add sender to state with initial balance
         let sender_address = address_from_public_key(&sender_wallet.public_key());
-        sm.world_state.insert(sender_address, Account { balance: 1000, nonce: Nonce(0) });
+        sm.world_state.insert(sender_address, Account { balance: 1000, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None });
 
         let tx = sender_wallet.create_signed_transaction(recipient_address, 100, Nonce(0)).unwrap();
         
-        let result = sm.apply_transaction(&tx);
+        let result = sm.apply_transaction(&tx, BlockHeight(1));
         assert!(result.is_ok());
 
         // Test insufficient balance
         let tx2 = sender_wallet.create_signed_transaction(recipient_address, 2000, Nonce(1)).unwrap();
-        let result2 = sm.apply_transaction(&tx2);
+        let result2 = sm.apply_transaction(&tx2, BlockHeight(1));
         assert!(matches!(result2, Err(StateMachineError::InsufficientBalance { .. })));
     }
 
@@ -373,14 +615,293 @@ mod tests {
         let recipient_address = Address([2u8; 32]);
 
         let sender_address = address_from_public_key(&sender_wallet.public_key());
-        sm.world_state.insert(sender_address, Account { balance: 1000, nonce: Nonce(5) });
+        sm.world_state.insert(sender_address, Account { balance: 1000, nonce: Nonce(5), authorized_key: None, vesting: None, hash_lock: None });
 
         let tx = sender_wallet.create_signed_transaction(recipient_address, 100, Nonce(0)).unwrap();
 
-        let result = sm.apply_transaction(&tx);
+        let result = sm.apply_transaction(&tx, BlockHeight(1));
         assert!(matches!(result, Err(StateMachineError::IncorrectNonce { .. })));
     }
 
+    #[test]
+    fn test_apply_batch_transfer() {
+        let mut sm = StateMachine::new();
+        let sender_wallet = Wallet::new();
+        let sender_address = address_from_public_key(sender_wallet.public_key());
+        sm.world_state.insert(sender_address, Account { balance: 100, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None });
+        let (alice, bob) = (Address([2u8; 32]), Address([3u8; 32]));
+        let payout = |recipient, amount| Transfer { recipient, amount };
+
+        // Repeated recipients and paying oneself are both allowed
+        let batch = TransactionKind::BatchTransfer(vec![payout(alice, 10), payout(bob, 20), payout(alice, 5), payout(sender_address, 1)]);
+        let tx = sender_wallet.create_signed_transaction_with_kind(batch, Nonce(0)).unwrap();
+        sm.apply_transaction(&tx, BlockHeight(1)).unwrap();
+        assert_eq!(sm.world_state[&sender_address], Account { balance: 65, nonce: Nonce(1), authorized_key: None, vesting: None, hash_lock: None });
+        assert_eq!(sm.world_state[&alice].balance, 15);
+        assert_eq!(sm.world_state[&bob].balance, 20);
+
+        // The balance check covers the sum, not each entry
+        let batch = TransactionKind::BatchTransfer(vec![payout(alice, 40), payout(bob, 40)]);
+        let tx = sender_wallet.create_signed_transaction_with_kind(batch, Nonce(1)).unwrap();
+        let before = sm.world_state.clone();
+        assert_eq!(sm.apply_transaction(&tx, BlockHeight(1)), Err(StateMachineError::InsufficientBalance { current: 65, required: 80 }));
+        assert_eq!(sm.world_state, before);
+
+        // One overflowing credit rejects the whole batch
+        sm.world_state.insert(bob, Account { balance: u64::MAX, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None });
+        let batch = TransactionKind::BatchTransfer(vec![payout(alice, 1), payout(bob, 1)]);
+        let tx = sender_wallet.create_signed_transaction_with_kind(batch, Nonce(1)).unwrap();
+        let before = sm.world_state.clone();
+        assert_eq!(sm.apply_transaction(&tx, BlockHeight(1)), Err(StateMachineError::BalanceOverflow(bob)));
+        assert_eq!(sm.world_state, before);
+    }
+
+    #[test]
+    fn test_overflow_is_rejected() {
+        let mut sm = StateMachine::new();
+        sm.set_account(Address([1u8; 32]), Account { balance: u64::MAX, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None }).unwrap();
+        assert_eq!(
+            sm.set_account(Address([2u8; 32]), Account { balance: 1, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None }),
+            Err(StateMachineError::SupplyOverflow)
+        );
+        assert_eq!(sm.total_supply(), u64::MAX);
+
+        let sender_wallet = Wallet::new();
+        let sender_address = address_from_public_key(sender_wallet.public_key());
+        let mut sm = StateMachine::new();
+        sm.set_account(sender_address, Account { balance: 10, nonce: Nonce(u64::MAX), authorized_key: None, vesting: None, hash_lock: None }).unwrap();
+        let tx = sender_wallet.create_signed_transaction(Address([2u8; 32]), 5, Nonce(u64::MAX)).unwrap();
+        let before = sm.world_state.clone();
+        assert_eq!(sm.apply_transaction(&tx, BlockHeight(1)), Err(StateMachineError::NonceOverflow(sender_address)));
+        assert_eq!(sm.world_state, before);
+    }
+
+    #[test]
+    fn test_validity_window_is_enforced() {
+        let mut sm = StateMachine::new();
+        let sender_wallet = Wallet::new();
+        let sender_address = address_from_public_key(sender_wallet.public_key());
+        sm.set_account(sender_address, Account { balance: 100, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None }).unwrap();
+
+        let validity = ValidityWindow { valid_after_height: Some(BlockHeight(2)), valid_until_height: Some(BlockHeight(4)) };
+        let tx = Transaction::new(*sender_wallet.public_key(), Address([2u8; 32]), 10, Nonce(0), Signature(vec![]))
+            .with_validity(validity);
+        let tx = sender_wallet.sign_transaction(tx).unwrap();
+
+        assert_eq!(
+            sm.validate_transaction_stateful(&tx, BlockHeight(2)),
+            Err(StateMachineError::TransactionNotYetValid { valid_after: BlockHeight(2), height: BlockHeight(2) })
+        );
+        assert_eq!(
+            sm.validate_transaction_stateful(&tx, BlockHeight(5)),
+            Err(StateMachineError::TransactionExpired { valid_until: BlockHeight(4), height: BlockHeight(5) })
+        );
+        sm.apply_transaction(&tx, BlockHeight(4)).unwrap();
+        assert_eq!(sm.world_state[&sender_address].balance, 90);
+    }
+
+    #[test]
+    fn test_vesting_limits_spending() {
+        let mut sm = StateMachine::new();
+        let sender_wallet = Wallet::new();
+        let sender_address = address_from_public_key(sender_wallet.public_key());
+        let vesting = VestingSchedule::Linear { amount: 1000, start_height: BlockHeight(10), end_height: BlockHeight(20) };
+        sm.set_account(sender_address, Account { balance: 1100, nonce: Nonce(0), authorized_key: None, vesting: Some(vesting), hash_lock: None }).unwrap();
+        let pay = |amount, nonce| sender_wallet.create_signed_transaction(Address([2u8; 32]), amount, Nonce(nonce)).unwrap();
+
+        // Only the unvested part can be spent before the schedule starts
+        assert_eq!(
+            sm.apply_transaction(&pay(101, 0), BlockHeight(5)),
+            Err(StateMachineError::BalanceLocked { unlocked: 100, required: 101 })
+        );
+        sm.apply_transaction(&pay(100, 0), BlockHeight(5)).unwrap();
+
+        // Halfway through, half of the schedule has unlocked
+        assert_eq!(sm.world_state[&sender_address].spendable_at(BlockHeight(15)), 500);
+        assert!(matches!(sm.apply_transaction(&pay(501, 1), BlockHeight(15)), Err(StateMachineError::BalanceLocked { .. })));
+        sm.apply_transaction(&pay(500, 1), BlockHeight(15)).unwrap();
+
+        sm.apply_transaction(&pay(500, 2), BlockHeight(20)).unwrap();
+        assert_eq!(sm.world_state[&sender_address].balance, 0);
+    }
+
+    #[test]
+    fn test_hash_time_lock_claim_and_refund() {
+        let mut sm = StateMachine::new();
+        let alice_wallet = Wallet::new();
+        let bob_wallet = Wallet::new();
+        let alice = address_from_public_key(alice_wallet.public_key());
+        let bob = address_from_public_key(bob_wallet.public_key());
+        sm.set_account(alice, Account { balance: 100, nonce: Nonce(0), ..Default::default() }).unwrap();
+        sm.set_account(bob, Account { balance: 5, nonce: Nonce(0), ..Default::default() }).unwrap();
+
+        let secret = [7u8; 32];
+        let lock = |timelock| HashTimeLock { hashlock: hashlock_of(&secret), timelock: BlockHeight(timelock), recipient: bob, refund_address: alice };
+        let lock_funds = |amount, timelock, nonce| {
+            alice_wallet.create_signed_transaction_with_kind(TransactionKind::LockFunds { amount, lock: lock(timelock) }, Nonce(nonce)).unwrap()
+        };
+
+        // Locked funds sit in the escrow account and still count towards the supply
+        sm.apply_transaction(&lock_funds(60, 10, 0), BlockHeight(1)).unwrap();
+        let first = HashTimeLock::lock_address(&alice, Nonce(0));
+        assert_eq!(sm.world_state[&first].balance, 60);
+        assert_eq!(sm.world_state[&alice].balance, 40);
+        sm.check_supply_invariant().unwrap();
+
+        // A claim needs the preimage and must land before the timelock
+        let claim = |preimage, nonce| {
+            bob_wallet.create_signed_transaction_with_kind(TransactionKind::ClaimLock { lock_id: first, preimage }, Nonce(nonce)).unwrap()
+        };
+        assert_eq!(sm.apply_transaction(&claim([8u8; 32], 0), BlockHeight(2)), Err(StateMachineError::WrongPreimage(first)));
+        assert_eq!(
+            sm.apply_transaction(&claim(secret, 0), BlockHeight(10)),
+            Err(StateMachineError::LockExpired { lock_id: first, timelock: BlockHeight(10), height: BlockHeight(10) })
+        );
+        sm.apply_transaction(&claim(secret, 0), BlockHeight(9)).unwrap();
+        assert_eq!(sm.world_state[&bob].balance, 65);
+        assert_eq!(sm.world_state[&first].balance, 0);
+
+        // The claim closes the lock, so it pays out only once
+        assert_eq!(sm.apply_transaction(&claim(secret, 1), BlockHeight(9)), Err(StateMachineError::LockNotFound(first)));
+        let refund = |lock_id, nonce| {
+            alice_wallet.create_signed_transaction_with_kind(TransactionKind::RefundLock { lock_id }, Nonce(nonce)).unwrap()
+        };
+        assert_eq!(sm.apply_transaction(&refund(first, 1), BlockHeight(11)), Err(StateMachineError::LockNotFound(first)));
+
+        // An unclaimed lock goes back to the refund address once the timelock has passed
+        sm.apply_transaction(&lock_funds(30, 20, 1), BlockHeight(12)).unwrap();
+        let second = HashTimeLock::lock_address(&alice, Nonce(1));
+        assert_eq!(
+            sm.apply_transaction(&refund(second, 2), BlockHeight(19)),
+            Err(StateMachineError::LockNotExpired { lock_id: second, timelock: BlockHeight(20), height: BlockHeight(19) })
+        );
+        sm.apply_transaction(&refund(second, 2), BlockHeight(20)).unwrap();
+        assert_eq!(sm.world_state[&alice].balance, 40);
+        assert_eq!(sm.apply_transaction(&claim(secret, 1), BlockHeight(20)), Err(StateMachineError::LockNotFound(first)));
+        sm.check_supply_invariant().unwrap();
+    }
+
+    #[test]
+    fn test_lock_with_passed_timelock_is_rejected() {
+        let mut sm = StateMachine::new();
+        let alice_wallet = Wallet::new();
+        let alice = address_from_public_key(alice_wallet.public_key());
+        sm.set_account(alice, Account { balance: 100, nonce: Nonce(0), ..Default::default() }).unwrap();
+        let lock_funds = |timelock| {
+            let lock = HashTimeLock { hashlock: hashlock_of(&[7u8; 32]), timelock: BlockHeight(timelock), recipient: Address([2u8; 32]), refund_address: alice };
+            alice_wallet.create_signed_transaction_with_kind(TransactionKind::LockFunds { amount: 60, lock }, Nonce(0)).unwrap()
+        };
+
+        // A lock that expires at or before its own block could only ever be refunded
+        for timelock in [4, 5] {
+            assert_eq!(
+                sm.apply_transaction(&lock_funds(timelock), BlockHeight(5)),
+                Err(StateMachineError::TimelockPassed { timelock: BlockHeight(timelock), height: BlockHeight(5) })
+            );
+        }
+        assert_eq!(sm.world_state[&alice].balance, 100);
+        sm.apply_transaction(&lock_funds(6), BlockHeight(5)).unwrap();
+        assert_eq!(sm.world_state[&alice].balance, 40);
+    }
+
+    #[test]
+    fn test_relayed_transaction_advances_both_nonces() {
+        let mut sm = StateMachine::new();
+        let user_wallet = Wallet::new();
+        let relayer_wallet = Wallet::new();
+        let user = address_from_public_key(user_wallet.public_key());
+        let relayer = address_from_public_key(relayer_wallet.public_key());
+        let recipient = Address([2u8; 32]);
+        sm.set_account(user, Account { balance: 100, nonce: Nonce(4), ..Default::default() }).unwrap();
+        sm.set_account(relayer, Account { balance: 0, nonce: Nonce(9), ..Default::default() }).unwrap();
+        let relay = |inner: Transaction, nonce| {
+            relayer_wallet.create_signed_transaction_with_kind(TransactionKind::Relayed(Box::new(inner)), Nonce(nonce)).unwrap()
+        };
+
+        // The inner transfer is checked against the user's nonce, the envelope against the relayer's
+        let stale = user_wallet.create_signed_transaction(recipient, 30, Nonce(3)).unwrap();
+        assert_eq!(sm.apply_transaction(&relay(stale, 9), BlockHeight(1)), Err(StateMachineError::InvalidNonce { expected: Nonce(4), actual: Nonce(3) }));
+
+        // The relayer cannot alter what the user signed
+        let mut tampered = user_wallet.create_signed_transaction(recipient, 30, Nonce(4)).unwrap();
+        tampered.kind = TransactionKind::Transfer(Transfer { recipient: relayer, amount: 30 });
+        assert!(matches!(sm.apply_transaction(&relay(tampered, 9), BlockHeight(1)), Err(StateMachineError::TransactionValidation(_))));
+
+        let inner = user_wallet.create_signed_transaction(recipient, 30, Nonce(4)).unwrap();
+        sm.apply_transaction(&relay(inner.clone(), 9), BlockHeight(1)).unwrap();
+        assert_eq!(sm.world_state[&user], Account { balance: 70, nonce: Nonce(5), ..Default::default() });
+        assert_eq!(sm.world_state[&relayer], Account { balance: 0, nonce: Nonce(10), ..Default::default() });
+        assert_eq!(sm.world_state[&recipient].balance, 30);
+
+        // Replaying the same inner transfer under a fresh relayer nonce fails on the user's nonce
+        assert_eq!(sm.apply_transaction(&relay(inner, 10), BlockHeight(2)), Err(StateMachineError::InvalidNonce { expected: Nonce(5), actual: Nonce(4) }));
+    }
+
+    #[test]
+    fn test_key_rotation_moves_signing_rights() {
+        let mut sm = StateMachine::new();
+        let old_wallet = Wallet::new();
+        let new_wallet = Wallet::new();
+        let account = address_from_public_key(old_wallet.public_key());
+        let recipient = Address([2u8; 32]);
+        sm.set_account(account, Account { balance: 100, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None }).unwrap();
+
+        // Nobody but the derived key may sign for the account before a rotation
+        let pay = |wallet: &Wallet, nonce| {
+            let tx = Transaction::new(*wallet.public_key(), recipient, 10, Nonce(nonce), Signature(vec![])).with_account(account);
+            wallet.sign_transaction(tx).unwrap()
+        };
+        assert_eq!(sm.apply_transaction(&pay(&new_wallet, 0), BlockHeight(1)), Err(StateMachineError::UnauthorizedSigner(account)));
+
+        let rotate = old_wallet
+            .create_signed_transaction_with_kind(TransactionKind::RotateKey { new_key: *new_wallet.public_key() }, Nonce(0))
+            .unwrap();
+        sm.apply_transaction(&rotate, BlockHeight(1)).unwrap();
+        assert_eq!(sm.world_state[&account].authorized_key, Some(*new_wallet.public_key()));
+        assert_eq!(sm.world_state[&account].nonce, Nonce(1));
+
+        // Naming the authorized key is not enough: it must have signed
+        let mut forged = pay(&new_wallet, 1);
+        forged.signature = crate::transaction::TxSignature::Single(Signature(vec![0xab; 64]));
+        assert!(matches!(sm.apply_transaction(&forged, BlockHeight(2)), Err(StateMachineError::TransactionValidation(_))));
+        assert_eq!(sm.world_state[&account].balance, 100);
+
+        // The old key is locked out, the new key spends from the same address
+        assert_eq!(sm.apply_transaction(&pay(&old_wallet, 1), BlockHeight(2)), Err(StateMachineError::UnauthorizedSigner(account)));
+        sm.apply_transaction(&pay(&new_wallet, 1), BlockHeight(2)).unwrap();
+        assert_eq!(sm.world_state[&account].balance, 90);
+
+        // Rotating back to the derived key restores the default
+        let tx = Transaction::with_kind(*new_wallet.public_key(), Nonce(2), TransactionKind::RotateKey { new_key: *old_wallet.public_key() }, Signature(vec![]))
+            .with_account(account);
+        sm.apply_transaction(&new_wallet.sign_transaction(tx).unwrap(), BlockHeight(3)).unwrap();
+        assert_eq!(sm.world_state[&account].authorized_key, None);
+        sm.apply_transaction(&pay(&old_wallet, 3), BlockHeight(4)).unwrap();
+        assert_eq!(sm.total_supply(), 100);
+    }
+
+    #[test]
+    fn test_multisig_needs_threshold_to_apply() {
+        use crate::multisig::MultisigPolicy;
+
+        let mut sm = StateMachine::new();
+        let cosigners = [Wallet::new(), Wallet::new(), Wallet::new()];
+        let policy = MultisigPolicy::new(2, cosigners.iter().map(|wallet| *wallet.public_key()).collect()).unwrap();
+        let account = policy.address();
+        sm.set_account(account, Account { balance: 100, nonce: Nonce(0), ..Default::default() }).unwrap();
+
+        // A block carrying the transfer with fewer cosignatures than the threshold is refused
+        let mut tx = Transaction::new_multisig(policy, Nonce(0), TransactionKind::Transfer(Transfer { recipient: Address([2u8; 32]), amount: 40 }));
+        assert!(matches!(sm.apply_transaction(&tx, BlockHeight(1)), Err(StateMachineError::TransactionValidation(_))));
+        cosigners[0].cosign(&mut tx).unwrap();
+        assert!(matches!(sm.apply_transaction(&tx, BlockHeight(1)), Err(StateMachineError::TransactionValidation(_))));
+        assert_eq!(sm.world_state[&account].balance, 100);
+
+        cosigners[2].cosign(&mut tx).unwrap();
+        sm.apply_transaction(&tx, BlockHeight(1)).unwrap();
+        assert_eq!(sm.world_state[&account], Account { balance: 60, nonce: Nonce(1), ..Default::default() });
+    }
+
     #[test]
     fn test_new_state_machine() {
         let mut sm = StateMachine::new();
@@ -434,4 +955,77 @@ mod tests {
         // Expect error because sender account does not exist
         assert!(sm.apply_block(&block).is_err());
     }
+
+    mod supply {
+        use super::*;
+        use proptest::prelude::*;
+
+        const ACCOUNTS: usize = 4;
+
+        /// Deterministic keys, so failing cases shrink and replay reliably.
+        fn sender_wallet(index: usize) -> Wallet {
+            Wallet::from_signing_key(SigningKey::from_bytes(&[index as u8 + 1; 32]))
+        }
+
+        /// Recipients are the funded accounts plus two addresses that start out empty.
+        fn recipient(index: usize) -> Address {
+            if index < ACCOUNTS {
+                address_from_public_key(sender_wallet(index).public_key())
+            } else {
+                Address([index as u8; 32])
+            }
+        }
+
+        fn arb_amount() -> impl Strategy<Value = u64> {
+            prop_oneof![0..1_000u64, any::<u64>(), Just(u64::MAX)]
+        }
+
+        fn arb_transfer() -> impl Strategy<Value = Transfer> {
+            (0..ACCOUNTS + 2, arb_amount()).prop_map(|(to, amount)| Transfer { recipient: recipient(to), amount })
+        }
+
+        /// Transactions from a funded sender, with a nonce that is usually but not always right.
+        fn arb_transaction() -> impl Strategy<Value = (usize, TransactionKind, bool)> {
+            let kind = prop_oneof![
+                arb_transfer().prop_map(TransactionKind::Transfer),
+                prop::collection::vec(arb_transfer(), 0..6).prop_map(TransactionKind::BatchTransfer),
+            ];
+            (0..ACCOUNTS, kind, prop::bool::weighted(0.9))
+        }
+
+        proptest! {
+            #[test]
+            fn supply_is_conserved(
+                balances in prop::collection::vec(prop_oneof![8 => 0..10_000u64, 1 => (u64::MAX - 50_000)..=u64::MAX, 1 => any::<u64>()], ACCOUNTS),
+                transactions in prop::collection::vec(arb_transaction(), 0..40),
+            ) {
+                let mut sm = StateMachine::new();
+                for (index, balance) in balances.iter().enumerate() {
+                    let account = Account { balance: *balance, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None };
+                    if sm.set_account(recipient(index), account).is_err() {
+                        // Genesis balances that overflow the supply are refused outright
+                        prop_assert!(balances.iter().try_fold(0u64, |total, b| total.checked_add(*b)).is_none());
+                        return Ok(());
+                    }
+                }
+                let supply = sm.total_supply();
+                prop_assert_eq!(supply, total_supply_of(&sm.world_state).unwrap());
+
+                for (sender, kind, correct_nonce) in transactions {
+                    let sender_address = recipient(sender);
+                    let nonce = sm.world_state[&sender_address].nonce;
+                    let nonce = if correct_nonce { nonce } else { Nonce(nonce.0 + 1) };
+                    let tx = sender_wallet(sender).create_signed_transaction_with_kind(kind, nonce).unwrap();
+
+                    let before = sm.world_state.clone();
+                    match sm.apply_transaction(&tx, BlockHeight(1)) {
+                        Ok(()) => prop_assert_eq!(sm.world_state[&sender_address].nonce.0, before[&sender_address].nonce.0 + 1),
+                        Err(_) => prop_assert_eq!(&sm.world_state, &before),
+                    }
+                    prop_assert_eq!(sm.total_supply(), supply);
+                    prop_assert!(sm.check_supply_invariant().is_ok());
+                }
+            }
+        }
+    }
 }
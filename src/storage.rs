@@ -1,17 +1,53 @@
 use crate::block::{Block, BlockHeader};
+use crate::chain_store::ChainStore;
+use crate::htlc::HashTimeLock;
+use crate::snapshot::{SnapshotError, StateSnapshot};
 use crate::state_machine::{Account, WorldState};
+use crate::transaction::{Transaction, TransactionKind};
 use crate::types::{Address, Hash, BlockHeight};
-use rocksdb::{DB, Options, WriteBatch};
+use rocksdb::{DB, Direction, IteratorMode, Options, WriteBatch};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 use thiserror::Error;
 
-const BLOCKS_CF: &str = "blocks";
-const HEADERS_CF: &str = "headers";
-const STATE_CF: &str = "state";
-const META_CF: &str = "meta";
+pub(crate) const BLOCKS_CF: &str = "blocks";
+pub(crate) const HEADERS_CF: &str = "headers";
+pub(crate) const STATE_CF: &str = "state";
+pub(crate) const META_CF: &str = "meta";
+pub(crate) const STATE_HISTORY_CF: &str = "state_history";
+pub(crate) const MEMO_INDEX_CF: &str = "memo_index";
+pub(crate) const ADDRESS_INDEX_CF: &str = "address_index";
+pub(crate) const HISTORY_INDEX_CF: &str = "history_index";
 
 const TIP_KEY: &[u8] = b"tip";
 const HEIGHT_KEY: &[u8] = b"height";
+const PRUNED_HEIGHT_KEY: &[u8] = b"pruned_height";
+pub(crate) const HISTORY_HEIGHT_KEY: &[u8] = b"history_height";
+pub(crate) const STATE_HEIGHT_KEY: &[u8] = b"state_height";
+pub(crate) const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+/// Version of the on-disk layout written by this build. Bump it, and add a step to
+/// `migrations::MIGRATIONS`, whenever a column family is added or the bincode encoding
+/// of a stored type changes.
+///
+/// 1. `blocks`, `headers`, `state` and `meta` (databases without a version key)
+/// 2. `state_history` and `meta/history_height`
+/// 3. `meta/state_height`, written atomically with every state change
+/// 4. Blocks hold versioned transaction envelopes
+/// 5. `memo_index`
+/// 6. Accounts hold an optional authorized key
+/// 7. Accounts hold an optional vesting schedule
+/// 8. Accounts hold an optional hash-time lock
+/// 9. `address_index`
+/// 10. `history_index`
+pub const SCHEMA_VERSION: u32 = 10;
+
+/// Schema version assumed for databases that hold a chain but no version key.
+pub(crate) const LEGACY_SCHEMA_VERSION: u32 = 1;
 
 #[derive(Debug, Error)]
 pub enum StorageError {
@@ -23,35 +59,386 @@ pub enum StorageError {
     DeserializationError(String),
     #[error("Item not found: {0}")]
     NotFound(String),
+    #[error("Snapshot error: {0}")]
+    Snapshot(#[from] SnapshotError),
+    #[error("No snapshot available at height {requested}: chain tip is at height {tip}")]
+    SnapshotUnavailable { requested: u64, tip: u64 },
+    #[error("Data at height {height} has been pruned: this node only retains blocks from height {horizon}")]
+    Pruned { height: u64, horizon: u64 },
+    #[error("Database schema version {found} is newer than version {supported} supported by this node")]
+    UnsupportedSchemaVersion { found: u32, supported: u32 },
+    #[error("Database schema version {found} is older than version {expected}; run `node db migrate` to upgrade it")]
+    SchemaOutdated { found: u32, expected: u32 },
+    #[error("Database is inconsistent and cannot be repaired: {0}")]
+    Corrupted(String),
+}
+
+/// A fix applied by `ChainStore::check_integrity` when the database was left inconsistent,
+/// e.g. by a crash between writes made by older versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityRepair {
+    /// The tip pointed at a block whose state was never written; it now points at the
+    /// block the stored state belongs to.
+    TipRolledBack { from: u64, to: u64 },
+    /// No state was ever written for the tip (e.g. an interrupted genesis), so the tip was
+    /// cleared and the chain will be initialized again.
+    TipCleared { height: u64 },
+    /// The height index entry for the tip was missing and was rebuilt from the block.
+    HeaderIndexRestored { height: u64 },
+}
+
+impl fmt::Display for IntegrityRepair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityRepair::TipRolledBack { from, to } => {
+                write!(f, "rolled chain tip back from height {} to {}, the last height with committed state", from, to)
+            }
+            IntegrityRepair::TipCleared { height } => {
+                write!(f, "cleared chain tip at height {} because no state was ever committed", height)
+            }
+            IntegrityRepair::HeaderIndexRestored { height } => {
+                write!(f, "restored missing header index entry at height {}", height)
+            }
+        }
+    }
+}
+
+/// How much historical chain data a node keeps. Headers and the height index are
+/// always retained; pruning only removes block bodies below the pruning horizon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruningMode {
+    /// Keep every block body forever.
+    #[default]
+    Archive,
+    /// Keep the bodies of the most recent N blocks.
+    KeepLast(u64),
+    /// Keep only the latest state and the tip block body.
+    StateOnly,
+}
+
+impl PruningMode {
+    /// Returns the lowest block height whose body must be retained for the given tip,
+    /// or `None` if nothing may be pruned.
+    pub fn horizon(&self, tip_height: u64) -> Option<u64> {
+        match self {
+            PruningMode::Archive => None,
+            PruningMode::KeepLast(blocks) => Some((tip_height + 1).saturating_sub((*blocks).max(1))),
+            PruningMode::StateOnly => Some(tip_height),
+        }
+    }
+}
+
+impl fmt::Display for PruningMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PruningMode::Archive => write!(f, "archive"),
+            PruningMode::KeepLast(blocks) => write!(f, "keep-last:{}", blocks),
+            PruningMode::StateOnly => write!(f, "state-only"),
+        }
+    }
+}
+
+impl FromStr for PruningMode {
+    type Err = String;
+
+    /// Parses `archive`, `state-only` or `keep-last:<N>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "archive" => Ok(PruningMode::Archive),
+            "state-only" | "state_only" => Ok(PruningMode::StateOnly),
+            _ => {
+                let blocks = s
+                    .strip_prefix("keep-last:")
+                    .or_else(|| s.strip_prefix("keep_last:"))
+                    .ok_or_else(|| format!("Unknown pruning mode '{}', expected archive, state-only or keep-last:<N>", s))?;
+                let blocks: u64 = blocks.parse().map_err(|e| format!("Invalid keep-last block count '{}': {}", blocks, e))?;
+                if blocks == 0 {
+                    return Err("keep-last block count must be greater than zero".to_string());
+                }
+                Ok(PruningMode::KeepLast(blocks))
+            }
+        }
+    }
+}
+
+/// Where an indexed transaction was included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TransactionLocation {
+    pub height: u64,
+    pub tx_id: Hash,
+}
+
+/// Memos are indexed by their hash, so index keys have a fixed length and a lookup is a
+/// scan over an exact 32-byte prefix.
+pub(crate) fn memo_hash(memo: &[u8]) -> Hash {
+    Hash(Sha256::digest(memo).into())
+}
+
+/// Returns the memo hash and location of every transaction in `block` that carries a memo,
+/// including transactions wrapped by a relay.
+pub(crate) fn memo_entries(block: &Block) -> Result<Vec<(Hash, TransactionLocation)>, StorageError> {
+    let height = block.header.block_number.0;
+    block
+        .transactions
+        .iter()
+        // A relayed transaction's memo is found under the relay that carried it
+        .flat_map(|tx| std::iter::once(tx).chain(tx.relayed()).map(move |signed| (tx, &signed.memo)))
+        .filter(|(_, memo)| !memo.is_empty())
+        .map(|(tx, memo)| {
+            let tx_id = tx.id().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            Ok((memo_hash(memo), TransactionLocation { height, tx_id }))
+        })
+        .collect()
+}
+
+/// Returns the escrow address and terms of every lock opened by a transaction in `tx`,
+/// including one wrapped by a relay.
+fn opened_locks(tx: &Transaction) -> impl Iterator<Item = (Address, HashTimeLock)> + '_ {
+    std::iter::once(tx).chain(tx.relayed()).filter_map(|signed| match &signed.kind {
+        TransactionKind::LockFunds { lock, .. } => Some((HashTimeLock::lock_address(&signed.sender_address(), signed.nonce), *lock)),
+        _ => None,
+    })
+}
+
+/// Returns the escrow address and terms of every lock opened in `block`.
+pub(crate) fn lock_entries(block: &Block) -> impl Iterator<Item = (Address, HashTimeLock)> + '_ {
+    block.transactions.iter().flat_map(opened_locks)
+}
+
+/// Returns every address touched by each transaction in `block`, with its location.
+///
+/// Claims and refunds only name the escrow account, so the lock's recipient and refund
+/// address are resolved from locks opened earlier in the block or, failing that, with
+/// `stored_lock`, which must see the escrow accounts as they were before the block.
+pub(crate) fn address_entries<F>(block: &Block, stored_lock: F) -> Result<Vec<(Address, TransactionLocation)>, StorageError>
+where
+    F: Fn(&Address) -> Result<Option<HashTimeLock>, StorageError>,
+{
+    let height = block.header.block_number.0;
+    let mut opened = BTreeMap::new();
+    let mut entries = Vec::new();
+    for tx in &block.transactions {
+        let tx_id = tx.id().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let mut addresses = tx.involved_addresses();
+        opened.extend(opened_locks(tx));
+        for signed in std::iter::once(tx).chain(tx.relayed()) {
+            match &signed.kind {
+                TransactionKind::ClaimLock { lock_id, .. } | TransactionKind::RefundLock { lock_id } => {
+                    let lock = match opened.get(lock_id) {
+                        Some(lock) => Some(*lock),
+                        None => stored_lock(lock_id)?,
+                    };
+                    addresses.extend(lock.iter().flat_map(|lock| [lock.recipient, lock.refund_address]));
+                }
+                _ => {}
+            }
+        }
+        addresses.sort_unstable();
+        addresses.dedup();
+        entries.extend(addresses.into_iter().map(|address| (address, TransactionLocation { height, tx_id })));
+    }
+    Ok(entries)
+}
+
+/// Index keys are a 32-byte prefix (the memo hash or the address), the big-endian height
+/// and the transaction id, so all entries for a prefix are contiguous and ordered by height.
+pub(crate) fn location_index_key(prefix: &[u8; 32], location: &TransactionLocation) -> [u8; 72] {
+    let mut key = [0u8; 72];
+    key[..32].copy_from_slice(prefix);
+    key[32..40].copy_from_slice(&location.height.to_be_bytes());
+    key[40..].copy_from_slice(&location.tx_id.0);
+    key
+}
+
+fn split_location_index_key(key: &[u8]) -> Result<TransactionLocation, StorageError> {
+    if key.len() != 72 {
+        return Err(StorageError::DeserializationError(format!("Invalid index key length: {}", key.len())));
+    }
+    let mut height = [0u8; 8];
+    height.copy_from_slice(&key[32..40]);
+    let mut tx_id = [0u8; 32];
+    tx_id.copy_from_slice(&key[40..]);
+    Ok(TransactionLocation { height: u64::from_be_bytes(height), tx_id: Hash(tx_id) })
 }
 
 pub struct Storage {
     db: DB,
 }
 
+/// State history keys are the address followed by the big-endian height, so all records
+/// for an address are contiguous and ordered by height.
+pub(crate) fn history_key(address: &Address, height: u64) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..32].copy_from_slice(&address.0);
+    key[32..].copy_from_slice(&height.to_be_bytes());
+    key
+}
+
+pub(crate) fn split_history_key(key: &[u8]) -> Result<(Address, u64), StorageError> {
+    if key.len() != 40 {
+        return Err(StorageError::DeserializationError(format!("Invalid state history key length: {}", key.len())));
+    }
+    let mut address = [0u8; 32];
+    address.copy_from_slice(&key[..32]);
+    let mut height = [0u8; 8];
+    height.copy_from_slice(&key[32..]);
+    Ok((Address(address), u64::from_be_bytes(height)))
+}
+
+/// History index keys are the big-endian height followed by the address, so the records
+/// written at or below a pruning horizon are found without scanning the whole history.
+pub(crate) fn history_index_key(height: u64, address: &Address) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&height.to_be_bytes());
+    key[8..].copy_from_slice(&address.0);
+    key
+}
+
+fn split_history_index_key(key: &[u8]) -> Result<(u64, Address), StorageError> {
+    if key.len() != 40 {
+        return Err(StorageError::DeserializationError(format!("Invalid history index key length: {}", key.len())));
+    }
+    let mut height = [0u8; 8];
+    height.copy_from_slice(&key[..8]);
+    let mut address = [0u8; 32];
+    address.copy_from_slice(&key[8..]);
+    Ok((u64::from_be_bytes(height), Address(address)))
+}
+
 impl Storage {
+    /// Opens the database, stamping fresh databases with the current schema version and
+    /// refusing databases written with any other version.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
+        let storage = Self::open_unchecked(path)?;
+        match storage.get_schema_version()? {
+            None => storage.put_schema_version(SCHEMA_VERSION)?,
+            Some(SCHEMA_VERSION) => {}
+            Some(found) if found > SCHEMA_VERSION => {
+                return Err(StorageError::UnsupportedSchemaVersion { found, supported: SCHEMA_VERSION });
+            }
+            Some(found) => return Err(StorageError::SchemaOutdated { found, expected: SCHEMA_VERSION }),
+        }
+        Ok(storage)
+    }
+
+    /// Opens the database without checking the schema version. Only migrations should use this.
+    pub(crate) fn open_unchecked<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
         let mut opts = Options::default();
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
         
-        let cfs = [BLOCKS_CF, HEADERS_CF, STATE_CF, META_CF];
+        let cfs = [BLOCKS_CF, HEADERS_CF, STATE_CF, META_CF, STATE_HISTORY_CF, MEMO_INDEX_CF, ADDRESS_INDEX_CF, HISTORY_INDEX_CF];
         let db = DB::open_cf(&opts, path, cfs)?;
         
         Ok(Storage { db })
     }
 
-    fn get_cf(&self, cf_name: &str) -> Result<&rocksdb::ColumnFamily, StorageError> {
+    pub(crate) fn db(&self) -> &DB {
+        &self.db
+    }
+
+    /// Returns the locations stored under `prefix` in the index column family `cf_name`.
+    fn scan_location_index(&self, cf_name: &str, prefix: &[u8; 32]) -> Result<Vec<TransactionLocation>, StorageError> {
+        let cf = self.get_cf(cf_name)?;
+        let mut locations = Vec::new();
+        for item in self.db.iterator_cf(cf, IteratorMode::From(prefix, Direction::Forward)) {
+            let (key, _) = item?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            locations.push(split_location_index_key(&key)?);
+        }
+        Ok(locations)
+    }
+
+    /// Returns the schema version of the database, or `None` for a fresh, empty database.
+    /// Databases that predate versioning but already hold a chain report version 1.
+    pub fn get_schema_version(&self) -> Result<Option<u32>, StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        match self.db.get_cf(cf, SCHEMA_VERSION_KEY)? {
+            Some(bytes) => {
+                let (version, _) = bincode::decode_from_slice::<u32, _>(&bytes, bincode::config::standard())
+                    .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+                Ok(Some(version))
+            }
+            None if self.get_tip()?.is_some() => Ok(Some(LEGACY_SCHEMA_VERSION)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_schema_version(&self, version: u32) -> Result<(), StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        let bytes = bincode::encode_to_vec(version, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        self.db.put_cf(cf, SCHEMA_VERSION_KEY, bytes)?;
+        Ok(())
+    }
+
+    pub(crate) fn get_cf(&self, cf_name: &str) -> Result<&rocksdb::ColumnFamily, StorageError> {
         self.db.cf_handle(cf_name).ok_or_else(|| StorageError::NotFound(format!("Column family '{}' not found", cf_name)))
     }
 
-    pub fn get_block(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
+    pub fn get_tip(&self) -> Result<Option<Hash>, StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        let result = self.db.get_cf(cf, TIP_KEY)?;
+        result.map(|bytes| bincode::decode_from_slice(&bytes, bincode::config::standard()).map(|(hash, _)| hash).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()
+    }
+
+    pub fn put_tip(&self, hash: &Hash) -> Result<(), StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        let bytes = bincode::encode_to_vec(hash, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        self.db.put_cf(cf, TIP_KEY, bytes)?;
+        Ok(())
+    }
+
+    /// Returns the lowest height covered by the state history (0 unless the database was
+    /// migrated from a layout without history).
+    pub fn get_history_height(&self) -> Result<u64, StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        let result = self.db.get_cf(cf, HISTORY_HEIGHT_KEY)?;
+        let height = result.map(|bytes| bincode::decode_from_slice::<u64, _>(&bytes, bincode::config::standard()).map(|(height, _)| height).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()?;
+        Ok(height.unwrap_or(0))
+    }
+
+    /// Returns the height of the block the `state` column family belongs to, or `None` if no
+    /// state has ever been committed.
+    pub fn get_state_height(&self) -> Result<Option<u64>, StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        let result = self.db.get_cf(cf, STATE_HEIGHT_KEY)?;
+        result.map(|bytes| bincode::decode_from_slice::<u64, _>(&bytes, bincode::config::standard()).map(|(height, _)| height).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()
+    }
+
+    /// Queues deletion of every history record that is superseded by a newer record at or
+    /// below `horizon`. Only the history index entries written since the last prune are
+    /// visited: for each address among them, everything below its newest record is dropped.
+    fn prune_state_history(&self, batch: &mut WriteBatch, horizon: u64) -> Result<(), StorageError> {
+        let history_cf = self.get_cf(STATE_HISTORY_CF)?;
+        let index_cf = self.get_cf(HISTORY_INDEX_CF)?;
+        let mut newest = BTreeMap::new();
+        for item in self.db.iterator_cf(index_cf, IteratorMode::Start) {
+            let (key, _) = item?;
+            let (height, address) = split_history_index_key(&key)?;
+            if height > horizon {
+                break;
+            }
+            newest.insert(address, height);
+            batch.delete_cf(index_cf, key);
+        }
+        for (address, height) in newest {
+            batch.delete_range_cf(history_cf, history_key(&address, 0), history_key(&address, height));
+        }
+        Ok(())
+    }
+}
+
+impl ChainStore for Storage {
+    fn get_block(&self, hash: &Hash) -> Result<Option<Block>, StorageError> {
         let cf = self.get_cf(BLOCKS_CF)?;
         let result = self.db.get_cf(cf, hash.0)?;
         result.map(|bytes| bincode::decode_from_slice(&bytes, bincode::config::standard()).map(|(block, _)| block).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()
     }
 
-    pub fn put_block(&self, block: &Block) -> Result<(), StorageError> {
+    fn put_block(&self, block: &Block) -> Result<(), StorageError> {
         let cf = self.get_cf(BLOCKS_CF)?;
         let hash = block.header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
         let bytes = bincode::encode_to_vec(block, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
@@ -59,33 +446,67 @@ impl Storage {
         Ok(())
     }
 
-    pub fn get_account(&self, address: &Address) -> Result<Option<Account>, StorageError> {
+    fn get_account(&self, address: &Address) -> Result<Option<Account>, StorageError> {
         let cf = self.get_cf(STATE_CF)?;
         let result = self.db.get_cf(cf, address.0)?;
         result.map(|bytes| bincode::decode_from_slice(&bytes, bincode::config::standard()).map(|(account, _)| account).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()
     }
 
-    pub fn put_account(&self, address: &Address, account: &Account) -> Result<(), StorageError> {
+    fn put_account(&self, address: &Address, account: &Account) -> Result<(), StorageError> {
         let cf = self.get_cf(STATE_CF)?;
         let bytes = bincode::encode_to_vec(account, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
         self.db.put_cf(cf, address.0, bytes)?;
         Ok(())
     }
 
-    pub fn get_tip(&self) -> Result<Option<Hash>, StorageError> {
-        let cf = self.get_cf(META_CF)?;
-        let result = self.db.get_cf(cf, TIP_KEY)?;
-        result.map(|bytes| bincode::decode_from_slice(&bytes, bincode::config::standard()).map(|(hash, _)| hash).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()
+    /// Records the value an account held as of `height` in the state history.
+    fn put_account_at(&self, address: &Address, height: u64, account: &Account) -> Result<(), StorageError> {
+        let mut batch = WriteBatch::default();
+        let bytes = bincode::encode_to_vec(account, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(self.get_cf(STATE_HISTORY_CF)?, history_key(address, height), bytes);
+        batch.put_cf(self.get_cf(HISTORY_INDEX_CF)?, history_index_key(height, address), []);
+        self.db.write(batch)?;
+        Ok(())
     }
 
-    pub fn put_tip(&self, hash: &Hash) -> Result<(), StorageError> {
-        let cf = self.get_cf(META_CF)?;
-        let bytes = bincode::encode_to_vec(hash, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
-        self.db.put_cf(cf, TIP_KEY, bytes)?;
-        Ok(())
+    /// Returns the account as it was after the block at `height` was applied, or `None` if the
+    /// address had no account at that height. Heights below the pruning horizon, or below the
+    /// first recorded history, return `StorageError::Pruned`.
+    fn get_account_at(&self, address: &Address, height: u64) -> Result<Option<Account>, StorageError> {
+        let horizon = self.get_pruned_height()?.max(self.get_history_height()?);
+        if height < horizon {
+            return Err(StorageError::Pruned { height, horizon });
+        }
+
+        let cf = self.get_cf(STATE_HISTORY_CF)?;
+        let key = history_key(address, height);
+        let mut iter = self.db.iterator_cf(cf, IteratorMode::From(&key, Direction::Reverse));
+        match iter.next() {
+            Some(item) => {
+                let (key, value) = item?;
+                if !key.starts_with(&address.0) {
+                    return Ok(None);
+                }
+                let (account, _) = bincode::decode_from_slice(&value, bincode::config::standard())
+                    .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+                Ok(Some(account))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Scans the memo index for entries under the hash of `memo`. Entries are kept when block
+    /// bodies are pruned, so the returned heights may no longer have a body.
+    fn get_transactions_by_memo(&self, memo: &[u8]) -> Result<Vec<TransactionLocation>, StorageError> {
+        self.scan_location_index(MEMO_INDEX_CF, &memo_hash(memo).0)
     }
 
-    pub fn get_chain_tip(&self) -> Result<Option<(Hash, u64)>, StorageError> {
+    /// Scans the address index. Like memo entries, these outlive pruned block bodies.
+    fn get_transactions_by_address(&self, address: &Address) -> Result<Vec<TransactionLocation>, StorageError> {
+        self.scan_location_index(ADDRESS_INDEX_CF, &address.0)
+    }
+
+    fn get_chain_tip(&self) -> Result<Option<(Hash, u64)>, StorageError> {
         let cf = self.get_cf(META_CF)?;
         
         // Get the tip hash
@@ -109,7 +530,7 @@ impl Storage {
         Ok(Some((tip_hash, height)))
     }
 
-    pub fn set_chain_tip(&self, hash: &Hash, height: u64) -> Result<(), StorageError> {
+    fn set_chain_tip(&self, hash: &Hash, height: u64) -> Result<(), StorageError> {
         let cf = self.get_cf(META_CF)?;
         
         let hash_bytes = bincode::encode_to_vec(hash, bincode::config::standard())
@@ -123,7 +544,7 @@ impl Storage {
         Ok(())
     }
 
-    pub fn put_header_by_height(&self, height: u64, header: &BlockHeader) -> Result<(), StorageError> {
+    fn put_header_by_height(&self, height: u64, header: &BlockHeader) -> Result<(), StorageError> {
         let cf = self.get_cf(HEADERS_CF)?;
         let key = height.to_be_bytes(); // Use big-endian encoding for consistent sorting
         let bytes = bincode::encode_to_vec(header, bincode::config::standard())
@@ -131,19 +552,147 @@ impl Storage {
         self.db.put_cf(cf, key, bytes)?;
         Ok(())
     }
-    
-    pub fn commit_block(&self, block: &Block, world_state: &WorldState) -> Result<(), StorageError> {
+
+    fn get_header_by_height(&self, height: u64) -> Result<Option<BlockHeader>, StorageError> {
+        let cf = self.get_cf(HEADERS_CF)?;
+        let result = self.db.get_cf(cf, height.to_be_bytes())?;
+        result.map(|bytes| bincode::decode_from_slice(&bytes, bincode::config::standard()).map(|(header, _)| header).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()
+    }
+
+    /// Returns the lowest height whose block body is still retained (0 if nothing has been pruned).
+    fn get_pruned_height(&self) -> Result<u64, StorageError> {
+        let cf = self.get_cf(META_CF)?;
+        let result = self.db.get_cf(cf, PRUNED_HEIGHT_KEY)?;
+        let height = result.map(|bytes| bincode::decode_from_slice::<u64, _>(&bytes, bincode::config::standard()).map(|(height, _)| height).map_err(|e| StorageError::DeserializationError(e.to_string()))).transpose()?;
+        Ok(height.unwrap_or(0))
+    }
+
+    /// Deletes block bodies below the pruning horizon of `mode`, keeping headers and the
+    /// height index. State history below the horizon is collapsed to the single record each
+    /// account needs to answer queries at the horizon. Returns the number of block bodies removed.
+    fn prune(&self, mode: PruningMode) -> Result<u64, StorageError> {
+        let Some((_, tip_height)) = self.get_chain_tip()? else {
+            return Ok(0);
+        };
+        let Some(horizon) = mode.horizon(tip_height) else {
+            return Ok(0);
+        };
+        let pruned_height = self.get_pruned_height()?;
+        if horizon <= pruned_height {
+            return Ok(0);
+        }
+
+        let mut batch = WriteBatch::default();
+        let blocks_cf = self.get_cf(BLOCKS_CF)?;
+        let meta_cf = self.get_cf(META_CF)?;
+
+        let mut removed = 0;
+        for height in pruned_height..horizon {
+            if let Some(header) = self.get_header_by_height(height)? {
+                let hash = header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+                batch.delete_cf(blocks_cf, hash.0);
+                removed += 1;
+            }
+        }
+        self.prune_state_history(&mut batch, horizon)?;
+        let horizon_bytes = bincode::encode_to_vec(horizon, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(meta_cf, PRUNED_HEIGHT_KEY, horizon_bytes);
+
+        self.db.write(batch)?;
+        Ok(removed)
+    }
+
+    /// Reads every account in the `state` column family, ordered by address.
+    fn load_accounts(&self) -> Result<Vec<(Address, Account)>, StorageError> {
+        let cf = self.get_cf(STATE_CF)?;
+        let mut accounts = Vec::new();
+        for item in self.db.iterator_cf(cf, IteratorMode::Start) {
+            let (key, value) = item?;
+            let address_bytes: [u8; 32] = key.as_ref().try_into()
+                .map_err(|_| StorageError::DeserializationError(format!("Invalid state key length: {}", key.len())))?;
+            let (account, _) = bincode::decode_from_slice(&value, bincode::config::standard())
+                .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+            accounts.push((Address(address_bytes), account));
+        }
+        Ok(accounts)
+    }
+
+    /// Verifies a snapshot and atomically replaces the local state with it. The snapshot's
+    /// header becomes the chain tip, so syncing can continue from the following height.
+    fn import_snapshot(&self, snapshot: &StateSnapshot) -> Result<(), StorageError> {
+        snapshot.verify()?;
+
+        let mut batch = WriteBatch::default();
+        let headers_cf = self.get_cf(HEADERS_CF)?;
+        let state_cf = self.get_cf(STATE_CF)?;
+        let history_cf = self.get_cf(STATE_HISTORY_CF)?;
+        let history_index_cf = self.get_cf(HISTORY_INDEX_CF)?;
+        let meta_cf = self.get_cf(META_CF)?;
+        let manifest = &snapshot.manifest;
+
+        // History before the snapshot is unknown, so queries below its height are treated as pruned
+        for cf in [state_cf, history_cf, history_index_cf] {
+            for item in self.db.iterator_cf(cf, IteratorMode::Start) {
+                let (key, _) = item?;
+                batch.delete_cf(cf, key);
+            }
+        }
+        for chunk in &snapshot.chunks {
+            for (address, account) in &chunk.accounts {
+                let account_bytes = bincode::encode_to_vec(account, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+                batch.put_cf(history_cf, history_key(address, manifest.height.0), &account_bytes);
+                batch.put_cf(history_index_cf, history_index_key(manifest.height.0, address), []);
+                batch.put_cf(state_cf, address.0, account_bytes);
+            }
+        }
+
+        let header_bytes = bincode::encode_to_vec(&manifest.header, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(headers_cf, manifest.height.0.to_be_bytes(), header_bytes);
+
+        let tip_bytes = bincode::encode_to_vec(manifest.block_hash, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(meta_cf, TIP_KEY, tip_bytes);
+        let height_bytes = bincode::encode_to_vec(manifest.height.0, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(meta_cf, HEIGHT_KEY, height_bytes);
+        let pruned_bytes = bincode::encode_to_vec(manifest.height.0, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(meta_cf, PRUNED_HEIGHT_KEY, &pruned_bytes);
+        batch.put_cf(meta_cf, STATE_HEIGHT_KEY, pruned_bytes);
+
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    fn commit_block(&self, block: &Block, changed_accounts: &WorldState) -> Result<(), StorageError> {
         let mut batch = WriteBatch::default();
         let block_cf = self.get_cf(BLOCKS_CF)?;
+        let headers_cf = self.get_cf(HEADERS_CF)?;
         let state_cf = self.get_cf(STATE_CF)?;
+        let history_cf = self.get_cf(STATE_HISTORY_CF)?;
+        let history_index_cf = self.get_cf(HISTORY_INDEX_CF)?;
+        let memo_cf = self.get_cf(MEMO_INDEX_CF)?;
+        let address_cf = self.get_cf(ADDRESS_INDEX_CF)?;
         let meta_cf = self.get_cf(META_CF)?;
 
         let hash = block.header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
         let block_bytes = bincode::encode_to_vec(block, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
         batch.put_cf(&block_cf, hash.0, block_bytes);
 
-        for (address, account) in world_state {
+        let header_bytes = bincode::encode_to_vec(&block.header, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        batch.put_cf(headers_cf, block.header.block_number.0.to_be_bytes(), header_bytes);
+
+        for (memo_hash, location) in memo_entries(block)? {
+            batch.put_cf(memo_cf, location_index_key(&memo_hash.0, &location), []);
+        }
+        // The batch is not written yet, so `get_account` still sees the escrow accounts' terms
+        let stored_lock = |address: &Address| Ok(self.get_account(address)?.and_then(|account| account.hash_lock));
+        for (address, location) in address_entries(block, stored_lock)? {
+            batch.put_cf(address_cf, location_index_key(&address.0, &location), []);
+        }
+
+        let height = block.header.block_number.0;
+        for (address, account) in changed_accounts {
             let account_bytes = bincode::encode_to_vec(account, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            batch.put_cf(history_cf, history_key(address, height), &account_bytes);
+            batch.put_cf(history_index_cf, history_index_key(height, address), []);
             batch.put_cf(&state_cf, address.0, account_bytes);
         }
 
@@ -152,12 +701,63 @@ impl Storage {
         batch.put_cf(&meta_cf, TIP_KEY, tip_bytes);
         
         let height_bytes = bincode::encode_to_vec(&block.header.block_number.0, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
-        batch.put_cf(&meta_cf, HEIGHT_KEY, height_bytes);
+        batch.put_cf(&meta_cf, HEIGHT_KEY, &height_bytes);
+        // Marks the state above as belonging to this height, for the startup integrity check
+        batch.put_cf(meta_cf, STATE_HEIGHT_KEY, height_bytes);
 
         self.db.write(batch)?;
 
         Ok(())
     }
+
+    /// Compares the tip with the height of the committed state and with the height index,
+    /// repairing what it can in a single batch.
+    fn check_integrity(&self) -> Result<Vec<IntegrityRepair>, StorageError> {
+        let Some((tip_hash, tip_height)) = self.get_chain_tip()? else {
+            return Ok(Vec::new());
+        };
+        let mut repairs = Vec::new();
+        let mut batch = WriteBatch::default();
+        let headers_cf = self.get_cf(HEADERS_CF)?;
+        let meta_cf = self.get_cf(META_CF)?;
+
+        let Some(state_height) = self.get_state_height()? else {
+            batch.delete_cf(meta_cf, TIP_KEY);
+            batch.delete_cf(meta_cf, HEIGHT_KEY);
+            self.db.write(batch)?;
+            return Ok(vec![IntegrityRepair::TipCleared { height: tip_height }]);
+        };
+
+        let (tip_hash, tip_height) = if state_height != tip_height {
+            let header = self.get_header_by_height(state_height)?.ok_or_else(|| {
+                StorageError::Corrupted(format!(
+                    "tip is at height {} but state belongs to height {}, whose header is missing",
+                    tip_height, state_height
+                ))
+            })?;
+            let hash = header.calculate_hash().map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            let tip_bytes = bincode::encode_to_vec(hash, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            batch.put_cf(meta_cf, TIP_KEY, tip_bytes);
+            let height_bytes = bincode::encode_to_vec(state_height, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            batch.put_cf(meta_cf, HEIGHT_KEY, height_bytes);
+            repairs.push(IntegrityRepair::TipRolledBack { from: tip_height, to: state_height });
+            (hash, state_height)
+        } else {
+            (tip_hash, tip_height)
+        };
+
+        if self.get_header_by_height(tip_height)?.is_none() {
+            let block = self.get_block(&tip_hash)?.ok_or_else(|| {
+                StorageError::Corrupted(format!("neither the header nor the body of the tip at height {} is stored", tip_height))
+            })?;
+            let header_bytes = bincode::encode_to_vec(&block.header, bincode::config::standard()).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            batch.put_cf(headers_cf, tip_height.to_be_bytes(), header_bytes);
+            repairs.push(IntegrityRepair::HeaderIndexRestored { height: tip_height });
+        }
+
+        self.db.write(batch)?;
+        Ok(repairs)
+    }
 }
 
 #[cfg(test)]
@@ -201,6 +801,9 @@ mod tests {
         let account = Account {
             balance: 100,
             nonce: Nonce(1),
+            authorized_key: None,
+            vesting: None,
+            hash_lock: None,
         };
 
         storage.put_account(&address, &account).unwrap();
@@ -227,6 +830,9 @@ mod tests {
         let account = Account {
             balance: 100,
             nonce: Nonce(1),
+            authorized_key: None,
+            vesting: None,
+            hash_lock: None,
         };
         let mut world_state = WorldState::new();
         world_state.insert(address, account);
@@ -253,5 +859,221 @@ mod tests {
 
         let retrieved_tip = storage.get_tip().unwrap().unwrap();
         assert_eq!(hash, retrieved_tip);
+
+        assert_eq!(storage.get_header_by_height(1).unwrap(), Some(block.header));
+        assert_eq!(storage.get_state_height().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_export_and_import_snapshot() {
+        let source_dir = temp_db_path();
+        let source = Storage::new(source_dir.path()).unwrap();
+        let mut world_state = WorldState::new();
+        for i in 1..=5u8 {
+            world_state.insert(Address([i; 32]), Account { balance: i as u64 * 10, nonce: Nonce(i as u64), authorized_key: None, vesting: None, hash_lock: None });
+        }
+        let block = Block {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(4),
+                timestamp: crate::types::Timestamp(123),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![],
+        };
+        source.commit_block(&block, &world_state).unwrap();
+
+        // Each account encodes to 37 bytes, so a 90-byte chunk holds two
+        assert!(matches!(
+            source.export_snapshot(3, 90),
+            Err(StorageError::SnapshotUnavailable { requested: 3, tip: 4 })
+        ));
+        let snapshot = source.export_snapshot(4, 90).unwrap();
+        assert_eq!(snapshot.manifest.chunk_count(), 3);
+        assert_eq!(snapshot.manifest.block_hash, block.header.calculate_hash().unwrap());
+
+        let target_dir = temp_db_path();
+        let target = Storage::new(target_dir.path()).unwrap();
+        let stale = Address([9; 32]);
+        target.put_account(&stale, &Account { balance: 1, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None }).unwrap();
+
+        target.import_snapshot(&snapshot).unwrap();
+        assert_eq!(target.load_world_state().unwrap(), world_state);
+        assert!(target.get_account(&stale).unwrap().is_none());
+        assert_eq!(target.get_chain_tip().unwrap(), Some((snapshot.manifest.block_hash, 4)));
+        assert_eq!(target.get_header_by_height(4).unwrap(), Some(block.header));
+    }
+
+    fn commit_chain(storage: &Storage, length: u64) -> Vec<Block> {
+        let mut parent_hash = Hash([0; 32]);
+        let mut blocks = Vec::new();
+        for height in 0..length {
+            let block = Block {
+                header: BlockHeader {
+                    parent_hash,
+                    block_number: BlockHeight(height),
+                    timestamp: crate::types::Timestamp(height),
+                    tx_root: Hash([1; 32]),
+                    validator: Address([2; 32]),
+                    signature: Signature(vec![0; 64]),
+                },
+                transactions: vec![],
+            };
+            storage.commit_block(&block, &WorldState::new()).unwrap();
+            parent_hash = block.header.calculate_hash().unwrap();
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_pruning_mode_horizon_and_parsing() {
+        assert_eq!(PruningMode::Archive.horizon(100), None);
+        assert_eq!(PruningMode::KeepLast(10).horizon(100), Some(91));
+        assert_eq!(PruningMode::KeepLast(10).horizon(5), Some(0));
+        assert_eq!(PruningMode::StateOnly.horizon(100), Some(100));
+
+        assert_eq!("archive".parse::<PruningMode>(), Ok(PruningMode::Archive));
+        assert_eq!("state-only".parse::<PruningMode>(), Ok(PruningMode::StateOnly));
+        assert_eq!("keep-last:64".parse::<PruningMode>(), Ok(PruningMode::KeepLast(64)));
+        assert!("keep-last:0".parse::<PruningMode>().is_err());
+        assert!("everything".parse::<PruningMode>().is_err());
+    }
+
+    #[test]
+    fn test_prune_keep_last_removes_old_bodies_only() {
+        let db_path = temp_db_path();
+        let storage = Storage::new(db_path.path()).unwrap();
+        let blocks = commit_chain(&storage, 10);
+
+        assert_eq!(storage.prune(PruningMode::KeepLast(3)).unwrap(), 7);
+        assert_eq!(storage.get_pruned_height().unwrap(), 7);
+        // Pruning again at the same tip is a no-op
+        assert_eq!(storage.prune(PruningMode::KeepLast(3)).unwrap(), 0);
+
+        let old_hash = blocks[2].header.calculate_hash().unwrap();
+        assert!(storage.get_block(&old_hash).unwrap().is_none());
+        assert_eq!(storage.get_header_by_height(2).unwrap(), Some(blocks[2].header.clone()));
+        assert_eq!(storage.get_block_by_height(7).unwrap(), Some(blocks[7].clone()));
+        assert!(matches!(
+            storage.get_block_by_height(2),
+            Err(StorageError::Pruned { height: 2, horizon: 7 })
+        ));
+    }
+
+    #[test]
+    fn test_prune_state_only_keeps_tip_body() {
+        let db_path = temp_db_path();
+        let storage = Storage::new(db_path.path()).unwrap();
+        let blocks = commit_chain(&storage, 5);
+
+        assert_eq!(storage.prune(PruningMode::Archive).unwrap(), 0);
+        assert_eq!(storage.prune(PruningMode::StateOnly).unwrap(), 4);
+        assert_eq!(storage.get_block_by_height(4).unwrap(), Some(blocks[4].clone()));
+        assert!(storage.get_block_by_height(3).is_err());
+    }
+
+    #[test]
+    fn test_get_account_at_returns_historical_values() {
+        let db_path = temp_db_path();
+        let storage = Storage::new(db_path.path()).unwrap();
+        let alice = Address([1; 32]);
+        let bob = Address([3; 32]);
+        let mut parent_hash = Hash([0; 32]);
+        for height in 0..4u64 {
+            // Only the accounts each block changed are committed
+            let mut changed = WorldState::new();
+            if height == 0 {
+                changed.insert(alice, Account { balance: 100, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None });
+            }
+            if height == 2 {
+                changed.insert(alice, Account { balance: 60, nonce: Nonce(1), authorized_key: None, vesting: None, hash_lock: None });
+                changed.insert(bob, Account { balance: 40, nonce: Nonce(0), authorized_key: None, vesting: None, hash_lock: None });
+            }
+            let block = Block {
+                header: BlockHeader {
+                    parent_hash,
+                    block_number: BlockHeight(height),
+                    timestamp: crate::types::Timestamp(height),
+                    tx_root: Hash([1; 32]),
+                    validator: Address([2; 32]),
+                    signature: Signature(vec![0; 64]),
+                },
+                transactions: vec![],
+            };
+            storage.commit_block(&block, &changed).unwrap();
+            parent_hash = block.header.calculate_hash().unwrap();
+        }
+
+        assert_eq!(storage.get_account(&alice).unwrap().unwrap().balance, 60);
+        assert_eq!(storage.get_account_at(&alice, 1).unwrap().unwrap().balance, 100);
+        assert_eq!(storage.get_account_at(&alice, 2).unwrap().unwrap().balance, 60);
+        assert_eq!(storage.get_account_at(&alice, 3).unwrap().unwrap().balance, 60);
+        assert!(storage.get_account_at(&bob, 1).unwrap().is_none());
+        assert_eq!(storage.get_account_at(&bob, 3).unwrap().unwrap().balance, 40);
+        assert!(storage.get_account_at(&Address([2; 32]), 3).unwrap().is_none());
+
+        // Collapsing history below the horizon keeps the record that is live at the horizon
+        storage.prune(PruningMode::KeepLast(1)).unwrap();
+        assert!(matches!(
+            storage.get_account_at(&alice, 2),
+            Err(StorageError::Pruned { height: 2, horizon: 3 })
+        ));
+        assert_eq!(storage.get_account_at(&alice, 3).unwrap().unwrap().balance, 60);
+        assert_eq!(storage.get_account_at(&bob, 3).unwrap().unwrap().balance, 40);
+        let history_cf = storage.get_cf(STATE_HISTORY_CF).unwrap();
+        assert_eq!(storage.db.iterator_cf(history_cf, IteratorMode::Start).count(), 2);
+        // The index entries at or below the horizon were consumed, so the next prune starts above it
+        let history_index_cf = storage.get_cf(HISTORY_INDEX_CF).unwrap();
+        assert_eq!(storage.db.iterator_cf(history_index_cf, IteratorMode::Start).count(), 0);
+    }
+
+    #[test]
+    fn test_check_integrity_repairs_torn_writes() {
+        let db_path = temp_db_path();
+        let storage = Storage::new(db_path.path()).unwrap();
+        let blocks = commit_chain(&storage, 3);
+        assert!(storage.check_integrity().unwrap().is_empty());
+
+        // A tip written without its state, as a crash between separate writes would leave it
+        let mut orphan = blocks[2].clone();
+        orphan.header.block_number = BlockHeight(3);
+        let orphan_hash = orphan.header.calculate_hash().unwrap();
+        storage.put_block(&orphan).unwrap();
+        storage.set_chain_tip(&orphan_hash, 3).unwrap();
+
+        assert_eq!(storage.check_integrity().unwrap(), vec![IntegrityRepair::TipRolledBack { from: 3, to: 2 }]);
+        let tip_hash = blocks[2].header.calculate_hash().unwrap();
+        assert_eq!(storage.get_chain_tip().unwrap(), Some((tip_hash, 2)));
+
+        let headers_cf = storage.get_cf(HEADERS_CF).unwrap();
+        storage.db.delete_cf(headers_cf, 2u64.to_be_bytes()).unwrap();
+        assert_eq!(storage.check_integrity().unwrap(), vec![IntegrityRepair::HeaderIndexRestored { height: 2 }]);
+        assert_eq!(storage.get_header_by_height(2).unwrap(), Some(blocks[2].header.clone()));
+        assert!(storage.check_integrity().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_check_integrity_clears_interrupted_genesis() {
+        let db_path = temp_db_path();
+        let storage = Storage::new(db_path.path()).unwrap();
+        let genesis = Block {
+            header: BlockHeader {
+                parent_hash: Hash([0; 32]),
+                block_number: BlockHeight(0),
+                timestamp: crate::types::Timestamp(0),
+                tx_root: Hash([1; 32]),
+                validator: Address([2; 32]),
+                signature: Signature(vec![0; 64]),
+            },
+            transactions: vec![],
+        };
+        storage.put_block(&genesis).unwrap();
+        storage.set_chain_tip(&genesis.header.calculate_hash().unwrap(), 0).unwrap();
+
+        assert_eq!(storage.check_integrity().unwrap(), vec![IntegrityRepair::TipCleared { height: 0 }]);
+        assert_eq!(storage.get_chain_tip().unwrap(), None);
     }
 }
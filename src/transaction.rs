@@ -1,19 +1,213 @@
 use serde::{Serialize, Deserialize};
-use crate::types::{Address, Signature, Nonce, Hash, PublicKey};
+use crate::htlc::{HashTimeLock, PREIMAGE_LEN};
+use crate::multisig::{Cosignature, MultisigError, MultisigPolicy};
+use crate::types::{address_from_public_key, Address, BlockHeight, Signature, Nonce, Hash, PublicKey};
 use bincode::{Encode, Decode};
+use bincode::error::{DecodeError, EncodeError};
 use sha2::{Sha256, Digest};
-use anyhow::{Result, Context}; // For context on errors if needed
 use thiserror::Error; // Using thiserror for convenience
 use ed25519_dalek;
+use std::fmt;
 
-/// A transaction in the blockchain.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+/// Envelope version of transactions created before typed kinds existed. These are always
+/// transfers and are signed over the original flat payload, so their ids do not change.
+pub const LEGACY_TRANSACTION_VERSION: u8 = 0;
+
+/// Envelope version with typed kinds but only single-key senders.
+pub const SINGLE_KEY_TRANSACTION_VERSION: u8 = 1;
+
+/// Envelope version that added multisig senders.
+pub const MULTISIG_TRANSACTION_VERSION: u8 = 2;
+
+/// Envelope version produced by this build. Adds a list of optional fields, so new optional
+/// fields are new `EnvelopeField` variants rather than new envelope versions.
+pub const TRANSACTION_VERSION: u8 = 3;
+
+/// Upper bound on the length of a memo in bytes. Enough for a payment reference or an
+/// exchange deposit tag, small enough that memos cannot be used to bloat blocks.
+pub const MAX_MEMO_LEN: usize = 128;
+
+/// Upper bound on the entries in one batch transfer, which keeps blocks and per-transaction
+/// work bounded.
+pub const MAX_BATCH_TRANSFERS: usize = 256;
+
+/// A transaction in the blockchain: a versioned envelope around a typed operation.
+///
+/// The bincode layout depends on `version`. Versions 0 and 1 encode a bare public key and
+/// signature and versions before 3 have no optional fields, so transactions stored or signed
+/// under them keep their bytes and ids. From version 3 the validity window and memo are
+/// optional fields, encoded only when they differ from their defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
-    pub sender: PublicKey,
+    pub version: u8,
+    pub sender: TxSender,
+    pub nonce: Nonce,
+    pub kind: TransactionKind,
+    #[serde(default)]
+    pub validity: ValidityWindow,
+    /// Free-form bytes chosen by the sender, e.g. a payment reference. Signed over and
+    /// indexed by nodes, but never interpreted by the state machine.
+    #[serde(default)]
+    pub memo: Vec<u8>,
+    pub signature: TxSignature,
+}
+
+/// Block heights a transaction may be included at. Both bounds are optional and signed
+/// over, so a transaction that sat in a mempool cannot be mined after its sender gave up on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct ValidityWindow {
+    /// Only blocks strictly above this height may include the transaction.
+    pub valid_after_height: Option<BlockHeight>,
+    /// Blocks above this height may no longer include the transaction.
+    pub valid_until_height: Option<BlockHeight>,
+}
+
+impl ValidityWindow {
+    /// A window with neither bound, which is what envelopes before version 3 imply.
+    pub fn is_unbounded(&self) -> bool {
+        self.valid_after_height.is_none() && self.valid_until_height.is_none()
+    }
+
+    /// Whether a block at `height` may include the transaction.
+    pub fn contains(&self, height: BlockHeight) -> bool {
+        self.valid_after_height.is_none_or(|after| height > after) && !self.is_expired_at(height)
+    }
+
+    /// Whether the transaction can no longer be included at `height` or any later height.
+    pub fn is_expired_at(&self, height: BlockHeight) -> bool {
+        self.valid_until_height.is_some_and(|until| height > until)
+    }
+}
+
+/// Who authorizes a transaction, and therefore which account it is sent from. New variants
+/// must be appended so the encoding of existing ones stays stable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum TxSender {
+    /// The account whose address is derived from this key.
+    Single(PublicKey),
+    /// An account controlled by an M-of-N policy.
+    Multisig(MultisigPolicy),
+    /// An account that rotated its authorized key to `public_key`. The address can no
+    /// longer be derived from the signer, so it is named explicitly.
+    AuthorizedKey { account: Address, public_key: PublicKey },
+}
+
+impl TxSender {
+    /// The account the transaction is sent from.
+    pub fn address(&self) -> Address {
+        match self {
+            TxSender::Single(public_key) => address_from_public_key(public_key),
+            TxSender::Multisig(policy) => policy.address(),
+            TxSender::AuthorizedKey { account, .. } => *account,
+        }
+    }
+
+    /// The key expected to sign, or `None` for multisig senders.
+    pub fn signing_key(&self) -> Option<&PublicKey> {
+        match self {
+            TxSender::Single(public_key) | TxSender::AuthorizedKey { public_key, .. } => Some(public_key),
+            TxSender::Multisig(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for TxSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxSender::Single(public_key) => write!(f, "{}", public_key),
+            TxSender::Multisig(policy) => write!(f, "{}", policy),
+            TxSender::AuthorizedKey { account, public_key } => write!(f, "{} on behalf of {}", public_key, account),
+        }
+    }
+}
+
+/// Signatures matching the shape of the `TxSender`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub enum TxSignature {
+    Single(Signature),
+    /// One entry per cosigning key, ordered by key index.
+    Multisig(Vec<Cosignature>),
+}
+
+impl fmt::Display for TxSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxSignature::Single(signature) => write!(f, "{}", signature),
+            TxSignature::Multisig(cosignatures) => {
+                let indices: Vec<String> = cosignatures.iter().map(|c| c.key_index.to_string()).collect();
+                write!(f, "{} cosignature(s) from keys [{}]", cosignatures.len(), indices.join(", "))
+            }
+        }
+    }
+}
+
+/// The operation a transaction performs. New kinds must be appended, never inserted, so
+/// the encoding of existing kinds stays stable. Decoding goes through `RelayableKind`, which
+/// must gain the same variant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode)]
+pub enum TransactionKind {
+    /// Moves `amount` from the sender to `recipient`.
+    Transfer(Transfer),
+    /// Pays every entry from the sender under one nonce and signature. Applied all-or-nothing.
+    BatchTransfer(Vec<Transfer>),
+    /// Makes `new_key` the only key that may sign for the sender's account. Rotating back to
+    /// the key the address was derived from restores the default.
+    RotateKey { new_key: PublicKey },
+    /// Moves `amount` from the sender into a new escrow account held under `lock`.
+    LockFunds { amount: u64, lock: HashTimeLock },
+    /// Releases the escrow at `lock_id` to its recipient before the timelock, given the
+    /// preimage of its hashlock. Anyone may submit it.
+    ClaimLock { lock_id: Address, preimage: [u8; PREIMAGE_LEN] },
+    /// Returns the escrow at `lock_id` to its refund address once the timelock has passed.
+    /// Anyone may submit it.
+    RefundLock { lock_id: Address },
+    /// A transaction signed by another account, submitted under the relayer's own nonce and
+    /// signature. Both nonces advance and both signatures are checked. Relays cannot be
+    /// nested.
+    Relayed(Box<Transaction>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+pub struct Transfer {
     pub recipient: Address,
     pub amount: u64,
-    pub nonce: Nonce,
-    pub signature: Signature,
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionKind::Transfer(transfer) => write!(f, "transfer of {} to {}", transfer.amount, transfer.recipient),
+            TransactionKind::BatchTransfer(transfers) => match self.total_amount() {
+                Some(total) => write!(f, "batch of {} transfers totalling {}", transfers.len(), total),
+                None => write!(f, "batch of {} transfers with an overflowing total", transfers.len()),
+            },
+            TransactionKind::RotateKey { new_key } => write!(f, "rotation of the authorized key to {}", new_key),
+            TransactionKind::LockFunds { amount, lock } => {
+                write!(f, "lock of {} for {} until height {}", amount, lock.recipient, lock.timelock)
+            }
+            TransactionKind::ClaimLock { lock_id, .. } => write!(f, "claim of lock {}", lock_id),
+            TransactionKind::RefundLock { lock_id } => write!(f, "refund of lock {}", lock_id),
+            TransactionKind::Relayed(inner) => write!(f, "relay of {} from {}", inner.kind, inner.sender_address()),
+        }
+    }
+}
+
+impl TransactionKind {
+    /// Total amount debited from the sender, or `None` if it does not fit in a `u64`.
+    pub fn total_amount(&self) -> Option<u64> {
+        match self {
+            TransactionKind::Transfer(transfer) => Some(transfer.amount),
+            TransactionKind::BatchTransfer(transfers) => {
+                transfers.iter().try_fold(0u64, |total, transfer| total.checked_add(transfer.amount))
+            }
+            TransactionKind::LockFunds { amount, .. } => Some(*amount),
+            // A relayer only pays its nonce; the wrapped transaction debits its own sender
+            TransactionKind::RotateKey { .. }
+            | TransactionKind::ClaimLock { .. }
+            | TransactionKind::RefundLock { .. }
+            | TransactionKind::Relayed(_) => Some(0),
+        }
+    }
 }
 
 /// Represents errors that can occur during transaction validation (stateless checks).
@@ -27,41 +221,419 @@ pub enum TxValidationError {
     ZeroAmount,
     #[error("Sender and recipient address cannot be the same")]
     SenderIsRecipient,
+    #[error("Unsupported transaction version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Invalid multisig policy: {0}")]
+    InvalidMultisigPolicy(#[from] MultisigError),
+    #[error("Signatures do not match the sender type")]
+    SignatureMismatch,
+    #[error("Cosignature index {0} is out of range or repeated")]
+    InvalidCosignatureIndex(u8),
+    #[error("Not enough valid signatures: have {have}, need {need}")]
+    InsufficientSignatures { have: usize, need: usize },
+    #[error("Transactions being combined are not the same transaction")]
+    TransactionMismatch,
+    #[error("Batch transfer has no entries")]
+    EmptyBatch,
+    #[error("Batch transfer has {got} entries, the limit is {max}")]
+    BatchTooLarge { got: usize, max: usize },
+    #[error("Total amount overflows")]
+    AmountOverflow,
+    #[error("Validity window is empty: valid after height {valid_after} but only until height {valid_until}")]
+    EmptyValidityWindow { valid_after: u64, valid_until: u64 },
+    #[error("Memo is {len} bytes, the limit is {max}")]
+    MemoTooLong { len: usize, max: usize },
+    #[error("A relayed transaction cannot wrap another relayed transaction")]
+    NestedRelay,
+    #[error("A relayed transaction must be sent by another account than its relayer")]
+    SelfRelay,
     // Add more stateless validation errors here if needed (e.g., amount is zero)
 }
 
-/// A subset of transaction fields that are signed over.
+/// The envelope fields that are signed over: everything except the signature.
 #[derive(Serialize, Encode)]
 struct TransactionSignablePayload<'a> {
+    version: u8,
+    sender: &'a TxSender,
+    nonce: Nonce,
+    kind: &'a TransactionKind,
+}
+
+/// The signed fields of a version 1 envelope, which only had single-key senders.
+#[derive(Serialize, Encode)]
+struct SingleKeySignablePayload<'a> {
+    version: u8,
+    sender: &'a PublicKey,
+    nonce: Nonce,
+    kind: &'a TransactionKind,
+}
+
+/// The signed fields of a legacy (version 0) transfer.
+#[derive(Serialize, Encode)]
+struct LegacyTransferSignablePayload<'a> {
     sender: &'a PublicKey,
     recipient: &'a Address,
     amount: u64,
     nonce: Nonce,
 }
 
+/// An optional envelope field. Version 3 envelopes carry a list of these between the kind
+/// and the signature, in variant order and only for fields that differ from their default,
+/// so every transaction has exactly one encoding. New variants must be appended.
+#[derive(Encode, Decode)]
+enum EnvelopeField {
+    Validity(ValidityWindow),
+    Memo(Vec<u8>),
+}
+
+impl EnvelopeField {
+    /// Position of the variant, which orders fields in the encoded list.
+    fn position(&self) -> u8 {
+        match self {
+            EnvelopeField::Validity(_) => 0,
+            EnvelopeField::Memo(_) => 1,
+        }
+    }
+}
+
+/// Versions 0 and 1 predate multisig and encode the key and signature without an enum tag.
+fn uses_single_key_layout(version: u8) -> bool {
+    version <= SINGLE_KEY_TRANSACTION_VERSION
+}
+
+/// Versions before 3 cannot carry optional fields.
+fn has_optional_fields(version: u8) -> bool {
+    version >= TRANSACTION_VERSION
+}
+
+impl Encode for Transaction {
+    fn encode<E: bincode::enc::Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.version.encode(encoder)?;
+        let fields = self.optional_fields();
+        if !has_optional_fields(self.version) && !fields.is_empty() {
+            return Err(EncodeError::Other("validity windows and memos require envelope version 3"));
+        }
+        if uses_single_key_layout(self.version) {
+            let (TxSender::Single(sender), TxSignature::Single(signature)) = (&self.sender, &self.signature) else {
+                return Err(EncodeError::Other("multisig and authorized-key senders require envelope version 2"));
+            };
+            sender.encode(encoder)?;
+            self.nonce.encode(encoder)?;
+            self.kind.encode(encoder)?;
+            signature.encode(encoder)
+        } else {
+            self.sender.encode(encoder)?;
+            self.nonce.encode(encoder)?;
+            self.kind.encode(encoder)?;
+            if has_optional_fields(self.version) {
+                fields.encode(encoder)?;
+            }
+            self.signature.encode(encoder)
+        }
+    }
+}
+
+/// Variant index of `TransactionKind::Relayed` in its bincode encoding.
+const RELAYED_KIND_INDEX: u32 = 6;
+
+/// The kinds a relay may wrap: every `TransactionKind` except `Relayed`, with the same
+/// variant indexes and fields. The transaction inside a relay is decoded with this as its
+/// kind, so a relay of a relay is refused by the decoder instead of recursing.
+enum RelayableKind {
+    Transfer(Transfer),
+    BatchTransfer(Vec<Transfer>),
+    RotateKey { new_key: PublicKey },
+    LockFunds { amount: u64, lock: HashTimeLock },
+    ClaimLock { lock_id: Address, preimage: [u8; PREIMAGE_LEN] },
+    RefundLock { lock_id: Address },
+}
+
+impl RelayableKind {
+    /// Decodes the fields of the variant at `index`, which has already been read.
+    fn decode_variant<C, D: bincode::de::Decoder<Context = C>>(index: u32, decoder: &mut D) -> Result<Self, DecodeError> {
+        Ok(match index {
+            0 => RelayableKind::Transfer(Decode::decode(decoder)?),
+            1 => RelayableKind::BatchTransfer(Decode::decode(decoder)?),
+            2 => RelayableKind::RotateKey { new_key: Decode::decode(decoder)? },
+            3 => RelayableKind::LockFunds { amount: Decode::decode(decoder)?, lock: Decode::decode(decoder)? },
+            4 => RelayableKind::ClaimLock { lock_id: Decode::decode(decoder)?, preimage: Decode::decode(decoder)? },
+            5 => RelayableKind::RefundLock { lock_id: Decode::decode(decoder)? },
+            RELAYED_KIND_INDEX => return Err(DecodeError::Other("relayed transactions cannot be nested")),
+            found => {
+                return Err(DecodeError::UnexpectedVariant {
+                    type_name: "TransactionKind",
+                    allowed: &bincode::error::AllowedEnumVariants::Range { min: 0, max: RELAYED_KIND_INDEX },
+                    found,
+                })
+            }
+        })
+    }
+}
+
+impl From<RelayableKind> for TransactionKind {
+    fn from(kind: RelayableKind) -> Self {
+        match kind {
+            RelayableKind::Transfer(transfer) => TransactionKind::Transfer(transfer),
+            RelayableKind::BatchTransfer(transfers) => TransactionKind::BatchTransfer(transfers),
+            RelayableKind::RotateKey { new_key } => TransactionKind::RotateKey { new_key },
+            RelayableKind::LockFunds { amount, lock } => TransactionKind::LockFunds { amount, lock },
+            RelayableKind::ClaimLock { lock_id, preimage } => TransactionKind::ClaimLock { lock_id, preimage },
+            RelayableKind::RefundLock { lock_id } => TransactionKind::RefundLock { lock_id },
+        }
+    }
+}
+
+impl<C> Decode<C> for RelayableKind {
+    fn decode<D: bincode::de::Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let index = Decode::decode(decoder)?;
+        RelayableKind::decode_variant(index, decoder)
+    }
+}
+
+impl<C> Decode<C> for TransactionKind {
+    fn decode<D: bincode::de::Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let index = Decode::decode(decoder)?;
+        if index == RELAYED_KIND_INDEX {
+            let inner = Transaction::decode_envelope::<RelayableKind, C, D>(decoder)?;
+            return Ok(TransactionKind::Relayed(Box::new(inner)));
+        }
+        RelayableKind::decode_variant(index, decoder).map(TransactionKind::from)
+    }
+}
+
+impl<'de, C> bincode::de::BorrowDecode<'de, C> for TransactionKind {
+    fn borrow_decode<D: bincode::de::BorrowDecoder<'de, Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Decode::decode(decoder)
+    }
+}
+
+impl<C> Decode<C> for Transaction {
+    fn decode<D: bincode::de::Decoder<Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Transaction::decode_envelope::<TransactionKind, C, D>(decoder)
+    }
+}
+
+impl Transaction {
+    /// Decodes an envelope whose kind is decoded as `K`, which is `RelayableKind` for the
+    /// transaction inside a relay.
+    fn decode_envelope<K, C, D>(decoder: &mut D) -> Result<Self, DecodeError>
+    where
+        K: Decode<C> + Into<TransactionKind>,
+        D: bincode::de::Decoder<Context = C>,
+    {
+        let version: u8 = Decode::decode(decoder)?;
+        if uses_single_key_layout(version) {
+            let sender: PublicKey = Decode::decode(decoder)?;
+            let nonce = Decode::decode(decoder)?;
+            let kind = K::decode(decoder)?.into();
+            let signature: Signature = Decode::decode(decoder)?;
+            Ok(Transaction {
+                version,
+                sender: TxSender::Single(sender),
+                nonce,
+                kind,
+                validity: ValidityWindow::default(),
+                memo: Vec::new(),
+                signature: TxSignature::Single(signature),
+            })
+        } else {
+            let sender = Decode::decode(decoder)?;
+            let nonce = Decode::decode(decoder)?;
+            let kind = K::decode(decoder)?.into();
+            let fields = if has_optional_fields(version) { Decode::decode(decoder)? } else { Vec::new() };
+            let mut transaction = Transaction {
+                version,
+                sender,
+                nonce,
+                kind,
+                validity: ValidityWindow::default(),
+                memo: Vec::new(),
+                signature: Decode::decode(decoder)?,
+            };
+            transaction.set_optional_fields(fields)?;
+            Ok(transaction)
+        }
+    }
+
+    /// The optional fields that differ from their defaults, in encoding order.
+    fn optional_fields(&self) -> Vec<EnvelopeField> {
+        let mut fields = Vec::new();
+        if !self.validity.is_unbounded() {
+            fields.push(EnvelopeField::Validity(self.validity));
+        }
+        if !self.memo.is_empty() {
+            fields.push(EnvelopeField::Memo(self.memo.clone()));
+        }
+        fields
+    }
+
+    /// Applies decoded optional fields, refusing any encoding `optional_fields` would not
+    /// produce so that a transaction's bytes cannot be altered without changing its id.
+    fn set_optional_fields(&mut self, fields: Vec<EnvelopeField>) -> Result<(), DecodeError> {
+        let mut previous = None;
+        for field in fields {
+            if previous.is_some_and(|position| position >= field.position()) {
+                return Err(DecodeError::Other("optional transaction fields are out of order or repeated"));
+            }
+            previous = Some(field.position());
+            match field {
+                EnvelopeField::Validity(validity) if !validity.is_unbounded() => self.validity = validity,
+                EnvelopeField::Memo(memo) if !memo.is_empty() => self.memo = memo,
+                _ => return Err(DecodeError::Other("optional transaction fields must not hold their default value")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de, C> bincode::de::BorrowDecode<'de, C> for Transaction {
+    fn borrow_decode<D: bincode::de::BorrowDecoder<'de, Context = C>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        Decode::decode(decoder)
+    }
+}
+
+fn verify_ed25519(public_key: &PublicKey, message: &Hash, signature: &Signature) -> bool {
+    let Ok(signature_bytes) = <&[u8; 64]>::try_from(signature.0.as_slice()) else {
+        return false;
+    };
+    let dalek_signature = ed25519_dalek::Signature::from_bytes(signature_bytes);
+    public_key.0.verify_strict(message.as_ref(), &dalek_signature).is_ok()
+}
+
 impl Transaction {
-    /// Creates a new transaction.
+    /// Creates a new transfer transaction.
     /// The signature is typically added after creation by the sender.
     pub fn new(sender: PublicKey, recipient: Address, amount: u64, nonce: Nonce, signature: Signature) -> Self {
+        Self::with_kind(sender, nonce, TransactionKind::Transfer(Transfer { recipient, amount }), signature)
+    }
+
+    /// Creates a single-key transaction of any kind using the current envelope version.
+    pub fn with_kind(sender: PublicKey, nonce: Nonce, kind: TransactionKind, signature: Signature) -> Self {
         Transaction {
-            sender,
-            recipient,
-            amount,
+            version: TRANSACTION_VERSION,
+            sender: TxSender::Single(sender),
             nonce,
-            signature,
+            kind,
+            validity: ValidityWindow::default(),
+            memo: Vec::new(),
+            signature: TxSignature::Single(signature),
         }
     }
 
-    /// Hashes the signable payload of the transaction.
-    pub fn id(&self) -> Result<Hash, bincode::error::EncodeError> {
-        let payload = TransactionSignablePayload {
-            sender: &self.sender,
-            recipient: &self.recipient,
-            amount: self.amount,
-            nonce: self.nonce,
-        };
+    /// Creates a multisig transaction with no cosignatures yet. Each key holder adds theirs
+    /// with `add_cosignature`, and partially signed copies are merged with `combine`.
+    pub fn new_multisig(policy: MultisigPolicy, nonce: Nonce, kind: TransactionKind) -> Self {
+        Transaction {
+            version: TRANSACTION_VERSION,
+            sender: TxSender::Multisig(policy),
+            nonce,
+            kind,
+            validity: ValidityWindow::default(),
+            memo: Vec::new(),
+            signature: TxSignature::Multisig(Vec::new()),
+        }
+    }
+
+    /// Sends from `account` instead of the address derived from the signing key, for accounts
+    /// that rotated their authorized key. Has no effect on multisig senders or when `account`
+    /// is the derived address.
+    pub fn with_account(mut self, account: Address) -> Self {
+        if let TxSender::Single(public_key) = self.sender {
+            if address_from_public_key(&public_key) != account {
+                self.sender = TxSender::AuthorizedKey { account, public_key };
+            }
+        }
+        self
+    }
+
+    /// Restricts the heights the transaction may be included at. Call before signing, since
+    /// the window is part of the signed payload.
+    pub fn with_validity(mut self, validity: ValidityWindow) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Attaches a memo. Like the validity window, it must be set before signing.
+    pub fn with_memo(mut self, memo: Vec<u8>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    /// The account this transaction is sent from.
+    pub fn sender_address(&self) -> Address {
+        self.sender.address()
+    }
+
+    /// The transaction wrapped by a relayer, if this is a relay.
+    pub fn relayed(&self) -> Option<&Transaction> {
+        match &self.kind {
+            TransactionKind::Relayed(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
+    /// Every account the transaction names: the sender, the recipients, escrow accounts,
+    /// and the same for a relayed transaction. Sorted and without duplicates. Claims and
+    /// refunds name only the escrow; the accounts they pay out to depend on the lock state.
+    pub fn involved_addresses(&self) -> Vec<Address> {
+        let sender = self.sender_address();
+        let mut addresses = vec![sender];
+        match &self.kind {
+            TransactionKind::Transfer(transfer) => addresses.push(transfer.recipient),
+            TransactionKind::BatchTransfer(transfers) => addresses.extend(transfers.iter().map(|transfer| transfer.recipient)),
+            TransactionKind::RotateKey { .. } => {}
+            TransactionKind::LockFunds { lock, .. } => {
+                addresses.extend([HashTimeLock::lock_address(&sender, self.nonce), lock.recipient, lock.refund_address]);
+            }
+            TransactionKind::ClaimLock { lock_id, .. } | TransactionKind::RefundLock { lock_id } => addresses.push(*lock_id),
+            TransactionKind::Relayed(inner) => addresses.extend(inner.involved_addresses()),
+        }
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses
+    }
+
+    /// Encodes the fields covered by the signature for this transaction's version.
+    fn signable_bytes(&self) -> Result<Vec<u8>, EncodeError> {
         let bincode_config = bincode::config::standard();
-        let serialized_payload = bincode::encode_to_vec(&payload, bincode_config)?;
+        match (self.version, &self.sender, &self.kind) {
+            (LEGACY_TRANSACTION_VERSION, TxSender::Single(sender), TransactionKind::Transfer(transfer)) => {
+                let payload = LegacyTransferSignablePayload {
+                    sender,
+                    recipient: &transfer.recipient,
+                    amount: transfer.amount,
+                    nonce: self.nonce,
+                };
+                bincode::encode_to_vec(&payload, bincode_config)
+            }
+            (SINGLE_KEY_TRANSACTION_VERSION, TxSender::Single(sender), _) => {
+                let payload = SingleKeySignablePayload {
+                    version: self.version,
+                    sender,
+                    nonce: self.nonce,
+                    kind: &self.kind,
+                };
+                bincode::encode_to_vec(&payload, bincode_config)
+            }
+            _ => {
+                let payload = TransactionSignablePayload {
+                    version: self.version,
+                    sender: &self.sender,
+                    nonce: self.nonce,
+                    kind: &self.kind,
+                };
+                let mut bytes = bincode::encode_to_vec(&payload, bincode_config)?;
+                if has_optional_fields(self.version) {
+                    bytes.extend(bincode::encode_to_vec(self.optional_fields(), bincode_config)?);
+                }
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Hashes the signable payload of the transaction.
+    pub fn id(&self) -> Result<Hash, EncodeError> {
+        let serialized_payload = self.signable_bytes()?;
         
         let mut hasher = Sha256::new();
         hasher.update(&serialized_payload);
@@ -69,29 +641,80 @@ impl Transaction {
         Ok(Hash(result.into()))
     }
 
-    /// Verifies the transaction's signature.
-    pub fn verify_signature(&self, sender_public_key: &PublicKey) -> Result<(), anyhow::Error> {
-        let message_hash = self.id()?;
-        let signature_bytes: &[u8; 64] = self.signature.0.as_slice().try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid signature format"))?;
-        
-        let dalek_signature = ed25519_dalek::Signature::from_bytes(signature_bytes);
+    /// Verifies the transaction's signatures against its sender: the single key's signature,
+    /// or at least `threshold` valid cosignatures from distinct keys of the policy.
+    pub fn verify_signature(&self) -> Result<(), TxValidationError> {
+        let message_hash = self.id().map_err(|e| TxValidationError::SerializationError(e.to_string()))?;
+        match (&self.sender, &self.signature) {
+            (TxSender::Single(public_key) | TxSender::AuthorizedKey { public_key, .. }, TxSignature::Single(signature)) => {
+                if verify_ed25519(public_key, &message_hash, signature) {
+                    Ok(())
+                } else {
+                    Err(TxValidationError::InvalidSignature)
+                }
+            }
+            (TxSender::Multisig(policy), TxSignature::Multisig(cosignatures)) => {
+                let keys = policy.public_keys();
+                let mut seen = vec![false; keys.len()];
+                for cosignature in cosignatures {
+                    let index = cosignature.key_index as usize;
+                    if index >= keys.len() || seen[index] {
+                        return Err(TxValidationError::InvalidCosignatureIndex(cosignature.key_index));
+                    }
+                    seen[index] = true;
+                    if !verify_ed25519(&keys[index], &message_hash, &cosignature.signature) {
+                        return Err(TxValidationError::InvalidSignature);
+                    }
+                }
+                let need = policy.threshold() as usize;
+                if cosignatures.len() < need {
+                    return Err(TxValidationError::InsufficientSignatures { have: cosignatures.len(), need });
+                }
+                Ok(())
+            }
+            _ => Err(TxValidationError::SignatureMismatch),
+        }
+    }
 
-        sender_public_key.0.verify_strict(message_hash.as_ref(), &dalek_signature)
-            .context("Signature verification failed")
+    /// Adds or replaces the cosignature for `key_index`, keeping the list ordered by index.
+    pub fn add_cosignature(&mut self, cosignature: Cosignature) -> Result<(), TxValidationError> {
+        let (TxSender::Multisig(policy), TxSignature::Multisig(cosignatures)) = (&self.sender, &mut self.signature) else {
+            return Err(TxValidationError::SignatureMismatch);
+        };
+        if cosignature.key_index as usize >= policy.public_keys().len() {
+            return Err(TxValidationError::InvalidCosignatureIndex(cosignature.key_index));
+        }
+        match cosignatures.binary_search_by_key(&cosignature.key_index, |c| c.key_index) {
+            Ok(position) => cosignatures[position] = cosignature,
+            Err(position) => cosignatures.insert(position, cosignature),
+        }
+        Ok(())
+    }
+
+    /// Merges the cosignatures of a partially signed copy of the same transaction.
+    pub fn combine(&mut self, other: &Transaction) -> Result<(), TxValidationError> {
+        let same_payload = self.version == other.version
+            && self.sender == other.sender
+            && self.nonce == other.nonce
+            && self.kind == other.kind
+            && self.validity == other.validity
+            && self.memo == other.memo;
+        if !same_payload {
+            return Err(TxValidationError::TransactionMismatch);
+        }
+        let TxSignature::Multisig(cosignatures) = &other.signature else {
+            return Err(TxValidationError::SignatureMismatch);
+        };
+        for cosignature in cosignatures {
+            self.add_cosignature(cosignature.clone())?;
+        }
+        Ok(())
     }
 
     /// Calculates the hash of the transaction data that is meant to be signed.
     /// This typically excludes the signature itself.
-    pub fn data_to_sign_hash(&self) -> Result<Hash, bincode::error::EncodeError> {
-        let payload = TransactionSignablePayload {
-            sender: &self.sender,
-            recipient: &self.recipient,
-            amount: self.amount,
-            nonce: self.nonce,
-        };
-        let bincode_config = bincode::config::standard();
-        let serialized_payload = bincode::encode_to_vec(&payload, bincode_config)?;
+    pub fn data_to_sign_hash(&self) -> Result<Hash, EncodeError> {
+        let serialized_payload = self.signable_bytes()?;
 
         let mut hasher = Sha256::new();
         hasher.update(&serialized_payload);
@@ -101,23 +724,84 @@ impl Transaction {
     /// Performs intrinsic property validation checks on the transaction.
     /// This does NOT verify the signature and does NOT check against world state.
     pub fn validate_intrinsic_properties(&self) -> Result<(), TxValidationError> {
-        if self.amount == 0 {
-            return Err(TxValidationError::ZeroAmount);
+        let supported = match self.version {
+            TRANSACTION_VERSION => true,
+            MULTISIG_TRANSACTION_VERSION => self.optional_fields().is_empty(),
+            SINGLE_KEY_TRANSACTION_VERSION | LEGACY_TRANSACTION_VERSION => {
+                matches!((&self.sender, &self.kind), (TxSender::Single(_), TransactionKind::Transfer(_)))
+                    && self.optional_fields().is_empty()
+            }
+            _ => false,
+        };
+        if !supported {
+            return Err(TxValidationError::UnsupportedVersion(self.version));
+        }
+        if let ValidityWindow { valid_after_height: Some(after), valid_until_height: Some(until) } = self.validity {
+            if after >= until {
+                return Err(TxValidationError::EmptyValidityWindow { valid_after: after.0, valid_until: until.0 });
+            }
+        }
+        if self.memo.len() > MAX_MEMO_LEN {
+            return Err(TxValidationError::MemoTooLong { len: self.memo.len(), max: MAX_MEMO_LEN });
+        }
+        match (&self.sender, &self.signature) {
+            (TxSender::Single(_) | TxSender::AuthorizedKey { .. }, TxSignature::Single(_)) => {}
+            (TxSender::Multisig(policy), TxSignature::Multisig(_)) => policy.check()?,
+            _ => return Err(TxValidationError::SignatureMismatch),
+        }
+        match &self.kind {
+            TransactionKind::Transfer(transfer) => {
+                if transfer.amount == 0 {
+                    return Err(TxValidationError::ZeroAmount);
+                }
+                // Optional: Prohibit sending to oneself in simple transfers
+                // if self.sender == self.recipient {
+                //     return Err(TxValidationError::SenderIsRecipient);
+                // }
+            }
+            TransactionKind::BatchTransfer(transfers) => {
+                if transfers.is_empty() {
+                    return Err(TxValidationError::EmptyBatch);
+                }
+                if transfers.len() > MAX_BATCH_TRANSFERS {
+                    return Err(TxValidationError::BatchTooLarge { got: transfers.len(), max: MAX_BATCH_TRANSFERS });
+                }
+                if transfers.iter().any(|transfer| transfer.amount == 0) {
+                    return Err(TxValidationError::ZeroAmount);
+                }
+                if self.kind.total_amount().is_none() {
+                    return Err(TxValidationError::AmountOverflow);
+                }
+            }
+            TransactionKind::LockFunds { amount, .. } => {
+                if *amount == 0 {
+                    return Err(TxValidationError::ZeroAmount);
+                }
+            }
+            TransactionKind::Relayed(inner) => {
+                if inner.relayed().is_some() {
+                    return Err(TxValidationError::NestedRelay);
+                }
+                if inner.sender_address() == self.sender_address() {
+                    return Err(TxValidationError::SelfRelay);
+                }
+                inner.validate_intrinsic_properties()?;
+            }
+            TransactionKind::RotateKey { .. } | TransactionKind::ClaimLock { .. } | TransactionKind::RefundLock { .. } => {}
         }
-        // Optional: Prohibit sending to oneself in simple transfers
-        // if self.sender == self.recipient {
-        //     return Err(TxValidationError::SenderIsRecipient);
-        // }
         // Add other stateless checks if necessary (e.g., max amount, field formats if not covered by types)
         Ok(())
     }
 
     /// Performs comprehensive stateless validation: intrinsic properties and signature verification.
     /// This combines stateless (`validate_intrinsic_properties`) and stateful-like (`verify_signature`) checks.
-    pub fn validate(&self, sender_public_key: &PublicKey) -> Result<(), TxValidationError> {
+    pub fn validate(&self) -> Result<(), TxValidationError> {
         self.validate_intrinsic_properties()?;
-        self.verify_signature(sender_public_key)
-            .map_err(|_e| TxValidationError::InvalidSignature)
+        self.verify_signature()?;
+        match self.relayed() {
+            Some(inner) => inner.verify_signature(),
+            None => Ok(()),
+        }
     }
 }
 
@@ -159,11 +843,13 @@ mod tests {
         let nonce_val = TypesNonce(1);
 
         // Create the data to be signed
+        let kind = TransactionKind::Transfer(Transfer { recipient: recipient_address, amount });
+        let sender = TxSender::Single(sender_wallet.public_key);
         let signable_payload = TransactionSignablePayload {
-            sender: &sender_wallet.public_key,
-            recipient: &recipient_address,
-            amount,
+            version: TRANSACTION_VERSION,
+            sender: &sender,
             nonce: nonce_val,
+            kind: &kind,
         };
         let bincode_config = bincode::config::standard();
         let serialized_payload = bincode::encode_to_vec(&signable_payload, bincode_config)?;
@@ -187,7 +873,7 @@ mod tests {
         assert_eq!(tx.data_to_sign_hash()?, data_hash, "data_to_sign_hash mismatch");
 
         // 2. Verify signature (direct call)
-        assert!(tx.verify_signature(&sender_wallet.public_key).is_ok(), "Signature verification failed");
+        assert!(tx.verify_signature().is_ok(), "Signature verification failed");
 
         // 3. Verify ID hash (should be different from data_to_sign_hash)
         let tx_id = tx.id()?;
@@ -196,10 +882,10 @@ mod tests {
         
         // Tamper with the transaction and check signature verification fails
         let mut tampered_tx = tx.clone();
-        tampered_tx.amount = 200;
+        tampered_tx.kind = TransactionKind::Transfer(Transfer { recipient: recipient_address, amount: 200 });
         assert!(tampered_tx.validate_intrinsic_properties().is_ok(), "Intrinsic validation should pass for tampered amount if not zero");
-        assert!(tampered_tx.verify_signature(&sender_wallet.public_key).is_err(), "Signature verification should fail for tampered tx");
-        assert_eq!(tampered_tx.validate(&sender_wallet.public_key), Err(TxValidationError::InvalidSignature), "Full validation should fail for tampered tx due to signature");
+        assert!(tampered_tx.verify_signature().is_err(), "Signature verification should fail for tampered tx");
+        assert_eq!(tampered_tx.validate(), Err(TxValidationError::InvalidSignature), "Full validation should fail for tampered tx due to signature");
 
         Ok(())
     }
@@ -213,12 +899,11 @@ mod tests {
         let signature = sender_wallet.sign_data_hash(&TypesHash([0u8; 32])); // Dummy signature for this test
         
         let tx1 = Transaction::new(sender_wallet.public_key, recipient_address, amount, nonce, signature.clone());
-        let tx1_again = Transaction::new(sender_wallet.public_key, recipient_address, amount, nonce, signature);
+        let tx1_again = Transaction::new(sender_wallet.public_key, recipient_address, amount, nonce, signature.clone());
 
         assert_eq!(tx1.id()?, tx1_again.id()?, "Transaction ID should be consistent for identical transactions");
 
-        let mut tx2 = tx1.clone();
-        tx2.amount = 51; // Change amount
+        let tx2 = Transaction::new(sender_wallet.public_key, recipient_address, 51, nonce, signature); // Change amount
         assert_ne!(tx1.id()?, tx2.id()?, "Transaction ID should change if amount changes");
         
         Ok(())
@@ -260,7 +945,7 @@ mod tests {
             TypesNonce(1),
             valid_signature.clone()
         );
-        assert!(tx_fully_valid.validate(&sender_wallet.public_key).is_ok(), "Full validation failed for valid tx");
+        assert!(tx_fully_valid.validate().is_ok(), "Full validation failed for valid tx");
 
         let tx_bad_sig = Transaction::new(
             sender_wallet.public_key,
@@ -269,7 +954,7 @@ mod tests {
             TypesNonce(1),
             sender_wallet.sign_data_hash(&TypesHash([1u8; 32])) // Signature for different data
         );
-        assert_eq!(tx_bad_sig.validate(&sender_wallet.public_key), Err(TxValidationError::InvalidSignature), "Full validation should fail for bad signature");
+        assert_eq!(tx_bad_sig.validate(), Err(TxValidationError::InvalidSignature), "Full validation should fail for bad signature");
 
         let tx_zero_amount_full_val = Transaction::new(
             sender_wallet.public_key,
@@ -279,6 +964,257 @@ mod tests {
             valid_signature // Signature might be valid for zero amount, but intrinsic check should fail first
         );
         // The validate() method calls validate_intrinsic_properties() first.
-        assert_eq!(tx_zero_amount_full_val.validate(&sender_wallet.public_key), Err(TxValidationError::ZeroAmount), "Full validation should fail for zero amount before checking signature");
+        assert_eq!(tx_zero_amount_full_val.validate(), Err(TxValidationError::ZeroAmount), "Full validation should fail for zero amount before checking signature");
+    }
+
+    #[test]
+    fn transaction_envelope_versions() {
+        let sender_wallet = TestWallet::new();
+        let recipient_address = TestWallet::new().address;
+        let tx = Transaction::new(sender_wallet.public_key, recipient_address, 10, TypesNonce(0), TypesSignature(vec![0; 64]));
+        assert_eq!(tx.version, TRANSACTION_VERSION);
+        assert_eq!(tx.kind, TransactionKind::Transfer(Transfer { recipient: recipient_address, amount: 10 }));
+
+        let bincode_config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&tx, bincode_config).unwrap();
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, tx);
+
+        // The version is signed over, and legacy transfers hash the original flat payload
+        let mut legacy = tx.clone();
+        legacy.version = LEGACY_TRANSACTION_VERSION;
+        assert_ne!(legacy.id().unwrap(), tx.id().unwrap());
+        assert!(legacy.validate_intrinsic_properties().is_ok());
+
+        // Version 1 keeps the layout it was stored with: no sender or signature tags
+        let mut single_key = tx.clone();
+        single_key.version = SINGLE_KEY_TRANSACTION_VERSION;
+        let encoded = bincode::encode_to_vec(&single_key, bincode_config).unwrap();
+        let flat = (SINGLE_KEY_TRANSACTION_VERSION, sender_wallet.public_key, TypesNonce(0), &tx.kind, TypesSignature(vec![0; 64]));
+        assert_eq!(encoded, bincode::encode_to_vec(&flat, bincode_config).unwrap());
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, single_key);
+
+        let mut future = tx.clone();
+        future.version = TRANSACTION_VERSION + 1;
+        assert_eq!(future.validate_intrinsic_properties(), Err(TxValidationError::UnsupportedVersion(TRANSACTION_VERSION + 1)));
+    }
+
+    #[test]
+    fn multisig_transaction_needs_threshold_of_cosignatures() {
+        let cosigners = [TestWallet::new(), TestWallet::new(), TestWallet::new()];
+        let policy = MultisigPolicy::new(2, cosigners.iter().map(|w| w.public_key).collect()).unwrap();
+        let recipient_address = TestWallet::new().address;
+        let unsigned = Transaction::new_multisig(
+            policy.clone(),
+            TypesNonce(0),
+            TransactionKind::Transfer(Transfer { recipient: recipient_address, amount: 25 }),
+        );
+        assert_eq!(unsigned.sender_address(), policy.address());
+        let id = unsigned.id().unwrap();
+        let cosign = |tx: &mut Transaction, wallet: &TestWallet| {
+            let key_index = policy.key_index(&wallet.public_key).unwrap();
+            tx.add_cosignature(Cosignature { key_index, signature: wallet.sign_data_hash(&id) }).unwrap();
+        };
+
+        // Two cosigners sign separate copies, which are then combined
+        let mut first = unsigned.clone();
+        cosign(&mut first, &cosigners[0]);
+        assert_eq!(first.validate(), Err(TxValidationError::InsufficientSignatures { have: 1, need: 2 }));
+        let mut second = unsigned.clone();
+        cosign(&mut second, &cosigners[2]);
+        first.combine(&second).unwrap();
+        assert_eq!(first.id().unwrap(), id, "signatures are not part of the id");
+        assert!(first.validate().is_ok());
+
+        let bincode_config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&first, bincode_config).unwrap();
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, first);
+
+        // A signature from a key outside the policy does not count
+        let mut forged = unsigned.clone();
+        cosign(&mut forged, &cosigners[1]);
+        forged.add_cosignature(Cosignature { key_index: 0, signature: TestWallet::new().sign_data_hash(&id) }).unwrap();
+        assert_eq!(forged.validate(), Err(TxValidationError::InvalidSignature));
+
+        let mut repeated = first.clone();
+        let TxSignature::Multisig(cosignatures) = &mut repeated.signature else { unreachable!() };
+        cosignatures[1] = cosignatures[0].clone();
+        let repeated_index = cosignatures[0].key_index;
+        assert_eq!(repeated.validate(), Err(TxValidationError::InvalidCosignatureIndex(repeated_index)));
+
+        let other = Transaction::new_multisig(policy, TypesNonce(1), unsigned.kind.clone());
+        assert_eq!(first.clone().combine(&other), Err(TxValidationError::TransactionMismatch));
+
+        // Multisig senders cannot be expressed in the older envelope layouts
+        let mut downgraded = first.clone();
+        downgraded.version = SINGLE_KEY_TRANSACTION_VERSION;
+        assert!(bincode::encode_to_vec(&downgraded, bincode_config).is_err());
+        assert_eq!(downgraded.validate_intrinsic_properties(), Err(TxValidationError::UnsupportedVersion(SINGLE_KEY_TRANSACTION_VERSION)));
+    }
+
+    #[test]
+    fn batch_transfer_validation() {
+        let sender_wallet = TestWallet::new();
+        let payout = |amount| Transfer { recipient: TestWallet::new().address, amount };
+        let batch = |transfers: Vec<Transfer>| {
+            Transaction::with_kind(sender_wallet.public_key, TypesNonce(0), TransactionKind::BatchTransfer(transfers), TypesSignature(vec![]))
+        };
+
+        let valid = batch(vec![payout(10), payout(20), payout(30)]);
+        assert_eq!(valid.kind.total_amount(), Some(60));
+        assert!(valid.validate_intrinsic_properties().is_ok());
+
+        assert_eq!(batch(vec![]).validate_intrinsic_properties(), Err(TxValidationError::EmptyBatch));
+        assert_eq!(batch(vec![payout(10), payout(0)]).validate_intrinsic_properties(), Err(TxValidationError::ZeroAmount));
+        assert_eq!(batch(vec![payout(u64::MAX), payout(1)]).validate_intrinsic_properties(), Err(TxValidationError::AmountOverflow));
+        assert_eq!(
+            batch((0..=MAX_BATCH_TRANSFERS).map(|_| payout(1)).collect()).validate_intrinsic_properties(),
+            Err(TxValidationError::BatchTooLarge { got: MAX_BATCH_TRANSFERS + 1, max: MAX_BATCH_TRANSFERS })
+        );
+
+        let mut downgraded = valid.clone();
+        downgraded.version = SINGLE_KEY_TRANSACTION_VERSION;
+        assert_eq!(downgraded.validate_intrinsic_properties(), Err(TxValidationError::UnsupportedVersion(SINGLE_KEY_TRANSACTION_VERSION)));
+    }
+
+    #[test]
+    fn validity_window_is_signed_and_versioned() {
+        let sender_wallet = TestWallet::new();
+        let recipient_address = TestWallet::new().address;
+        let tx = Transaction::new(sender_wallet.public_key, recipient_address, 10, TypesNonce(0), TypesSignature(vec![0; 64]));
+        let windowed = tx.clone().with_validity(ValidityWindow { valid_after_height: None, valid_until_height: Some(BlockHeight(100)) });
+        assert_ne!(windowed.id().unwrap(), tx.id().unwrap(), "the window is part of the signed payload");
+
+        let bincode_config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&windowed, bincode_config).unwrap();
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, windowed);
+
+        // Version 2 transactions have no window in their layout or their id
+        let mut multisig_era = tx.clone();
+        multisig_era.version = MULTISIG_TRANSACTION_VERSION;
+        let encoded = bincode::encode_to_vec(&multisig_era, bincode_config).unwrap();
+        let flat = (MULTISIG_TRANSACTION_VERSION, &tx.sender, TypesNonce(0), &tx.kind, &tx.signature);
+        assert_eq!(encoded, bincode::encode_to_vec(flat, bincode_config).unwrap());
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, multisig_era);
+
+        multisig_era.validity = windowed.validity;
+        assert!(bincode::encode_to_vec(&multisig_era, bincode_config).is_err());
+        assert_eq!(multisig_era.validate_intrinsic_properties(), Err(TxValidationError::UnsupportedVersion(MULTISIG_TRANSACTION_VERSION)));
+
+        let empty = tx.with_validity(ValidityWindow { valid_after_height: Some(BlockHeight(7)), valid_until_height: Some(BlockHeight(7)) });
+        assert_eq!(empty.validate_intrinsic_properties(), Err(TxValidationError::EmptyValidityWindow { valid_after: 7, valid_until: 7 }));
+    }
+
+    #[test]
+    fn memo_is_signed_bounded_and_versioned() {
+        let sender_wallet = TestWallet::new();
+        let recipient_address = TestWallet::new().address;
+        let tx = Transaction::new(sender_wallet.public_key, recipient_address, 10, TypesNonce(0), TypesSignature(vec![0; 64]));
+        let with_memo = tx.clone().with_memo(b"deposit 1234".to_vec());
+        assert_ne!(with_memo.id().unwrap(), tx.id().unwrap(), "the memo is part of the signed payload");
+        assert!(with_memo.validate_intrinsic_properties().is_ok());
+
+        let bincode_config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&with_memo, bincode_config).unwrap();
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, with_memo);
+
+        let mut multisig_era = with_memo.clone();
+        multisig_era.version = MULTISIG_TRANSACTION_VERSION;
+        assert!(bincode::encode_to_vec(&multisig_era, bincode_config).is_err());
+        assert_eq!(multisig_era.validate_intrinsic_properties(), Err(TxValidationError::UnsupportedVersion(MULTISIG_TRANSACTION_VERSION)));
+
+        let too_long = tx.with_memo(vec![b'x'; MAX_MEMO_LEN + 1]);
+        assert_eq!(too_long.validate_intrinsic_properties(), Err(TxValidationError::MemoTooLong { len: MAX_MEMO_LEN + 1, max: MAX_MEMO_LEN }));
+    }
+
+    #[test]
+    fn optional_fields_have_one_encoding() {
+        let sender_wallet = TestWallet::new();
+        let recipient_address = TestWallet::new().address;
+        let tx = Transaction::new(sender_wallet.public_key, recipient_address, 10, TypesNonce(0), TypesSignature(vec![0; 64]));
+        let bincode_config = bincode::config::standard();
+        let with_fields = |fields: Vec<EnvelopeField>| {
+            let flat = (TRANSACTION_VERSION, &tx.sender, TypesNonce(0), &tx.kind, fields, &tx.signature);
+            bincode::encode_to_vec(flat, bincode_config).unwrap()
+        };
+
+        // Defaults are left out, so a plain transfer carries an empty field list
+        assert_eq!(bincode::encode_to_vec(&tx, bincode_config).unwrap(), with_fields(vec![]));
+        let window = ValidityWindow { valid_after_height: Some(BlockHeight(5)), valid_until_height: None };
+        let both = tx.clone().with_validity(window).with_memo(b"ref".to_vec());
+        let encoded = with_fields(vec![EnvelopeField::Validity(window), EnvelopeField::Memo(b"ref".to_vec())]);
+        assert_eq!(bincode::encode_to_vec(&both, bincode_config).unwrap(), encoded);
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, both);
+
+        // Any other encoding of the same fields is refused
+        let reordered = with_fields(vec![EnvelopeField::Memo(b"ref".to_vec()), EnvelopeField::Validity(window)]);
+        assert!(bincode::decode_from_slice::<Transaction, _>(&reordered, bincode_config).is_err());
+        let repeated = with_fields(vec![EnvelopeField::Memo(b"ref".to_vec()), EnvelopeField::Memo(b"ref".to_vec())]);
+        assert!(bincode::decode_from_slice::<Transaction, _>(&repeated, bincode_config).is_err());
+        let default = with_fields(vec![EnvelopeField::Memo(Vec::new())]);
+        assert!(bincode::decode_from_slice::<Transaction, _>(&default, bincode_config).is_err());
+    }
+
+    #[test]
+    fn relayed_transaction_wraps_one_level() {
+        let user = TestWallet::new();
+        let relayer = TestWallet::new();
+        let recipient_address = TestWallet::new().address;
+        let sign = |tx: Transaction, wallet: &TestWallet| Transaction {
+            signature: TxSignature::Single(wallet.sign_data_hash(&tx.id().unwrap())),
+            ..tx
+        };
+        let relay = |inner: Transaction, wallet: &TestWallet, nonce| {
+            let kind = TransactionKind::Relayed(Box::new(inner));
+            sign(Transaction::with_kind(wallet.public_key, TypesNonce(nonce), kind, TypesSignature(vec![])), wallet)
+        };
+
+        let inner = sign(Transaction::new(user.public_key, recipient_address, 10, TypesNonce(3), TypesSignature(vec![])), &user);
+        let relayed = relay(inner.clone(), &relayer, 0);
+        assert!(relayed.validate().is_ok());
+        assert_eq!(relayed.kind.total_amount(), Some(0), "the relayer pays nothing but its nonce");
+
+        let bincode_config = bincode::config::standard();
+        let encoded = bincode::encode_to_vec(&relayed, bincode_config).unwrap();
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+        assert_eq!(decoded, relayed);
+
+        // Both signatures are checked
+        let mut forged_inner = inner.clone();
+        forged_inner.signature = TxSignature::Single(TypesSignature(vec![0; 64]));
+        assert_eq!(relay(forged_inner, &relayer, 0).validate(), Err(TxValidationError::InvalidSignature));
+
+        // A relay of a relay is rejected, and so is decoding one
+        let nested = relay(relayed.clone(), &TestWallet::new(), 0);
+        assert_eq!(nested.validate_intrinsic_properties(), Err(TxValidationError::NestedRelay));
+        let encoded = bincode::encode_to_vec(&nested, bincode_config).unwrap();
+        assert!(bincode::decode_from_slice::<Transaction, _>(&encoded, bincode_config).is_err());
+        assert!(bincode::decode_from_slice::<Transaction, _>(&bincode::encode_to_vec(&relayed, bincode_config).unwrap(), bincode_config).is_ok());
+
+        assert_eq!(relay(inner, &user, 0).validate_intrinsic_properties(), Err(TxValidationError::SelfRelay));
+
+        // The inner transaction is decoded as a `RelayableKind`, which must mirror every other kind
+        let lock = HashTimeLock { hashlock: TypesHash([1; 32]), timelock: BlockHeight(9), recipient: recipient_address, refund_address: user.address };
+        let kinds = [
+            TransactionKind::Transfer(Transfer { recipient: recipient_address, amount: 1 }),
+            TransactionKind::BatchTransfer(vec![Transfer { recipient: recipient_address, amount: 2 }]),
+            TransactionKind::RotateKey { new_key: relayer.public_key },
+            TransactionKind::LockFunds { amount: 3, lock },
+            TransactionKind::ClaimLock { lock_id: recipient_address, preimage: [4; PREIMAGE_LEN] },
+            TransactionKind::RefundLock { lock_id: recipient_address },
+        ];
+        for kind in kinds {
+            let inner = Transaction::with_kind(user.public_key, TypesNonce(0), kind, TypesSignature(vec![0; 64]));
+            let relayed = relay(inner, &relayer, 0);
+            let encoded = bincode::encode_to_vec(&relayed, bincode_config).unwrap();
+            let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, bincode_config).unwrap();
+            assert_eq!(decoded, relayed);
+        }
     }
 }
@@ -4,6 +4,7 @@ use std::fmt;
 use std::convert::TryFrom;
 use anyhow;
 use sha2::Digest;
+use bech32::{primitives::decode::CheckedHrpstring, Bech32m, Hrp};
 
 /// Represents a 32-byte SHA-256 hash.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Encode, Decode)]
@@ -131,9 +132,14 @@ impl fmt::Debug for Address {
     }
 }
 
+/// Human-readable prefix of RustChain addresses, e.g. `rc1qy352e...`.
+pub const ADDRESS_HRP: Hrp = Hrp::parse_unchecked("rc");
+
+/// Writes the bech32m form. Its checksum catches any mistyped or swapped characters, so
+/// a typo cannot turn into a different valid address.
 impl fmt::Display for Address {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "0x{}", hex::encode(self.0)) // Common "0x" prefix for addresses
+        bech32::encode_lower_to_fmt::<Bech32m, _>(f, ADDRESS_HRP, &self.0).map_err(|_| fmt::Error)
     }
 }
 
@@ -149,6 +155,51 @@ impl From<[u8; 32]> for Address {
     }
 }
 
+/// Parses the `Display` form, verifying its checksum and prefix. Legacy hex is rejected;
+/// see `Address::parse`.
+impl std::str::FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let checked = CheckedHrpstring::new::<Bech32m>(s).map_err(|e| format!("Invalid address `{}`: {}", s, e))?;
+        if checked.hrp() != ADDRESS_HRP {
+            return Err(format!("Invalid address `{}`: expected prefix `{}`, got `{}`", s, ADDRESS_HRP, checked.hrp()));
+        }
+        let bytes: Vec<u8> = checked.byte_iter().collect();
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| format!("Invalid address `{}`: expected 32 bytes, got {}", s, bytes.len()))?;
+        Ok(Address(bytes))
+    }
+}
+
+impl Address {
+    /// Parses the 64 hex characters, with an optional `0x` prefix, that addresses were
+    /// written as before they had a checksum.
+    pub fn from_hex(s: &str) -> Result<Self, String> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        if s.len() != 64 {
+            return Err(format!("Address hex string must be 64 characters long, got {}", s.len()));
+        }
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(s, &mut bytes).map_err(|e| format!("Invalid hex string for address: {}", e))?;
+        Ok(Address(bytes))
+    }
+
+    /// Parses a bech32m address, or also legacy hex if `legacy_hex` is set. Hex has no
+    /// checksum, so it is only accepted where the user explicitly asked for it.
+    pub fn parse(s: &str, legacy_hex: bool) -> Result<Self, String> {
+        match s.parse() {
+            Ok(address) => Ok(address),
+            Err(_) if legacy_hex => Address::from_hex(s),
+            Err(e) if Address::from_hex(s).is_ok() => {
+                Err(format!("{}. Hex addresses have no checksum and are only accepted with --legacy-hex-addresses", e))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
 /// Derives a blockchain address from a public key by hashing it.
 pub fn address_from_public_key(pk: &PublicKey) -> Address {
     let mut hasher = sha2::Sha256::new();
@@ -296,7 +347,7 @@ mod tests {
         
         println!("Derived Address (Placeholder): {:?}", address_val);
         println!("Derived Address (Display): {}", address_val);
-        assert_eq!(format!("{}", address_val), format!("0x{}", hex::encode(address_bytes)));
+        assert!(format!("{}", address_val).starts_with("rc1"));
 
         // Example of creating an Address from a known hash (e.g. if address is a hash)
         let data_for_addr = b"address_data";
@@ -308,6 +359,31 @@ mod tests {
 
     }
 
+    #[test]
+    fn address_bech32m_encoding() {
+        let address = Address([7u8; 32]);
+        let encoded = address.to_string();
+        assert!(encoded.starts_with("rc1"));
+        assert_eq!(encoded.parse::<Address>().unwrap(), address);
+        assert_eq!(encoded.to_uppercase().parse::<Address>().unwrap(), address);
+
+        // Any single mistyped character breaks the checksum
+        let mut typo = encoded.clone().into_bytes();
+        typo[10] = if typo[10] == b'q' { b'p' } else { b'q' };
+        assert!(String::from_utf8(typo).unwrap().parse::<Address>().is_err());
+
+        let other_chain = bech32::encode::<Bech32m>(Hrp::parse_unchecked("xy"), &address.0).unwrap();
+        assert!(other_chain.parse::<Address>().is_err());
+        let bech32 = bech32::encode::<bech32::Bech32>(ADDRESS_HRP, &address.0).unwrap();
+        assert!(bech32.parse::<Address>().is_err(), "only the bech32m checksum is accepted");
+
+        let hex = format!("0x{}", hex::encode(address.0));
+        assert!(hex.parse::<Address>().is_err());
+        assert!(Address::parse(&hex, false).unwrap_err().contains("--legacy-hex-addresses"));
+        assert_eq!(Address::parse(&hex, true).unwrap(), address);
+        assert_eq!(Address::parse(&encoded, false).unwrap(), address);
+    }
+
     #[test]
     fn numeric_types_creation_and_conversion() {
         let height_val: u64 = 100;
@@ -0,0 +1,108 @@
+use crate::types::BlockHeight;
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum VestingError {
+    #[error("A vesting schedule must lock a non-zero amount")]
+    ZeroAmount,
+    #[error("Linear vesting must end after it starts: start height {start}, end height {end}")]
+    EmptyPeriod { start: u64, end: u64 },
+    #[error("Vesting schedule locks {locked}, more than the balance of {balance}")]
+    ExceedsBalance { locked: u64, balance: u64 },
+}
+
+/// Part of an account's balance that cannot be spent until a block height is reached. The
+/// schedule only limits spending: the locked amount is still counted in the balance and
+/// in the total supply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VestingSchedule {
+    /// All of `amount` stays locked until a block at `unlock_height`.
+    Cliff { amount: u64, unlock_height: BlockHeight },
+    /// `amount` unlocks in equal parts per block, starting after `start_height` and fully
+    /// unlocked at `end_height`.
+    Linear { amount: u64, start_height: BlockHeight, end_height: BlockHeight },
+}
+
+impl VestingSchedule {
+    /// Checks the schedule for an account holding `balance`, as done for genesis allocations.
+    pub fn check(&self, balance: u64) -> Result<(), VestingError> {
+        if self.amount() == 0 {
+            return Err(VestingError::ZeroAmount);
+        }
+        if self.amount() > balance {
+            return Err(VestingError::ExceedsBalance { locked: self.amount(), balance });
+        }
+        if let VestingSchedule::Linear { start_height, end_height, .. } = *self {
+            if end_height <= start_height {
+                return Err(VestingError::EmptyPeriod { start: start_height.0, end: end_height.0 });
+            }
+        }
+        Ok(())
+    }
+
+    /// The total amount the schedule locks before anything unlocks.
+    pub fn amount(&self) -> u64 {
+        match *self {
+            VestingSchedule::Cliff { amount, .. } | VestingSchedule::Linear { amount, .. } => amount,
+        }
+    }
+
+    /// The amount still locked for a transaction in a block at `height`.
+    pub fn locked_at(&self, height: BlockHeight) -> u64 {
+        match *self {
+            VestingSchedule::Cliff { amount, unlock_height } => {
+                if height < unlock_height {
+                    amount
+                } else {
+                    0
+                }
+            }
+            VestingSchedule::Linear { amount, start_height, end_height } => {
+                if height <= start_height {
+                    amount
+                } else if height >= end_height {
+                    0
+                } else {
+                    // Rounds the locked part up, so nothing unlocks early
+                    let remaining = (end_height.0 - height.0) as u128;
+                    let period = (end_height.0 - start_height.0) as u128;
+                    (amount as u128 * remaining).div_ceil(period) as u64
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cliff_unlocks_at_once() {
+        let cliff = VestingSchedule::Cliff { amount: 500, unlock_height: BlockHeight(10) };
+        assert_eq!(cliff.locked_at(BlockHeight(0)), 500);
+        assert_eq!(cliff.locked_at(BlockHeight(9)), 500);
+        assert_eq!(cliff.locked_at(BlockHeight(10)), 0);
+    }
+
+    #[test]
+    fn test_linear_unlocks_per_block() {
+        let linear = VestingSchedule::Linear { amount: 1000, start_height: BlockHeight(100), end_height: BlockHeight(400) };
+        assert_eq!(linear.locked_at(BlockHeight(50)), 1000);
+        assert_eq!(linear.locked_at(BlockHeight(100)), 1000);
+        assert_eq!(linear.locked_at(BlockHeight(101)), 997);
+        assert_eq!(linear.locked_at(BlockHeight(250)), 500);
+        assert_eq!(linear.locked_at(BlockHeight(399)), 4);
+        assert_eq!(linear.locked_at(BlockHeight(400)), 0);
+
+        let large = VestingSchedule::Linear { amount: u64::MAX, start_height: BlockHeight(0), end_height: BlockHeight(u64::MAX) };
+        assert_eq!(large.locked_at(BlockHeight(1)), u64::MAX - 1);
+
+        let empty = VestingSchedule::Linear { amount: 1, start_height: BlockHeight(5), end_height: BlockHeight(5) };
+        assert_eq!(empty.check(10), Err(VestingError::EmptyPeriod { start: 5, end: 5 }));
+        assert_eq!(linear.check(999), Err(VestingError::ExceedsBalance { locked: 1000, balance: 999 }));
+    }
+}
@@ -1,5 +1,8 @@
 use crate::types::{Address, PublicKey, Signature, Nonce};
-use crate::transaction::Transaction;
+use crate::hd_wallet::{self, DerivationPath};
+use crate::keystore::{Keystore, ScryptParams};
+use crate::multisig::Cosignature;
+use crate::transaction::{Transaction, TransactionKind, Transfer, TxSender, TxSignature};
 use ed25519_dalek::{Signer, SigningKey, VerifyingKey, SECRET_KEY_LENGTH};
 use rand::rngs::OsRng; 
 use sha2::{Sha256, Digest}; 
@@ -10,9 +13,8 @@ use anyhow::Result;
 use anyhow::Context; 
 use serde;
 
-/// Represents a wallet, holding a keypair.
-/// For simplicity, we'll store the secret key directly.
-/// In a real wallet, this would be encrypted or handled by a secure enclave.
+/// Represents a wallet, holding a keypair. On disk the secret key is kept in a
+/// passphrase-encrypted keystore (see `save_encrypted`).
 pub struct Wallet {
     pub signing_key: SigningKey,
     pub public_key: PublicKey,
@@ -41,7 +43,7 @@ impl Wallet {
 
     /// Creates a Wallet from a SigningKey.
     /// Useful when loading a key from an external source.
-    fn from_signing_key(signing_key: SigningKey) -> Self {
+    pub fn from_signing_key(signing_key: SigningKey) -> Self {
         let verifying_key: VerifyingKey = signing_key.verifying_key();
         let public_key = PublicKey(verifying_key);
         let address = Address(*verifying_key.as_bytes());
@@ -76,8 +78,8 @@ impl Wallet {
         Ok(Signature(dalek_signature.to_bytes().to_vec()))
     }
 
-    /// Saves the wallet's secret key to the specified file.
-    /// For development/testing purposes only.
+    /// Saves the wallet's secret key to the specified file, unencrypted.
+    /// Only meant for validator keys read by an unattended node; use `save_encrypted` for wallets.
     pub fn save_to_file(&self, path_str: &str) -> anyhow::Result<()> {
         let path = Path::new(path_str);
         if let Some(parent) = path.parent() {
@@ -102,25 +104,93 @@ impl Wallet {
         Ok(Wallet::from_signing_key(signing_key))
     }
 
-    /// Creates and signs a transaction.
+    /// Encrypts the secret key under `passphrase` and writes it to `path` as a keystore.
+    pub fn save_encrypted(&self, path: &Path, passphrase: &str, params: ScryptParams) -> anyhow::Result<()> {
+        Keystore::encrypt(&self.signing_key, passphrase, params)?.save(path)?;
+        Ok(())
+    }
+
+    /// Loads a wallet from a keystore written by `save_encrypted`.
+    pub fn load_encrypted(path: &Path, passphrase: &str) -> anyhow::Result<Self> {
+        let keystore: Keystore = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Wallet::from_signing_key(keystore.decrypt(passphrase)?))
+    }
+
+    /// Derives the wallet of account `index` from a BIP39 mnemonic, at path
+    /// `m/44'/1'/index'`. The mnemonic is used without a BIP39 passphrase.
+    pub fn from_mnemonic(mnemonic: &bip39::Mnemonic, index: u32) -> anyhow::Result<Self> {
+        let path = DerivationPath::for_account(index)?;
+        Ok(Wallet::from_signing_key(hd_wallet::derive_signing_key(&mnemonic.to_seed(""), &path)))
+    }
+
+    /// Creates and signs a transfer transaction.
     pub fn create_signed_transaction(&self, recipient: Address, amount: u64, nonce: Nonce) -> Result<Transaction, anyhow::Error> {
-        let tx_payload = Transaction {
-            sender: *self.public_key(),
-            recipient,
-            amount,
-            nonce,
-            signature: Signature(vec![]), // Dummy signature
-        };
+        self.create_signed_transaction_with_kind(TransactionKind::Transfer(Transfer { recipient, amount }), nonce)
+    }
+
+    /// Creates and signs a transaction of any kind.
+    pub fn create_signed_transaction_with_kind(&self, kind: TransactionKind, nonce: Nonce) -> Result<Transaction, anyhow::Error> {
+        let tx_payload = Transaction::with_kind(*self.public_key(), nonce, kind, Signature(vec![])); // Dummy signature
+        self.sign_transaction(tx_payload)
+    }
 
+    /// Signs a single-key transaction sent from this wallet, or from an account that rotated
+    /// its authorized key to this wallet's key, replacing any existing signature.
+    pub fn sign_transaction(&self, tx_payload: Transaction) -> Result<Transaction, anyhow::Error> {
+        if tx_payload.sender.signing_key() != Some(self.public_key()) {
+            return Err(anyhow::anyhow!("Transaction is not sent from key {}", self.public_key()));
+        }
         let tx_hash = tx_payload.id()?;
         let dalek_signature = self.signing_key.sign(tx_hash.as_ref());
         
         let signed_tx = Transaction {
-            signature: Signature(dalek_signature.to_bytes().to_vec()),
+            signature: TxSignature::Single(Signature(dalek_signature.to_bytes().to_vec())),
             ..tx_payload
         };
         Ok(signed_tx)
     }
+
+    /// Adds this wallet's cosignature to a multisig transaction it is one of the keys of.
+    /// Cosignatures from other key holders already on the transaction are kept.
+    pub fn cosign(&self, transaction: &mut Transaction) -> Result<(), anyhow::Error> {
+        let TxSender::Multisig(policy) = &transaction.sender else {
+            return Err(anyhow::anyhow!("Transaction is not sent from a multisig account"));
+        };
+        let key_index = policy
+            .key_index(self.public_key())
+            .ok_or_else(|| anyhow::anyhow!("Key {} is not part of {}", self.public_key(), policy))?;
+        let signature = self.sign(transaction.id()?.as_ref())?;
+        transaction.add_cosignature(Cosignature { key_index, signature })?;
+        Ok(())
+    }
+}
+
+/// Parses payouts for a batch transfer from CSV text: one `recipient,amount` pair per line.
+/// Blank lines, `#` comments and a leading `recipient,amount` header are skipped. Recipients
+/// are bech32m addresses, or also hex if `legacy_hex_addresses` is set.
+pub fn parse_payouts_csv(contents: &str, legacy_hex_addresses: bool) -> Result<Vec<Transfer>> {
+    let mut transfers = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_number = index + 1;
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if transfers.is_empty() && fields.first().is_some_and(|field| field.eq_ignore_ascii_case("recipient")) {
+            continue;
+        }
+        let [recipient, amount] = fields[..] else {
+            return Err(anyhow::anyhow!("Line {}: expected `recipient,amount`, got {} fields", line_number, fields.len()));
+        };
+        let recipient = Address::parse(recipient, legacy_hex_addresses)
+            .map_err(|e| anyhow::anyhow!("Line {}: {}", line_number, e))?;
+        let amount: u64 = amount
+            .parse()
+            .with_context(|| format!("Line {}: invalid amount `{}`", line_number, amount))?;
+        transfers.push(Transfer { recipient, amount });
+    }
+    Ok(transfers)
 }
 
 /// Derive address from public key - standalone function for use in other modules
@@ -141,6 +211,7 @@ pub fn generate_validator_keypair() -> (SigningKey, PublicKey) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::multisig::MultisigPolicy;
     use crate::types::{Address, Nonce, Hash};
     use std::fs;
     use tempfile::NamedTempFile;
@@ -250,6 +321,18 @@ mod tests {
         std::fs::remove_file(&path).unwrap(); // Clean up
     }
 
+    #[test]
+    fn test_encrypted_wallet_round_trip() {
+        let wallet = Wallet::new();
+        let file = NamedTempFile::new().unwrap();
+        let params = ScryptParams { log_n: 4, r: 8, p: 1 };
+        wallet.save_encrypted(file.path(), "passphrase", params).unwrap();
+
+        let loaded_wallet = Wallet::load_encrypted(file.path(), "passphrase").unwrap();
+        assert_eq!(wallet.public_key(), loaded_wallet.public_key());
+        assert!(Wallet::load_encrypted(file.path(), "wrong").is_err());
+    }
+
     #[test]
     fn test_create_and_sign_transaction() {
         let wallet = Wallet::new();
@@ -261,12 +344,47 @@ mod tests {
         assert!(tx_result.is_ok());
         let tx = tx_result.unwrap();
 
-        assert_eq!(tx.sender, *wallet.public_key());
-        assert_eq!(tx.recipient, recipient_address);
-        assert_eq!(tx.amount, amount);
+        assert_eq!(tx.sender, TxSender::Single(*wallet.public_key()));
+        assert_eq!(tx.kind, TransactionKind::Transfer(Transfer { recipient: recipient_address, amount }));
         assert_eq!(tx.nonce, nonce);
 
         // Verify the signature
-        assert!(tx.verify_signature(&wallet.public_key()).is_ok());
+        assert!(tx.verify_signature().is_ok());
+    }
+
+    #[test]
+    fn test_cosign_multisig_transaction() {
+        let wallets = [Wallet::new(), Wallet::new(), Wallet::new()];
+        let policy = MultisigPolicy::new(2, wallets.iter().map(|w| *w.public_key()).collect()).unwrap();
+        let kind = TransactionKind::Transfer(Transfer { recipient: Address([1u8; 32]), amount: 5 });
+        let mut tx = Transaction::new_multisig(policy, Nonce(0), kind);
+
+        wallets[0].cosign(&mut tx).unwrap();
+        wallets[0].cosign(&mut tx).unwrap();
+        assert!(tx.verify_signature().is_err(), "signing twice with one key must not reach the threshold");
+        wallets[1].cosign(&mut tx).unwrap();
+        assert!(tx.validate().is_ok());
+
+        assert!(Wallet::new().cosign(&mut tx).is_err());
+        let mut single = wallets[0].create_signed_transaction(Address([1u8; 32]), 5, Nonce(0)).unwrap();
+        assert!(wallets[0].cosign(&mut single).is_err());
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_parse_payouts_csv() {
+        let alice = Address([0xaa; 32]);
+        let bob = Address([0xbb; 32]);
+        let csv = format!("recipient,amount\n# monthly payroll\n{},100\n\n {} , 250 \n", alice, bob);
+        let transfers = parse_payouts_csv(&csv, false).unwrap();
+        assert_eq!(transfers, vec![Transfer { recipient: alice, amount: 100 }, Transfer { recipient: bob, amount: 250 }]);
+
+        let err = parse_payouts_csv(&format!("{},100\n{},ten\n", alice, bob), false).unwrap_err();
+        assert!(err.to_string().starts_with("Line 2"), "{}", err);
+        assert!(parse_payouts_csv(&format!("{},1,2\n", alice), false).is_err());
+        assert!(parse_payouts_csv("0x1234,5\n", true).is_err());
+
+        let hex_csv = format!("0x{},100\n", hex::encode(alice.0));
+        assert!(parse_payouts_csv(&hex_csv, false).is_err());
+        assert_eq!(parse_payouts_csv(&hex_csv, true).unwrap(), vec![Transfer { recipient: alice, amount: 100 }]);
+    }
+}
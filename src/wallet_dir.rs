@@ -0,0 +1,220 @@
+use crate::keystore::{Keystore, KeystoreError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Index of the accounts in a wallet directory.
+const INDEX_FILE: &str = "wallets.json";
+/// Subdirectory holding one keystore per account.
+const KEYS_DIR: &str = "keys";
+/// Subdirectory that removed accounts' keystores are moved to, so a mistaken `remove` can
+/// be undone by hand.
+const REMOVED_DIR: &str = "removed";
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum WalletDirError {
+    #[error("Invalid account name `{0}`: use 1 to 64 letters, digits, `-` or `_`")]
+    InvalidName(String),
+    #[error("Account `{0}` already exists")]
+    AccountExists(String),
+    #[error("No account named `{0}`")]
+    AccountNotFound(String),
+    #[error("Wallet index is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Keystore error: {0}")]
+    Keystore(#[from] KeystoreError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// What the index records about an account. The key itself lives in the account's
+/// keystore file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    #[serde(default)]
+    default: Option<String>,
+    #[serde(default)]
+    accounts: BTreeMap<String, AccountEntry>,
+}
+
+/// A directory of named accounts, each an encrypted keystore, one of which can be the
+/// default. Every change is written back to the index immediately.
+#[derive(Debug)]
+pub struct WalletDir {
+    root: PathBuf,
+    index: Index,
+}
+
+impl WalletDir {
+    /// `~/.rustchain/wallets`, or `.rustchain/wallets` when there is no home directory.
+    pub fn default_path() -> PathBuf {
+        let home = std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+        home.join(".rustchain").join("wallets")
+    }
+
+    /// Opens the wallet directory at `root`. A directory that does not exist yet has no
+    /// accounts and is created on the first `add`.
+    pub fn open(root: &Path) -> Result<Self, WalletDirError> {
+        let index_path = root.join(INDEX_FILE);
+        let index = if index_path.exists() { serde_json::from_slice(&fs::read(&index_path)?)? } else { Index::default() };
+        Ok(WalletDir { root: root.to_path_buf(), index })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Accounts in name order.
+    pub fn accounts(&self) -> impl Iterator<Item = (&str, &AccountEntry)> {
+        self.index.accounts.iter().map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    pub fn default_account(&self) -> Option<&str> {
+        self.index.default.as_deref()
+    }
+
+    /// Path of the keystore of account `name`.
+    pub fn keyfile(&self, name: &str) -> Result<PathBuf, WalletDirError> {
+        if !self.index.accounts.contains_key(name) {
+            return Err(WalletDirError::AccountNotFound(name.to_string()));
+        }
+        Ok(self.keyfile_path(name))
+    }
+
+    /// Reads the keystore of account `name`, e.g. to show its public key without a passphrase.
+    pub fn keystore(&self, name: &str) -> Result<Keystore, WalletDirError> {
+        Ok(serde_json::from_slice(&fs::read(self.keyfile(name)?)?)?)
+    }
+
+    /// Stores `keystore` as a new account. The first account becomes the default.
+    pub fn add(&mut self, name: &str, label: Option<String>, keystore: &Keystore) -> Result<PathBuf, WalletDirError> {
+        validate_name(name)?;
+        if self.index.accounts.contains_key(name) {
+            return Err(WalletDirError::AccountExists(name.to_string()));
+        }
+        let path = self.keyfile_path(name);
+        if path.exists() {
+            // Left behind by hand; never overwrite a key
+            return Err(WalletDirError::AccountExists(name.to_string()));
+        }
+        keystore.save(&path)?;
+        self.index.accounts.insert(name.to_string(), AccountEntry { label });
+        self.index.default.get_or_insert_with(|| name.to_string());
+        self.save_index()?;
+        Ok(path)
+    }
+
+    pub fn rename(&mut self, name: &str, new_name: &str) -> Result<(), WalletDirError> {
+        validate_name(new_name)?;
+        if self.index.accounts.contains_key(new_name) || self.keyfile_path(new_name).exists() {
+            return Err(WalletDirError::AccountExists(new_name.to_string()));
+        }
+        let entry = self.index.accounts.remove(name).ok_or_else(|| WalletDirError::AccountNotFound(name.to_string()))?;
+        fs::rename(self.keyfile_path(name), self.keyfile_path(new_name))?;
+        self.index.accounts.insert(new_name.to_string(), entry);
+        if self.index.default.as_deref() == Some(name) {
+            self.index.default = Some(new_name.to_string());
+        }
+        self.save_index()
+    }
+
+    /// Removes account `name` and returns where its keystore was moved to. Removing the
+    /// default account leaves the directory without a default.
+    pub fn remove(&mut self, name: &str) -> Result<PathBuf, WalletDirError> {
+        let keyfile = self.keyfile(name)?;
+        let removed_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let removed_dir = self.root.join(REMOVED_DIR);
+        fs::create_dir_all(&removed_dir)?;
+        let removed_path = removed_dir.join(format!("{}.{}.json", name, removed_at));
+        fs::rename(&keyfile, &removed_path)?;
+        self.index.accounts.remove(name);
+        if self.index.default.as_deref() == Some(name) {
+            self.index.default = None;
+        }
+        self.save_index()?;
+        Ok(removed_path)
+    }
+
+    pub fn set_default(&mut self, name: &str) -> Result<(), WalletDirError> {
+        self.keyfile(name)?;
+        self.index.default = Some(name.to_string());
+        self.save_index()
+    }
+
+    fn keyfile_path(&self, name: &str) -> PathBuf {
+        self.root.join(KEYS_DIR).join(format!("{}.json", name))
+    }
+
+    /// Replaces the index atomically, so an interrupted write never loses the account list.
+    fn save_index(&self) -> Result<(), WalletDirError> {
+        fs::create_dir_all(&self.root)?;
+        let tmp_path = self.root.join(format!("{}.tmp", INDEX_FILE));
+        fs::write(&tmp_path, serde_json::to_string_pretty(&self.index)?)?;
+        fs::rename(&tmp_path, self.root.join(INDEX_FILE))?;
+        Ok(())
+    }
+}
+
+/// Names become file names, so they are restricted to characters that are safe in paths.
+fn validate_name(name: &str) -> Result<(), WalletDirError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(WalletDirError::InvalidName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keystore::ScryptParams;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use tempfile::tempdir;
+
+    fn keystore() -> Keystore {
+        let params = ScryptParams { log_n: 4, r: 8, p: 1 };
+        Keystore::encrypt(&SigningKey::generate(&mut OsRng), "passphrase", params).unwrap()
+    }
+
+    #[test]
+    fn test_wallet_dir_accounts() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("wallets");
+        let mut wallets = WalletDir::open(&root).unwrap();
+        assert_eq!(wallets.accounts().count(), 0);
+
+        let ops = keystore();
+        wallets.add("ops", Some("Hot wallet".to_string()), &ops).unwrap();
+        wallets.add("treasury", None, &keystore()).unwrap();
+        assert!(matches!(wallets.add("ops", None, &keystore()), Err(WalletDirError::AccountExists(_))));
+        assert!(matches!(wallets.add("../ops", None, &keystore()), Err(WalletDirError::InvalidName(_))));
+        assert_eq!(wallets.default_account(), Some("ops"), "the first account becomes the default");
+
+        wallets.rename("ops", "payroll").unwrap();
+        let reopened = WalletDir::open(&root).unwrap();
+        assert_eq!(reopened.accounts().map(|(name, _)| name).collect::<Vec<_>>(), vec!["payroll", "treasury"]);
+        assert_eq!(reopened.default_account(), Some("payroll"));
+        assert_eq!(reopened.keystore("payroll").unwrap(), ops);
+        assert!(matches!(reopened.keyfile("ops"), Err(WalletDirError::AccountNotFound(_))));
+
+        wallets.set_default("treasury").unwrap();
+        let removed = wallets.remove("treasury").unwrap();
+        assert!(removed.exists(), "removed keys are kept aside");
+        assert_eq!(wallets.default_account(), None);
+        assert!(matches!(wallets.set_default("treasury"), Err(WalletDirError::AccountNotFound(_))));
+    }
+}
@@ -0,0 +1,193 @@
+use rustchain::chain_store::{ChainStore, MemoryStore};
+use rustchain::genesis::GenesisData;
+use rustchain::node::{Node, NodeConfiguration, NodeConsensusConfig, NodeNetworkConfig, NodeValidatorConfig};
+use rustchain::rpc::RpcClient;
+use rustchain::types::{address_from_public_key, Address, Nonce};
+use rustchain::wallet::Wallet;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn node_config(genesis_file: &Path, port: u16, bootstrap_peers: Vec<String>) -> NodeConfiguration {
+    let defaults = NodeConfiguration::default();
+    NodeConfiguration {
+        network: NodeNetworkConfig { listen_port: port, bootstrap_peers, ..defaults.network },
+        consensus: NodeConsensusConfig { block_interval: 1, ..defaults.consensus },
+        genesis_file: Some(genesis_file.to_string_lossy().to_string()),
+        ..defaults
+    }
+}
+
+/// Polls until `done` holds, failing the test after 30 seconds.
+async fn wait_until<F, Fut>(what: &str, mut done: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+    while !done().await {
+        assert!(tokio::time::Instant::now() < deadline, "timed out waiting until {}", what);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+async fn tip(node: &Node<MemoryStore>) -> Option<(rustchain::types::Hash, u64)> {
+    node.storage().lock().await.get_chain_tip().unwrap()
+}
+
+async fn balance(node: &Node<MemoryStore>, address: &Address) -> u64 {
+    node.storage().lock().await.get_account(address).unwrap().map_or(0, |account| account.balance)
+}
+
+/// A chain with a single validator and a funded account, written to `dir`.
+struct TestChain {
+    genesis_file: PathBuf,
+    key_path: PathBuf,
+    funder: Wallet,
+}
+
+impl TestChain {
+    fn new(dir: &Path) -> Self {
+        let validator = Wallet::new();
+        let key_path = dir.join("validator.key");
+        validator.save_to_file(key_path.to_str().unwrap()).unwrap();
+        let funder = Wallet::new();
+        let genesis = GenesisData {
+            validators: vec![validator.public_key().to_string()],
+            initial_balances: BTreeMap::from([(address_from_public_key(funder.public_key()).to_string(), 1_000)]),
+            vesting: BTreeMap::new(),
+            timestamp: 0,
+            message: "multi-node test".to_string(),
+        };
+        let genesis_file = dir.join("genesis.json");
+        std::fs::write(&genesis_file, serde_json::to_vec(&genesis).unwrap()).unwrap();
+        TestChain { genesis_file, key_path, funder }
+    }
+
+    fn funder_address(&self) -> Address {
+        address_from_public_key(self.funder.public_key())
+    }
+
+    /// Configuration for a node producing blocks with the validator key.
+    fn producer_config(&self, port: u16) -> NodeConfiguration {
+        let mut config = node_config(&self.genesis_file, port, Vec::new());
+        config.validator = Some(NodeValidatorConfig {
+            private_key_path: self.key_path.to_string_lossy().to_string(),
+            enabled: true,
+            remote_signer: None,
+            remote_signer_secret_path: None,
+        });
+        config
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nodes_on_memory_stores_converge() {
+    let dir = tempfile::tempdir().unwrap();
+    let chain = TestChain::new(dir.path());
+    let funder = &chain.funder;
+    let funder_address = chain.funder_address();
+
+    // The producer holds the only validator key; the follower dials it and serves RPC
+    let producer_port = free_port();
+    let producer = Node::start(chain.producer_config(producer_port), MemoryStore::new()).await.unwrap();
+
+    let rpc_addr = format!("127.0.0.1:{}", free_port());
+    let mut follower_config = node_config(&chain.genesis_file, free_port(), vec![format!("/ip4/127.0.0.1/tcp/{}", producer_port)]);
+    follower_config.rpc.enabled = true;
+    follower_config.rpc.listen_addr = rpc_addr.clone();
+    let follower = Node::start(follower_config, MemoryStore::new()).await.unwrap();
+
+    wait_until("the follower receives a block", || async { tip(&follower).await.is_some_and(|(_, height)| height > 0) }).await;
+
+    // Submitted to the follower, gossiped to the producer and included in its next block
+    let recipient = Address([7; 32]);
+    let transaction = funder.create_signed_transaction(recipient, 250, Nonce(0)).unwrap();
+    tokio::task::spawn_blocking(move || RpcClient::connect(&rpc_addr).unwrap().submit_transaction(&transaction).unwrap())
+        .await
+        .unwrap();
+
+    wait_until("both nodes apply the transfer", || async {
+        balance(&producer, &recipient).await == 250 && balance(&follower, &recipient).await == 250
+    })
+    .await;
+    assert_eq!(balance(&follower, &funder_address).await, 750);
+    wait_until("both nodes share a tip", || async { tip(&producer).await == tip(&follower).await }).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restarted_node_keeps_its_balances_and_supply() {
+    let dir = tempfile::tempdir().unwrap();
+    let chain = TestChain::new(dir.path());
+    let recipient = Address([7; 32]);
+
+    let node = Node::start(chain.producer_config(free_port()), MemoryStore::new()).await.unwrap();
+    let transaction = chain.funder.create_signed_transaction(recipient, 250, Nonce(0)).unwrap();
+    node.mempool().lock().await.add_transaction(transaction).unwrap();
+    wait_until("the transfer is committed", || async { balance(&node, &recipient).await == 250 }).await;
+
+    // Restart on the same store: the state machine picks up where the chain left off
+    let store = node.shutdown().await.unwrap();
+    let node = Node::start(chain.producer_config(free_port()), store).await.unwrap();
+    {
+        let state_machine = node.state_machine().lock().await;
+        assert_eq!(state_machine.get_account(&recipient).map(|account| account.balance), Some(250));
+        assert_eq!(state_machine.get_account(&chain.funder_address()).map(|account| account.balance), Some(750));
+        assert_eq!(state_machine.total_supply(), 1_000);
+        state_machine.check_supply_invariant().unwrap();
+    }
+
+    // Transactions built on the restored state are still accepted
+    let transaction = chain.funder.create_signed_transaction(recipient, 100, Nonce(1)).unwrap();
+    node.mempool().lock().await.add_transaction(transaction).unwrap();
+    wait_until("a transfer after the restart is committed", || async { balance(&node, &recipient).await == 350 }).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fast_synced_node_catches_up_from_its_snapshot() {
+    let dir = tempfile::tempdir().unwrap();
+    let chain = TestChain::new(dir.path());
+    let recipient = Address([7; 32]);
+
+    let producer_port = free_port();
+    let producer = Node::start(chain.producer_config(producer_port), MemoryStore::new()).await.unwrap();
+    let transaction = chain.funder.create_signed_transaction(recipient, 250, Nonce(0)).unwrap();
+    producer.mempool().lock().await.add_transaction(transaction).unwrap();
+    wait_until("the transfer is committed", || async { balance(&producer, &recipient).await == 250 }).await;
+
+    let importer_config = || {
+        let mut config = node_config(&chain.genesis_file, free_port(), vec![format!("/ip4/127.0.0.1/tcp/{}", producer_port)]);
+        config.sync.fast_sync = true;
+        config
+    };
+    let importer = Node::start(importer_config(), MemoryStore::new()).await.unwrap();
+    wait_until("the snapshot is imported", || async {
+        importer.storage().lock().await.get_pruned_height().unwrap() > 0
+    })
+    .await;
+
+    // While the importer is down the producer moves several blocks past the snapshot, which the
+    // importer can only get back through block sync
+    let store = importer.shutdown().await.unwrap();
+    let snapshot_height = store.get_pruned_height().unwrap();
+    wait_until("the producer moves past the snapshot", || async {
+        tip(&producer).await.is_some_and(|(_, height)| height >= snapshot_height + 3)
+    })
+    .await;
+    let importer = Node::start(importer_config(), store).await.unwrap();
+    wait_until("the importer reaches the producer's tip", || async { tip(&importer).await == tip(&producer).await }).await;
+
+    // Every block above the snapshot was applied, on top of the imported state
+    let (_, tip_height) = tip(&importer).await.unwrap();
+    let storage = importer.storage().lock().await;
+    for height in snapshot_height + 1..=tip_height {
+        assert!(storage.get_block_by_height(height).unwrap().is_some(), "missing block {}", height);
+    }
+    drop(storage);
+    assert_eq!(balance(&importer, &recipient).await, 250);
+    assert_eq!(importer.state_machine().lock().await.get_account(&recipient).map(|account| account.balance), Some(250));
+}